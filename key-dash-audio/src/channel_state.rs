@@ -0,0 +1,467 @@
+//! Controller state tracking: Enough of every channel's state to restore it after a seek without
+//! replaying the song from the start, along with the system exclusive messages that still apply.
+//!
+
+use midi_msg::{Channel, ChannelVoiceMsg, ControlChange, MidiMsg};
+
+use super::{midi_sequencer::MidiSink, sysex::SysEx};
+
+/// Split raw channel message bytes into `[status, data1, data2]` triplets.
+///
+/// `midi_msg` may merge related messages into one, such as MSB/LSB controller pairs or high
+/// resolution velocities. These are split back into plain 2–3 byte messages, following running
+/// status where the status byte was omitted.
+pub(crate) fn split_messages(raw: &[u8]) -> impl Iterator<Item = [u8; 3]> + '_ {
+    let mut status = 0;
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        while i < raw.len() {
+            if raw[i] & 0x80 != 0 {
+                status = raw[i];
+                i += 1;
+            }
+            let data_len = match status & 0xf0 {
+                0x80 | 0x90 | 0xa0 | 0xb0 | 0xe0 => 2,
+                0xc0 | 0xd0 => 1,
+                // Not a channel message, skip the byte.
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let data = raw.get(i..i + data_len)?;
+            i += data_len;
+            return Some([status, data[0], data.get(1).copied().unwrap_or(0)]);
+        }
+        None
+    })
+}
+
+/// Build the [`MidiMsg`] of a `[status, data1, data2]` channel message.
+pub(crate) fn channel_message([status, data1, data2]: [u8; 3]) -> MidiMsg {
+    let msg = match status & 0xf0 {
+        0x80 => ChannelVoiceMsg::NoteOff {
+            note: data1,
+            velocity: data2,
+        },
+        0x90 => ChannelVoiceMsg::NoteOn {
+            note: data1,
+            velocity: data2,
+        },
+        0xa0 => ChannelVoiceMsg::PolyPressure {
+            note: data1,
+            pressure: data2,
+        },
+        0xb0 => ChannelVoiceMsg::ControlChange {
+            control: ControlChange::CC {
+                control: data1,
+                value: data2,
+            },
+        },
+        0xc0 => ChannelVoiceMsg::ProgramChange { program: data1 },
+        0xd0 => ChannelVoiceMsg::ChannelPressure { pressure: data1 },
+        _ => ChannelVoiceMsg::PitchBend {
+            bend: u16::from(data1) | u16::from(data2) << 7,
+        },
+    };
+    MidiMsg::ChannelVoice {
+        channel: Channel::from_u8(status & 0x0f),
+        msg,
+    }
+}
+
+/// RPN or NRPN selected for data entry, by its 14-bit number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Parameter {
+    Registered(u16),
+    NonRegistered(u16),
+}
+
+impl Parameter {
+    pub const PITCH_BEND_SENSITIVITY: Self = Self::Registered(0);
+    pub const FINE_TUNING: Self = Self::Registered(1);
+    pub const COARSE_TUNING: Self = Self::Registered(2);
+
+    /// Value before any data entry: Pitch bend of 2 semitones, no fine or coarse tuning.
+    pub fn default_value(self) -> u16 {
+        match self {
+            Self::PITCH_BEND_SENSITIVITY => 2 << 7,
+            Self::FINE_TUNING | Self::COARSE_TUNING => 0x2000,
+            _ => 0,
+        }
+    }
+
+    /// `value` after the data entry controller `control` with `data`, `None` for other
+    /// controllers.
+    ///
+    /// Data Entry sets the MSB and Data Entry LSB the LSB. Increment and Decrement step by the
+    /// LSB, coarse tuning by semitones.
+    pub fn data_entry(self, value: u16, control: u8, data: u8) -> Option<u16> {
+        let step = if self == Self::COARSE_TUNING {
+            1 << 7
+        } else {
+            1
+        };
+        match control {
+            ParameterSelection::DATA_ENTRY => Some(u16::from(data) << 7 | value & 0x7f),
+            ParameterSelection::DATA_ENTRY_LSB => Some(value & !0x7f | u16::from(data)),
+            ParameterSelection::DATA_INCREMENT => Some((value + step).min(0x3fff)),
+            ParameterSelection::DATA_DECREMENT => Some(value.saturating_sub(step)),
+            _ => None,
+        }
+    }
+}
+
+/// Parameter selection of a channel, which data entry controllers write to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ParameterSelection {
+    msb: u8,
+    lsb: u8,
+    /// Whether the selected parameter is an NRPN
+    non_registered: bool,
+}
+
+impl Default for ParameterSelection {
+    fn default() -> Self {
+        Self {
+            msb: Self::NULL,
+            lsb: Self::NULL,
+            non_registered: false,
+        }
+    }
+}
+
+impl ParameterSelection {
+    pub const DATA_ENTRY: u8 = 0x06;
+    pub const DATA_ENTRY_LSB: u8 = 0x26;
+    pub const DATA_INCREMENT: u8 = 0x60;
+    pub const DATA_DECREMENT: u8 = 0x61;
+    pub const NRPN_LSB: u8 = 0x62;
+    pub const NRPN_MSB: u8 = 0x63;
+    pub const RPN_LSB: u8 = 0x64;
+    pub const RPN_MSB: u8 = 0x65;
+    /// Parameter number of the null function, which deselects parameters
+    const NULL: u8 = 0x7f;
+
+    pub fn selected(&self) -> Option<Parameter> {
+        let number = u16::from(self.msb) << 7 | u16::from(self.lsb);
+        match (self.msb, self.lsb) {
+            (Self::NULL, Self::NULL) => None,
+            _ if self.non_registered => Some(Parameter::NonRegistered(number)),
+            _ => Some(Parameter::Registered(number)),
+        }
+    }
+
+    /// Follow the (N)RPN MSB and LSB controllers, `false` for other controllers.
+    pub fn apply(&mut self, control: u8, value: u8) -> bool {
+        match control {
+            Self::RPN_MSB | Self::NRPN_MSB => self.msb = value,
+            Self::RPN_LSB | Self::NRPN_LSB => self.lsb = value,
+            _ => return false,
+        }
+        self.non_registered = matches!(control, Self::NRPN_MSB | Self::NRPN_LSB);
+        true
+    }
+
+    /// Select `parameter`, as [`Self::messages`] do.
+    pub fn select(&mut self, parameter: Parameter) {
+        let (number, non_registered) = match parameter {
+            Parameter::Registered(number) => (number, false),
+            Parameter::NonRegistered(number) => (number, true),
+        };
+        self.msb = (number >> 7) as u8 & 0x7f;
+        self.lsb = (number & 0x7f) as u8;
+        self.non_registered = non_registered;
+    }
+
+    /// Controllers and values that make the same selection.
+    pub fn controls(&self) -> [(u8, u8); 2] {
+        let (msb_control, lsb_control) = if self.non_registered {
+            (Self::NRPN_MSB, Self::NRPN_LSB)
+        } else {
+            (Self::RPN_MSB, Self::RPN_LSB)
+        };
+        [(msb_control, self.msb), (lsb_control, self.lsb)]
+    }
+}
+
+/// State of a single MIDI channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelState {
+    program: Option<u8>,
+    /// Last value of every controller, `None` if it was never set.
+    controllers: [Option<u8>; 128],
+    pitch_bend: Option<u16>,
+    pressure: Option<u8>,
+    selection: ParameterSelection,
+    /// Value of every (N)RPN that was written to.
+    parameters: Vec<(Parameter, u16)>,
+    /// Velocity of every sounding note.
+    notes: [Option<u8>; 128],
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        Self {
+            program: None,
+            controllers: [None; 128],
+            pitch_bend: None,
+            pressure: None,
+            selection: ParameterSelection::default(),
+            parameters: vec![],
+            notes: [None; 128],
+        }
+    }
+}
+
+impl ChannelState {
+    const BANK_SELECT: u8 = 0x00;
+    const BANK_SELECT_LSB: u8 = 0x20;
+
+    /// Update the state with one `[status, data1, data2]` message.
+    fn apply(&mut self, [status, data1, data2]: [u8; 3]) {
+        let data1 = data1 & 0x7f;
+        let data2 = data2 & 0x7f;
+        match status & 0xf0 {
+            0x80 => self.notes[usize::from(data1)] = None,
+            0x90 if data2 == 0 => self.notes[usize::from(data1)] = None,
+            0x90 => self.notes[usize::from(data1)] = Some(data2),
+            0xb0 => self.apply_controller(data1, data2),
+            0xc0 => self.program = Some(data1),
+            0xd0 => self.pressure = Some(data1),
+            0xe0 => self.pitch_bend = Some(u16::from(data1) | u16::from(data2) << 7),
+            _ => (),
+        }
+    }
+
+    fn apply_controller(&mut self, control: u8, value: u8) {
+        if self.selection.apply(control, value) {
+            return;
+        }
+        if let Some(parameter) = self.selection.selected() {
+            let index = self.parameters.iter().position(|(p, _)| *p == parameter);
+            let value_before = index.map_or(parameter.default_value(), |i| self.parameters[i].1);
+            if let Some(value) = parameter.data_entry(value_before, control, value) {
+                match index {
+                    Some(i) => self.parameters[i].1 = value,
+                    None => self.parameters.push((parameter, value)),
+                }
+                return;
+            }
+        }
+        match control {
+            // Data entry without a selected parameter
+            ParameterSelection::DATA_ENTRY
+            | ParameterSelection::DATA_ENTRY_LSB
+            | ParameterSelection::DATA_INCREMENT
+            | ParameterSelection::DATA_DECREMENT => (),
+            // All Sound Off, All Notes Off and the mode changes that imply it.
+            0x78 | 0x7b..=0x7f => self.notes = [None; 128],
+            // Reset All Controllers
+            0x79 => {
+                let bank = self.controllers[usize::from(Self::BANK_SELECT)];
+                let bank_lsb = self.controllers[usize::from(Self::BANK_SELECT_LSB)];
+                self.controllers = [None; 128];
+                self.controllers[usize::from(Self::BANK_SELECT)] = bank;
+                self.controllers[usize::from(Self::BANK_SELECT_LSB)] = bank_lsb;
+                self.pitch_bend = None;
+                self.pressure = None;
+                self.selection = ParameterSelection::default();
+            }
+            0x7a => (),
+            _ => self.controllers[usize::from(control)] = Some(value),
+        }
+    }
+
+    /// Send messages that bring a freshly reset `event_sink` to this state.
+    fn restore<R>(&self, channel: Channel, event_sink: &mut R, retrigger_notes: bool)
+    where
+        R: MidiSink,
+    {
+        let mut send = |msg| {
+            let _ = event_sink.receive_midi(&MidiMsg::ChannelVoice { channel, msg });
+        };
+        let cc = |control, value| ChannelVoiceMsg::ControlChange {
+            control: ControlChange::CC { control, value },
+        };
+
+        // Bank select only takes effect with the following program change.
+        for control in [Self::BANK_SELECT, Self::BANK_SELECT_LSB] {
+            if let Some(value) = self.controllers[usize::from(control)] {
+                send(cc(control, value));
+            }
+        }
+        if let Some(program) = self.program {
+            send(ChannelVoiceMsg::ProgramChange { program });
+        }
+        for (control, value) in self.controllers.iter().enumerate() {
+            let control = control as u8;
+            let skip = matches!(
+                control,
+                Self::BANK_SELECT
+                    | Self::BANK_SELECT_LSB
+                    | ParameterSelection::DATA_ENTRY
+                    | ParameterSelection::DATA_ENTRY_LSB
+                    | ParameterSelection::DATA_INCREMENT
+                    ..=ParameterSelection::RPN_MSB
+            );
+            if let (Some(value), false) = (value, skip) {
+                send(cc(control, *value));
+            }
+        }
+
+        for &(parameter, value) in &self.parameters {
+            let mut selection = ParameterSelection::default();
+            selection.select(parameter);
+            for (control, value) in selection.controls() {
+                send(cc(control, value));
+            }
+            send(cc(ParameterSelection::DATA_ENTRY, (value >> 7) as u8));
+            send(cc(ParameterSelection::DATA_ENTRY_LSB, (value & 0x7f) as u8));
+        }
+        // Leave the same parameter selected as the song did.
+        if self.selection != ParameterSelection::default() {
+            for (control, value) in self.selection.controls() {
+                send(cc(control, value));
+            }
+        }
+
+        if let Some(bend) = self.pitch_bend {
+            send(ChannelVoiceMsg::PitchBend { bend });
+        }
+        if let Some(pressure) = self.pressure {
+            send(ChannelVoiceMsg::ChannelPressure { pressure });
+        }
+        if retrigger_notes {
+            for (note, velocity) in self.notes.iter().enumerate() {
+                if let Some(velocity) = *velocity {
+                    let note = note as u8;
+                    send(ChannelVoiceMsg::NoteOn { note, velocity });
+                }
+            }
+        }
+    }
+}
+
+/// [`ChannelState`] of all 16 channels.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelStates {
+    channels: [ChannelState; 16],
+    /// System exclusive messages since the last reset, only the last one of each kind.
+    system: Vec<(SysEx, MidiMsg)>,
+}
+
+impl ChannelStates {
+    /// Update the state with any message, other messages than channel and known system exclusive
+    /// ones are ignored.
+    pub fn apply(&mut self, msg: &MidiMsg) {
+        if let Some(sysex) = SysEx::from_midi(msg) {
+            match sysex {
+                SysEx::Reset => *self = Self::default(),
+                sysex => {
+                    self.system.retain(|(other, _)| !sysex.replaces(other));
+                    self.system.push((sysex, msg.clone()));
+                }
+            }
+            return;
+        }
+        if !(msg.is_channel_voice() || msg.is_channel_mode()) {
+            return;
+        }
+        for message in split_messages(&msg.to_midi()) {
+            self.channels[usize::from(message[0] & 0x0f)].apply(message);
+        }
+    }
+
+    /// Send messages that bring a freshly reset `event_sink` to this state.
+    ///
+    /// With `retrigger_notes`, notes that are held are started again.
+    pub fn restore<R>(&self, event_sink: &mut R, retrigger_notes: bool)
+    where
+        R: MidiSink,
+    {
+        // Drum parts must be set before their bank and program are restored.
+        for (_, msg) in &self.system {
+            let _ = event_sink.receive_midi(msg);
+        }
+        for (channel, state) in self.channels.iter().enumerate() {
+            state.restore(Channel::from_u8(channel as u8), event_sink, retrigger_notes);
+        }
+    }
+}
+
+#[test]
+fn test_split_messages() {
+    // Merged RPN selection with running status, followed by a high resolution note on.
+    let raw = [
+        0xb2, 0x65, 0x00, 0x64, 0x00, 0x92, 0x3c, 0x40, 0xb2, 0x58, 0x01,
+    ];
+    let messages: Vec<_> = split_messages(&raw).collect();
+    assert_eq!(
+        messages,
+        [
+            [0xb2, 0x65, 0x00],
+            [0xb2, 0x64, 0x00],
+            [0x92, 0x3c, 0x40],
+            [0xb2, 0x58, 0x01]
+        ]
+    );
+}
+
+#[test]
+fn test_channel_state_parameters() {
+    let mut state = ChannelState::default();
+    for message in split_messages(&[0xb0, 0x65, 0x00, 0x64, 0x00, 0x06, 0x0c, 0x26, 0x00]) {
+        state.apply(message);
+    }
+    // Increments step by the LSB, coarse tuning by semitones.
+    state.apply([0xb0, 0x60, 0x00]);
+    for message in split_messages(&[0xb0, 0x64, 0x02, 0x60, 0x00]) {
+        state.apply(message);
+    }
+    assert_eq!(
+        state.parameters,
+        [
+            (Parameter::PITCH_BEND_SENSITIVITY, 12 << 7 | 1),
+            (Parameter::COARSE_TUNING, 0x2080)
+        ]
+    );
+}
+
+#[test]
+fn test_channel_states_sysex() {
+    let sysex = |bytes: &[u8]| MidiMsg::from_midi(bytes).unwrap().0;
+    let gs_drum_part = |map| {
+        let checksum = (0x80 - (0x40 + 0x1a + 0x15 + map) % 0x80) % 0x80;
+        sysex(&[
+            0xf0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x1a, 0x15, map, checksum, 0xf7,
+        ])
+    };
+    let xg_volume = |volume| sysex(&[0xf0, 0x43, 0x10, 0x4c, 0x00, 0x00, 0x04, volume, 0xf7]);
+
+    let mut states = ChannelStates::default();
+    states.apply(&channel_message([0xc0, 5, 0]));
+    states.apply(&gs_drum_part(1));
+    states.apply(&xg_volume(100));
+    states.apply(&gs_drum_part(0));
+    states.apply(&xg_volume(90));
+    assert_eq!(
+        states.system,
+        [
+            (
+                SysEx::DrumPart {
+                    channel: 10,
+                    drums: false
+                },
+                gs_drum_part(0)
+            ),
+            (SysEx::MasterVolume(90. / 127.), xg_volume(90)),
+        ]
+    );
+
+    // XG System On
+    states.apply(&sysex(&[
+        0xf0, 0x43, 0x10, 0x4c, 0x00, 0x00, 0x7e, 0x00, 0xf7,
+    ]));
+    assert_eq!(states, ChannelStates::default());
+}
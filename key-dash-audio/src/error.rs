@@ -0,0 +1,116 @@
+use midi_msg::ParseError;
+use rustysynth::{SoundFontError, SynthesizerError};
+use std::{
+    fmt::Display,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can go wrong in the [`Player`](super::Player).
+#[derive(Debug)]
+pub enum PlayerError {
+    /// No audio output is set.
+    NoSink,
+    /// No SoundFont is loaded.
+    NoFont,
+    /// No MIDI file is loaded.
+    NoMidi,
+    /// There is no audio device to play on.
+    NoDevice,
+    /// A loop must end after it starts.
+    EmptyLoop,
+    /// A file could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// A file could not be written.
+    Write { path: PathBuf, source: io::Error },
+    /// The SoundFont could not be parsed.
+    SoundFont {
+        /// File the SoundFont was read from, if any
+        path: Option<PathBuf>,
+        source: SoundFontError,
+    },
+    /// The MIDI file could not be parsed.
+    Midi {
+        /// File the MIDI data was read from, if any
+        path: Option<PathBuf>,
+        /// Byte offset of the error in the file
+        offset: usize,
+        source: ParseError,
+    },
+    /// The synthesizer rejected its settings.
+    Synthesizer(SynthesizerError),
+    /// The audio output failed.
+    AudioOutput(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Display for PlayerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSink => write!(f, "No audio output"),
+            Self::NoFont => write!(f, "No SoundFont loaded"),
+            Self::NoMidi => write!(f, "No MIDI file loaded"),
+            Self::NoDevice => write!(f, "No audio device found"),
+            Self::EmptyLoop => write!(f, "Loop must end after it starts"),
+            Self::Io { path, source } => write!(f, "Could not read {}: {source}", path.display()),
+            Self::Write { path, source } => {
+                write!(f, "Could not write {}: {source}", path.display())
+            }
+            Self::SoundFont {
+                path: Some(path),
+                source,
+            } => write!(f, "Could not read SoundFont {}: {source}", path.display()),
+            Self::SoundFont { path: None, source } => {
+                write!(f, "Could not read SoundFont: {source}")
+            }
+            Self::Midi {
+                path: Some(path),
+                offset,
+                source,
+            } => write!(
+                f,
+                "Could not read MIDI file {} at byte {offset}: {source}",
+                path.display()
+            ),
+            Self::Midi {
+                path: None,
+                offset,
+                source,
+            } => write!(f, "Could not read MIDI file at byte {offset}: {source}"),
+            Self::Synthesizer(err) => write!(f, "Could not create synthesizer: {err}"),
+            Self::AudioOutput(err) => write!(f, "Audio output failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PlayerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } | Self::Write { source, .. } => Some(source),
+            Self::SoundFont { source, .. } => Some(source),
+            Self::Midi { source, .. } => Some(source),
+            Self::Synthesizer(err) => Some(err),
+            Self::AudioOutput(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl PlayerError {
+    /// Error of writing a WAV stream: I/O errors of the file at `path` are [`Self::Write`]
+    /// errors, others are [`Self::AudioOutput`] errors.
+    pub(crate) fn wav(path: Option<&Path>, err: hound::Error) -> Self {
+        match (path, err) {
+            (Some(path), hound::Error::IoError(source)) => Self::Write {
+                path: path.to_path_buf(),
+                source,
+            },
+            (_, err) => Self::AudioOutput(Box::new(err)),
+        }
+    }
+}
+
+impl From<SynthesizerError> for PlayerError {
+    fn from(value: SynthesizerError) -> Self {
+        Self::Synthesizer(value)
+    }
+}
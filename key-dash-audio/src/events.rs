@@ -0,0 +1,72 @@
+//! Playback events: Sent from the audio thread to any number of subscribers, such as the UI.
+//!
+
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Something that happened during playback.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    /// Current song position, sent periodically while playing.
+    Position(Duration),
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    ControlChange {
+        channel: u8,
+        control: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// A system exclusive message switched `channel` to drum kits, or to melodic instruments.
+    DrumPart {
+        channel: u8,
+        drums: bool,
+    },
+    /// Every channel returned to its defaults, after a seek or a GM/GS/XG reset.
+    Reset,
+    TempoChange {
+        bpm: f64,
+    },
+    Lyric(String),
+    /// A marker or cue point was passed.
+    Marker(String),
+    /// The song has no events left.
+    EndOfSong,
+    /// Something went wrong in the audio thread, playback continues.
+    Error(String),
+}
+
+/// Cheap to clone broadcasting end of [`PlayerEvent`]s.
+///
+/// Sending never blocks the audio thread: Subscribers that fall behind skip the oldest events.
+#[derive(Debug, Clone)]
+pub(crate) struct EventSender(broadcast::Sender<PlayerEvent>);
+
+impl EventSender {
+    const CAPACITY: usize = 1024;
+
+    pub fn send(&self, event: PlayerEvent) {
+        // Having no subscribers is fine.
+        let _ = self.0.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PlayerEvent> {
+        self.0.subscribe()
+    }
+}
+
+impl Default for EventSender {
+    fn default() -> Self {
+        Self(broadcast::Sender::new(Self::CAPACITY))
+    }
+}
@@ -0,0 +1,208 @@
+//! General MIDI instrument names, and the names SoundFont presets give them.
+//!
+
+use rustysynth::SoundFont;
+
+/// Bank the drum kits of a SoundFont are in.
+pub const DRUM_BANK: u16 = 128;
+
+/// Instruments of the General MIDI Level 1 sound set, by program number.
+pub const PROGRAM_NAMES: [&str; 128] = [
+    // Piano
+    "Acoustic Grand Piano",
+    "Bright Acoustic Piano",
+    "Electric Grand Piano",
+    "Honky-tonk Piano",
+    "Electric Piano 1",
+    "Electric Piano 2",
+    "Harpsichord",
+    "Clavi",
+    // Chromatic Percussion
+    "Celesta",
+    "Glockenspiel",
+    "Music Box",
+    "Vibraphone",
+    "Marimba",
+    "Xylophone",
+    "Tubular Bells",
+    "Dulcimer",
+    // Organ
+    "Drawbar Organ",
+    "Percussive Organ",
+    "Rock Organ",
+    "Church Organ",
+    "Reed Organ",
+    "Accordion",
+    "Harmonica",
+    "Tango Accordion",
+    // Guitar
+    "Acoustic Guitar (nylon)",
+    "Acoustic Guitar (steel)",
+    "Electric Guitar (jazz)",
+    "Electric Guitar (clean)",
+    "Electric Guitar (muted)",
+    "Overdriven Guitar",
+    "Distortion Guitar",
+    "Guitar Harmonics",
+    // Bass
+    "Acoustic Bass",
+    "Electric Bass (finger)",
+    "Electric Bass (pick)",
+    "Fretless Bass",
+    "Slap Bass 1",
+    "Slap Bass 2",
+    "Synth Bass 1",
+    "Synth Bass 2",
+    // Strings
+    "Violin",
+    "Viola",
+    "Cello",
+    "Contrabass",
+    "Tremolo Strings",
+    "Pizzicato Strings",
+    "Orchestral Harp",
+    "Timpani",
+    // Ensemble
+    "String Ensemble 1",
+    "String Ensemble 2",
+    "Synth Strings 1",
+    "Synth Strings 2",
+    "Choir Aahs",
+    "Voice Oohs",
+    "Synth Voice",
+    "Orchestra Hit",
+    // Brass
+    "Trumpet",
+    "Trombone",
+    "Tuba",
+    "Muted Trumpet",
+    "French Horn",
+    "Brass Section",
+    "Synth Brass 1",
+    "Synth Brass 2",
+    // Reed
+    "Soprano Sax",
+    "Alto Sax",
+    "Tenor Sax",
+    "Baritone Sax",
+    "Oboe",
+    "English Horn",
+    "Bassoon",
+    "Clarinet",
+    // Pipe
+    "Piccolo",
+    "Flute",
+    "Recorder",
+    "Pan Flute",
+    "Blown Bottle",
+    "Shakuhachi",
+    "Whistle",
+    "Ocarina",
+    // Synth Lead
+    "Lead 1 (square)",
+    "Lead 2 (sawtooth)",
+    "Lead 3 (calliope)",
+    "Lead 4 (chiff)",
+    "Lead 5 (charang)",
+    "Lead 6 (voice)",
+    "Lead 7 (fifths)",
+    "Lead 8 (bass + lead)",
+    // Synth Pad
+    "Pad 1 (new age)",
+    "Pad 2 (warm)",
+    "Pad 3 (polysynth)",
+    "Pad 4 (choir)",
+    "Pad 5 (bowed)",
+    "Pad 6 (metallic)",
+    "Pad 7 (halo)",
+    "Pad 8 (sweep)",
+    // Synth Effects
+    "FX 1 (rain)",
+    "FX 2 (soundtrack)",
+    "FX 3 (crystal)",
+    "FX 4 (atmosphere)",
+    "FX 5 (brightness)",
+    "FX 6 (goblins)",
+    "FX 7 (echoes)",
+    "FX 8 (sci-fi)",
+    // Ethnic
+    "Sitar",
+    "Banjo",
+    "Shamisen",
+    "Koto",
+    "Kalimba",
+    "Bag pipe",
+    "Fiddle",
+    "Shanai",
+    // Percussive
+    "Tinkle Bell",
+    "Agogo",
+    "Steel Drums",
+    "Woodblock",
+    "Taiko Drum",
+    "Melodic Tom",
+    "Synth Drum",
+    "Reverse Cymbal",
+    // Sound Effects
+    "Guitar Fret Noise",
+    "Breath Noise",
+    "Seashore",
+    "Bird Tweet",
+    "Telephone Ring",
+    "Helicopter",
+    "Applause",
+    "Gunshot",
+];
+
+/// Name of the GS drum kit at `program`, the nearest kit below for programs without one.
+pub const fn drum_kit_name(program: u8) -> &'static str {
+    match program {
+        0..=7 => "Standard Kit",
+        8..=15 => "Room Kit",
+        16..=23 => "Power Kit",
+        24 => "Electronic Kit",
+        25..=31 => "TR-808 Kit",
+        32..=39 => "Jazz Kit",
+        40..=47 => "Brush Kit",
+        48..=55 => "Orchestra Kit",
+        56..=126 => "SFX Kit",
+        _ => "CM-64/32L Kit",
+    }
+}
+
+/// What a channel playing `program` of `bank` (Bank Select MSB) sounds like: The name of the
+/// matching preset of `soundfont`, falling back to bank 0 as the synthesizer does, or else the
+/// General MIDI name.
+pub fn instrument_name(
+    soundfont: Option<&SoundFont>,
+    bank: u8,
+    program: u8,
+    drums: bool,
+) -> String {
+    let program = program & 0x7f;
+    let (bank, fallback) = if drums {
+        (i32::from(DRUM_BANK), i32::from(DRUM_BANK))
+    } else {
+        (i32::from(bank), 0)
+    };
+    let preset = soundfont.and_then(|soundfont| {
+        let find = |bank| {
+            soundfont.get_presets().iter().find(|preset| {
+                preset.get_bank_number() == bank && preset.get_patch_number() == i32::from(program)
+            })
+        };
+        find(bank).or_else(|| find(fallback))
+    });
+    match preset {
+        Some(preset) => preset.get_name().trim().to_string(),
+        None if drums => drum_kit_name(program).to_string(),
+        None => PROGRAM_NAMES[usize::from(program)].to_string(),
+    }
+}
+
+#[test]
+fn test_instrument_name() {
+    assert_eq!(instrument_name(None, 0, 0, false), "Acoustic Grand Piano");
+    assert_eq!(instrument_name(None, 8, 127, false), "Gunshot");
+    assert_eq!(instrument_name(None, 0, 25, true), "TR-808 Kit");
+}
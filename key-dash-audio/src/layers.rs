@@ -0,0 +1,298 @@
+//! SoundFont layering: MIDI channels played by other SoundFonts than the main one, or with a
+//! fixed preset, see [`Player::set_channel_override`](super::Player::set_channel_override).
+//!
+//! Every SoundFont gets its own synthesizer. Channel messages go to the synthesizer of their
+//! channel only, system exclusive messages and resets to all of them, and their outputs are
+//! summed.
+//!
+
+use midi_msg::MidiMsg;
+use rustysynth::{SoundFont, Synthesizer, SynthesizerError};
+use std::sync::Arc;
+
+use super::{
+    channel_state::{channel_message, split_messages},
+    gm,
+    midi_sequencer::MidiSink,
+    midi_synth::MidiSynth,
+    sysex::SysEx,
+};
+
+/// How one MIDI channel sounds instead of the main SoundFont and the song's program changes.
+#[derive(Clone, Default)]
+pub struct ChannelOverride {
+    /// Plays the channel, `None` keeps the main SoundFont
+    pub soundfont: Option<Arc<SoundFont>>,
+    /// Bank and program played regardless of the song's program changes, bank 128 holds the drum
+    /// kits. `None` follows the song.
+    pub preset: Option<(u16, u8)>,
+}
+
+/// Where the messages of each channel go.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Routes {
+    /// Index into [`LayeredSynth::layers`] of each channel
+    layers: [usize; 16],
+    /// Preset forced on each channel
+    presets: [Option<(u16, u8)>; 16],
+}
+
+impl Routes {
+    const PROGRAM_CHANGE: u8 = 0xc0;
+    const BANK_SELECT: u8 = 0x00;
+    const BANK_SELECT_LSB: u8 = 0x20;
+
+    /// Layer `[status, data1, data2]` goes to, `None` if a preset override drops it.
+    fn route(&self, [status, data1, _]: [u8; 3]) -> Option<usize> {
+        let channel = usize::from(status & 0x0f);
+        let overridden = self.presets[channel].is_some();
+        match status & 0xf0 {
+            Self::PROGRAM_CHANGE if overridden => None,
+            0xb0 if overridden && matches!(data1, Self::BANK_SELECT | Self::BANK_SELECT_LSB) => {
+                None
+            }
+            _ => Some(self.layers[channel]),
+        }
+    }
+}
+
+/// Synthesizers of the main SoundFont and of the [`ChannelOverride`]s, behaving like one.
+pub(crate) struct LayeredSynth {
+    /// The main SoundFont first, then one per other SoundFont
+    layers: Vec<MidiSynth>,
+    routes: Routes,
+    /// Output of the other layers, before it's added
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl LayeredSynth {
+    pub fn new(synthesizer: MidiSynth) -> Self {
+        Self {
+            layers: vec![synthesizer],
+            routes: Routes::default(),
+            left: vec![],
+            right: vec![],
+        }
+    }
+
+    /// Play the channels with `overrides`, a synthesizer is created per SoundFont.
+    pub fn set_overrides(
+        &mut self,
+        overrides: &[Option<ChannelOverride>; 16],
+    ) -> Result<(), SynthesizerError> {
+        let main = &self.layers[0];
+        let settings = MidiSynth::settings(main.get_sample_rate());
+        let master_volume = main.get_master_volume();
+        self.layers.truncate(1);
+        let mut soundfonts: Vec<&Arc<SoundFont>> = vec![];
+        for (channel, channel_override) in overrides.iter().enumerate() {
+            let (soundfont, preset) = channel_override
+                .as_ref()
+                .map_or((None, None), |o| (o.soundfont.as_ref(), o.preset));
+            self.routes.presets[channel] = preset;
+            self.routes.layers[channel] = match soundfont {
+                None => 0,
+                Some(soundfont) => {
+                    match soundfonts.iter().position(|s| Arc::ptr_eq(s, soundfont)) {
+                        Some(idx) => idx + 1,
+                        None => {
+                            let mut synthesizer =
+                                MidiSynth::new(Synthesizer::new(soundfont, &settings)?);
+                            synthesizer.set_master_volume(master_volume);
+                            self.layers.push(synthesizer);
+                            soundfonts.push(soundfont);
+                            soundfonts.len()
+                        }
+                    }
+                }
+            };
+        }
+        Ok(())
+    }
+
+    pub fn get_sample_rate(&self) -> i32 {
+        self.layers[0].get_sample_rate()
+    }
+
+    pub fn set_master_volume(&mut self, value: f32) {
+        for layer in &mut self.layers {
+            layer.set_master_volume(value);
+        }
+    }
+
+    /// Render every layer, summed into `left` and `right`.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let (main, others) = self.layers.split_at_mut(1);
+        main[0].render(left, right);
+        if others.is_empty() {
+            return;
+        }
+        // Only grows, up to the largest block.
+        if self.left.len() < left.len() {
+            self.left.resize(left.len(), 0.);
+            self.right.resize(left.len(), 0.);
+        }
+        let layer_left = &mut self.left[..left.len()];
+        let layer_right = &mut self.right[..left.len()];
+        for layer in others {
+            layer.render(layer_left, layer_right);
+            for (sample, layer_sample) in left.iter_mut().zip(layer_left.iter()) {
+                *sample += layer_sample;
+            }
+            for (sample, layer_sample) in right.iter_mut().zip(layer_right.iter()) {
+                *sample += layer_sample;
+            }
+        }
+    }
+
+    /// Select the overridden preset of `channel`, before each note so nothing can change it.
+    fn select_preset(&mut self, channel: u8) {
+        let Some((bank, program)) = self.routes.presets[usize::from(channel)] else {
+            return;
+        };
+        // The percussion channel adds the drum bank itself. Other channels take drum banks
+        // directly, which Bank Select can't.
+        let bank = if usize::from(channel) == Synthesizer::PERCUSSION_CHANNEL {
+            bank.saturating_sub(gm::DRUM_BANK)
+        } else {
+            bank
+        };
+        let layer = &mut self.layers[self.routes.layers[usize::from(channel)]];
+        let channel = i32::from(channel);
+        layer.process_midi_message(channel, 0xb0, Routes::BANK_SELECT.into(), bank.into());
+        layer.process_midi_message(channel, Routes::PROGRAM_CHANGE.into(), program.into(), 0);
+    }
+}
+
+impl MidiSink for LayeredSynth {
+    fn receive_midi(&mut self, msg: &MidiMsg) -> Result<(), ()> {
+        if SysEx::from_midi(msg).is_some() {
+            for layer in &mut self.layers {
+                let _ = layer.receive_midi(msg);
+            }
+            return Ok(());
+        }
+        if !(msg.is_channel_voice() || msg.is_channel_mode()) {
+            return Err(());
+        }
+
+        let mut result = Ok(());
+        for message in split_messages(&msg.to_midi()) {
+            let Some(layer) = self.routes.route(message) else {
+                continue;
+            };
+            if message[0] & 0xf0 == 0x90 && message[2] > 0 {
+                self.select_preset(message[0] & 0x0f);
+            }
+            result = result.and(self.layers[layer].receive_midi(&channel_message(message)));
+        }
+        result
+    }
+
+    fn reset(&mut self) {
+        for layer in &mut self.layers {
+            MidiSink::reset(layer);
+        }
+    }
+}
+
+#[test]
+fn test_routes() {
+    let mut routes = Routes::default();
+    routes.layers[2] = 1;
+    routes.presets[2] = Some((0, 48));
+    routes.presets[9] = Some((128, 25));
+    // Notes and controllers go to the layer of their channel.
+    assert_eq!(routes.route([0x92, 60, 100]), Some(1));
+    assert_eq!(routes.route([0xb9, 0x07, 90]), Some(0));
+    // Program changes and bank selects of overridden channels are dropped.
+    assert_eq!(routes.route([0xc2, 10, 0]), None);
+    assert_eq!(routes.route([0xb9, 0x20, 1]), None);
+    assert_eq!(routes.route([0xc0, 10, 0]), Some(0));
+}
+
+#[test]
+fn test_layered_synth() {
+    use super::loader::test_soundfont;
+    use midi_msg::{Channel, ChannelVoiceMsg, ControlChange};
+
+    // Only program 48 of the second SoundFont sounds.
+    let main = test_soundfont(&[(0, 0, false), (0, 10, false)]);
+    let second = test_soundfont(&[(0, 0, false), (0, 10, false), (0, 48, true)]);
+    let layered = || {
+        let settings = MidiSynth::settings(44100);
+        let mut synth =
+            LayeredSynth::new(MidiSynth::new(Synthesizer::new(&main, &settings).unwrap()));
+        let mut overrides: [Option<ChannelOverride>; 16] = Default::default();
+        overrides[2] = Some(ChannelOverride {
+            soundfont: Some(second.clone()),
+            preset: Some((0, 48)),
+        });
+        overrides[3] = Some(ChannelOverride {
+            soundfont: Some(second.clone()),
+            preset: None,
+        });
+        synth.set_overrides(&overrides).unwrap();
+        synth
+    };
+    let send = |synth: &mut LayeredSynth, channel, msg| {
+        synth
+            .receive_midi(&MidiMsg::ChannelVoice { channel, msg })
+            .unwrap();
+    };
+    let note_on = ChannelVoiceMsg::NoteOn {
+        note: 60,
+        velocity: 100,
+    };
+    let peak = |synth: &mut LayeredSynth| {
+        let mut left = vec![0.; 4096];
+        let mut right = vec![0.; 4096];
+        synth.render(&mut left, &mut right);
+        left.iter()
+            .chain(&right)
+            .fold(0f32, |peak, s| peak.max(s.abs()))
+    };
+
+    // Channels of the same SoundFont share a layer.
+    let mut synth = layered();
+    assert_eq!(synth.layers.len(), 2);
+    assert_eq!(synth.routes.layers[..4], [0, 0, 1, 1]);
+    // The main SoundFont has no program 48.
+    send(
+        &mut synth,
+        Channel::Ch1,
+        ChannelVoiceMsg::ProgramChange { program: 48 },
+    );
+    send(&mut synth, Channel::Ch1, note_on);
+    assert_eq!(peak(&mut synth), 0.);
+    // Channel 4 plays the second SoundFont, with the song's programs.
+    send(
+        &mut synth,
+        Channel::Ch4,
+        ChannelVoiceMsg::ProgramChange { program: 10 },
+    );
+    send(&mut synth, Channel::Ch4, note_on);
+    assert_eq!(peak(&mut synth), 0.);
+    send(
+        &mut synth,
+        Channel::Ch4,
+        ChannelVoiceMsg::ProgramChange { program: 48 },
+    );
+    send(&mut synth, Channel::Ch4, note_on);
+    assert!(peak(&mut synth) > 0.001);
+
+    // Channel 3 keeps its preset, whatever the song selects.
+    let mut synth = layered();
+    send(
+        &mut synth,
+        Channel::Ch3,
+        ChannelVoiceMsg::ProgramChange { program: 10 },
+    );
+    let bank_select = ChannelVoiceMsg::ControlChange {
+        control: ControlChange::BankSelect(1 << 7),
+    };
+    send(&mut synth, Channel::Ch3, bank_select);
+    send(&mut synth, Channel::Ch3, note_on);
+    assert!(peak(&mut synth) > 0.001);
+}
@@ -1,61 +1,397 @@
-use midi_msg::{MidiFile, MidiMsg};
-use midi_source::MidiSource;
-use rodio::Sink;
+use events::EventSender;
+use midi_msg::MidiFile;
+use midi_source::{MidiSource, SharedPosition};
+use output::OutputBackend;
 use rustysynth::SoundFont;
-use std::{sync::Arc, time::Duration};
-use strum::Display;
+use song_settings::SettingsStore;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::broadcast;
 
+mod channel_state;
+mod error;
+mod events;
+pub mod gm;
+mod layers;
+pub mod loader;
+pub mod lyrics;
+pub mod m3u;
+pub mod metadata;
 mod midi_sequencer;
 mod midi_source;
 mod midi_synth;
+mod mixer;
+pub mod notes;
+pub mod output;
+pub mod playlist;
+pub mod render;
+mod song_settings;
+mod status;
+mod sysex;
+pub mod tempo_map;
+mod transpose;
+
+pub use error::PlayerError;
+pub use events::PlayerEvent;
+pub use layers::ChannelOverride;
+pub use loader::Song;
+pub use metadata::SongMetadata;
+pub use mixer::{ChannelMix, Mixer};
+pub use notes::NoteList;
+pub use playlist::{Playlist, PlaylistEntry, RepeatMode};
+pub use song_settings::{LoopPoint, LoopRegion, SongSettings};
+pub use status::{PlaybackState, PlayerStatus};
 
 #[derive(Default)]
 pub struct Player {
     soundfont: Option<Arc<SoundFont>>,
+    /// Sound of the channels that don't play `soundfont` as the song says
+    channel_overrides: [Option<ChannelOverride>; 16],
     midi_file: Option<MidiFile>,
+    /// Identifies `midi_file` to the user
+    midi_name: Option<String>,
+    /// Collected from the meta events of `midi_file`
+    metadata: Option<SongMetadata>,
+    /// Every note of `midi_file`
+    notes: Option<NoteList>,
+    /// Time from the start of `midi_file` to its last event
+    midi_length: Option<Duration>,
     midi_duration: Option<Duration>,
-    sink: Option<Sink>,
-    msg_callback: Option<Box<dyn Fn(MidiMsg)>>,
+    output: Option<Box<dyn OutputBackend>>,
+    retrigger_on_seek: bool,
+    /// Shared with the playing [`MidiSource`]
+    mixer: Arc<Mutex<Mixer>>,
+    /// Settings of the current song, shared with the playing [`MidiSource`]
+    song_settings: Arc<Mutex<SongSettings>>,
+    /// [`Song::key`] of `midi_file`
+    midi_key: Option<String>,
+    /// Settings of every song they were changed for
+    remembered_settings: SettingsStore,
+    /// Start over at the end of the song, shared with the playing [`MidiSource`]
+    repeat_one: Arc<AtomicBool>,
+    /// Written by the playing [`MidiSource`]
+    position: SharedPosition,
+    events: EventSender,
 }
 
 impl Player {
-    pub fn set_sink(&mut self, value: Option<Sink>) {
-        if let Some(ref sink) = value {
-            sink.pause();
+    /// Receive [`PlayerEvent`]s of every following playback.
+    ///
+    /// A subscriber that falls behind gets [`broadcast::error::RecvError::Lagged`] and skips the
+    /// oldest events.
+    pub fn subscribe(&self) -> broadcast::Receiver<PlayerEvent> {
+        self.events.subscribe()
+    }
+
+    /// Snapshot of the current playback.
+    pub fn status(&self) -> PlayerStatus {
+        let state = match (&self.output, self.midi_duration) {
+            (None, _) | (Some(_), None) => PlaybackState::Stopped,
+            (Some(output), Some(_)) if output.empty() => PlaybackState::Finished,
+            (Some(output), Some(_)) if output.is_paused() => PlaybackState::Paused,
+            (Some(_), Some(_)) => PlaybackState::Playing,
+        };
+        let position = match state {
+            PlaybackState::Playing | PlaybackState::Paused => self.position.get(),
+            PlaybackState::Finished => self.midi_duration.unwrap_or_default(),
+            PlaybackState::Stopped => Duration::ZERO,
+        };
+        PlayerStatus {
+            state,
+            position,
+            duration: self.midi_duration,
+            bar_beat: self
+                .metadata
+                .as_ref()
+                .map(|metadata| metadata.bar_beat_at(position)),
+            midi_name: self.midi_name.clone(),
+            soundfont_name: self
+                .soundfont
+                .as_ref()
+                .map(|soundfont| soundfont.get_info().get_bank_name().to_string()),
+            master_gain_db: self.mixer().master_gain_db(),
+            speed: self.song_settings().speed(),
+            transpose: self.song_settings().transpose(),
+            loop_region: self.song_settings().loop_region(),
+            repeat_one: self.repeat_one(),
         }
-        self.sink = value;
     }
 
+    /// Play through `value` from the next [`Player::start_playback`], see [`output`] for the
+    /// available backends.
+    pub fn set_output(&mut self, value: Option<Box<dyn OutputBackend>>) {
+        if let Some(ref output) = value {
+            output.pause();
+        }
+        self.output = value;
+        self.midi_duration = None;
+    }
+
+    /// Switch to SoundFont `value`. The current song goes on from where it was with the new
+    /// sounds, paused if it was.
     pub fn set_soundfont(&mut self, value: Arc<SoundFont>) {
         self.soundfont = Some(value);
+        self.restart_playback();
+    }
+
+    /// Sound of each MIDI channel other than the main SoundFont or the song's programs, see
+    /// [`ChannelOverride`].
+    pub const fn channel_overrides(&self) -> &[Option<ChannelOverride>; 16] {
+        &self.channel_overrides
+    }
+
+    /// Play MIDI `channel` (0-15) with `value`, or as the song says with `None`. The current song
+    /// goes on from where it was, paused if it was. Out of range channels are ignored.
+    pub fn set_channel_override(&mut self, channel: u8, value: Option<ChannelOverride>) {
+        let Some(channel_override) = self.channel_overrides.get_mut(usize::from(channel)) else {
+            return;
+        };
+        *channel_override = value;
+        self.restart_playback();
+    }
+
+    /// Read the SoundFont at `path` and switch to it, see [`loader::read_soundfont`].
+    pub fn load_soundfont<P>(&mut self, path: P) -> Result<(), PlayerError>
+    where
+        P: AsRef<Path>,
+    {
+        self.set_soundfont(loader::read_soundfont(path)?);
+        Ok(())
+    }
+
+    /// Parse SoundFont `bytes` and switch to it, see [`loader::parse_soundfont`].
+    pub fn load_soundfont_bytes(&mut self, bytes: &[u8]) -> Result<(), PlayerError> {
+        self.set_soundfont(loader::parse_soundfont(bytes)?);
+        Ok(())
+    }
+
+    /// Replace the current song, stopping its playback. Its remembered [`SongSettings`] are
+    /// restored.
+    pub fn set_song(&mut self, song: Song) {
+        if let Some(output) = &self.output {
+            output.clear();
+        }
+        self.midi_duration = None;
+
+        let settings = self.remembered_settings.get(&song.key);
+        self.midi_length = Some(song.length());
+        *self
+            .song_settings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = settings;
+        self.midi_name = Some(song.name);
+        self.midi_key = Some(song.key);
+        let metadata = SongMetadata::new(&song.midi_file);
+        self.notes = Some(NoteList::new(&song.midi_file, metadata.tempo_map()));
+        self.metadata = Some(metadata);
+        self.midi_file = Some(song.midi_file);
+    }
+
+    /// Keep the remembered [`SongSettings`] in `file`, so they last until the next run, see
+    /// [`Song::key`]. The settings it holds are read, a missing file is created once changed
+    /// settings are saved.
+    pub fn set_settings_file(&mut self, file: PathBuf) -> Result<(), PlayerError> {
+        self.remembered_settings.set_file(file)
+    }
+
+    /// Write the remembered [`SongSettings`] into the file of [`Player::set_settings_file`] if
+    /// they changed, and the last change is at least `delay` old. A failed write is tried again
+    /// `delay` later.
+    ///
+    /// Settings change with every step of speed or transposition, a `delay` writes the file once
+    /// for all of them. Without any, pending changes are written right away, such as before
+    /// quitting.
+    pub fn save_song_settings(&mut self, delay: Duration) -> Result<(), PlayerError> {
+        self.remembered_settings.save(delay)
+    }
+
+    /// Read the MIDI file at `path` and make it the current song, see [`loader::read_midi`].
+    pub fn load_midi<P>(&mut self, path: P) -> Result<(), PlayerError>
+    where
+        P: AsRef<Path>,
+    {
+        self.set_song(loader::read_midi(path)?);
+        Ok(())
+    }
+
+    /// Parse MIDI file `bytes` and make it the current song, see [`loader::parse_midi`].
+    pub fn load_midi_bytes(&mut self, bytes: &[u8], name: &str) -> Result<(), PlayerError> {
+        self.set_song(loader::parse_midi(bytes, name)?);
+        Ok(())
+    }
+
+    /// Start notes that are held at the seek target again, instead of waiting for the next ones.
+    ///
+    /// Takes effect from the next [`Player::start_playback`].
+    pub const fn set_retrigger_on_seek(&mut self, value: bool) {
+        self.retrigger_on_seek = value;
+    }
+
+    /// The current SoundFont, if any.
+    pub const fn soundfont(&self) -> Option<&Arc<SoundFont>> {
+        self.soundfont.as_ref()
+    }
+
+    /// Current mix settings.
+    pub fn mixer(&self) -> Mixer {
+        self.update_mixer(|mixer| mixer.clone())
+    }
+
+    /// Master gain in dB, see [`Mixer::set_master_gain_db`]. Takes effect while playing.
+    pub fn set_master_gain_db(&self, gain_db: f32) {
+        self.update_mixer(|mixer| mixer.set_master_gain_db(gain_db));
+    }
+
+    /// Scale the volume of MIDI `channel` (0-15). Takes effect while playing.
+    pub fn set_channel_volume(&self, channel: u8, volume: f32) {
+        self.update_mixer(|mixer| mixer.set_channel_volume(channel, volume));
+    }
+
+    /// Silence MIDI `channel` (0-15). Takes effect while playing.
+    pub fn set_channel_mute(&self, channel: u8, mute: bool) {
+        self.update_mixer(|mixer| mixer.set_channel_mute(channel, mute));
+    }
+
+    /// Silence every MIDI channel not soloed, if any is. Takes effect while playing.
+    pub fn set_channel_solo(&self, channel: u8, solo: bool) {
+        self.update_mixer(|mixer| mixer.set_channel_solo(channel, solo));
+    }
+
+    /// Title, markers, signatures and other meta events of the current song.
+    pub const fn metadata(&self) -> Option<&SongMetadata> {
+        self.metadata.as_ref()
+    }
 
-        if let Some(sink) = &self.sink {
-            if !sink.empty() {
-                let position = sink.get_pos();
-                sink.clear();
-                let _ = self.start_playback();
-                let _ = self.seek_to(position);
+    /// Notes of the current song, as written in the file.
+    pub const fn notes(&self) -> Option<&NoteList> {
+        self.notes.as_ref()
+    }
+
+    /// Speed, transposition and loop region of the current song.
+    pub fn song_settings(&self) -> SongSettings {
+        *self
+            .song_settings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Play faster or slower, see [`SongSettings::set_speed`]. Takes effect while playing and is
+    /// remembered for the current song, see [`Player::save_song_settings`].
+    pub fn set_speed(&mut self, speed: f64) {
+        self.update_song_settings(|settings| settings.set_speed(speed));
+    }
+
+    /// Transpose non-drum channels, see [`SongSettings::set_transpose`]. Takes effect while
+    /// playing and is remembered for the current song, see [`Player::save_song_settings`].
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.update_song_settings(|settings| settings.set_transpose(semitones));
+    }
+
+    /// Play the section between `start` and `end` over and over. Takes effect while playing and
+    /// is remembered for the current song, see [`Player::save_song_settings`]. Loops past the end
+    /// of the song end with it.
+    pub fn set_loop(&mut self, start: LoopPoint, end: LoopPoint) -> Result<(), PlayerError> {
+        let Some(metadata) = &self.metadata else {
+            return Err(PlayerError::NoMidi);
+        };
+        let tempo_map = metadata.tempo_map();
+        let to_time = |point| match point {
+            LoopPoint::Time(time) => time,
+            LoopPoint::BarBeat(position) => tempo_map.bar_beat_to_time(position),
+        };
+        let region = LoopRegion {
+            start: to_time(start),
+            end: to_time(end).min(self.midi_length.unwrap_or(Duration::MAX)),
+        };
+        if region.start >= region.end {
+            return Err(PlayerError::EmptyLoop);
+        }
+        self.update_song_settings(|settings| settings.set_loop_region(Some(region)));
+        Ok(())
+    }
+
+    /// Play on past the loop region.
+    pub fn clear_loop(&mut self) {
+        self.update_song_settings(|settings| settings.set_loop_region(None));
+    }
+
+    pub fn repeat_one(&self) -> bool {
+        self.repeat_one.load(Ordering::Relaxed)
+    }
+
+    /// Start the song over when it ends. Takes effect while playing.
+    pub fn set_repeat_one(&self, value: bool) {
+        self.repeat_one.store(value, Ordering::Relaxed);
+    }
+
+    /// Start the current song over from where it was, to pick up a new synthesizer setup.
+    fn restart_playback(&mut self) {
+        if let Some(output) = &self.output
+            && !output.empty()
+        {
+            let position = self.position.get();
+            let paused = output.is_paused();
+            output.clear();
+            let _ = self.start_playback();
+            let _ = self.seek_to(position);
+            if paused {
+                let _ = self.pause();
             }
         }
     }
+
+    /// Change the settings of the current song and remember them. The file is written later, see
+    /// [`Player::save_song_settings`].
+    fn update_song_settings<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut SongSettings),
+    {
+        let settings = {
+            // The audio thread only `try_lock`s, so it's held as short as possible.
+            let mut settings = self
+                .song_settings
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            f(&mut settings);
+            *settings
+        };
+        if let Some(key) = &self.midi_key {
+            self.remembered_settings.insert(key, settings);
+        }
+    }
+
+    fn update_mixer<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Mixer) -> T,
+    {
+        // The audio thread never panics while holding the lock, only `try_lock`s it.
+        let mut mixer = self.mixer.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut mixer)
+    }
 }
 
 impl Player {
     /// Unpause
     pub fn play(&self) -> Result<(), PlayerError> {
-        let Some(sink) = &self.sink else {
+        let Some(output) = &self.output else {
             return Err(PlayerError::NoSink);
         };
-        sink.play();
+        output.play();
         Ok(())
     }
 
     /// Pause
     pub fn pause(&self) -> Result<(), PlayerError> {
-        let Some(sink) = &self.sink else {
+        let Some(output) = &self.output else {
             return Err(PlayerError::NoSink);
         };
-        sink.pause();
+        output.pause();
         Ok(())
     }
 
@@ -67,41 +403,70 @@ impl Player {
         let Some(midi_file) = self.midi_file.clone() else {
             return Err(PlayerError::NoMidi);
         };
-        let Some(sink) = &self.sink else {
+        let Some(output) = &self.output else {
             return Err(PlayerError::NoSink);
         };
-        let source = MidiSource::new(soundfont, midi_file);
+        let mut source = MidiSource::new(soundfont, midi_file)?;
+        source.set_channel_overrides(&self.channel_overrides)?;
+        source.set_retrigger_on_seek(self.retrigger_on_seek);
+        source.set_mixer(self.mixer.clone());
+        source.set_song_settings(self.song_settings.clone());
+        source.set_repeat(self.repeat_one.clone());
+        source.set_shared_position(self.position.clone());
+        source.set_event_sender(self.events.clone());
         self.midi_duration = Some(source.song_length());
 
-        sink.append(source);
-        sink.play();
+        output.append(Box::new(source));
+        output.play();
         Ok(())
     }
 
     /// Full stop.
     pub fn stop_playback(&mut self) -> Result<(), PlayerError> {
-        let Some(sink) = &self.sink else {
+        let Some(output) = &self.output else {
             return Err(PlayerError::NoSink);
         };
         self.midi_duration = None;
-        sink.clear();
-        sink.pause();
+        output.clear();
         Ok(())
     }
 
     pub fn seek_to(&self, position: Duration) -> Result<(), PlayerError> {
-        let Some(sink) = &self.sink else {
+        let Some(output) = &self.output else {
             return Err(PlayerError::NoSink);
         };
-        let _ = sink.try_seek(position);
-        Ok(())
+        output.try_seek(position)
     }
 }
 
-#[derive(Debug, Display)]
-pub enum PlayerError {
-    NoSink,
-    NoFont,
-    NoMidi,
-    NoDevice,
+#[test]
+fn test_set_loop_ends_with_the_song() {
+    // A note of two beats, 1s at 120 BPM
+    let track = [
+        0x00, 0x90, 60, 100, 0x87, 0x40, 0x80, 60, 0, 0x00, 0xff, 0x2f, 0x00,
+    ];
+    let bytes = [
+        b"MThd".as_slice(),
+        &[0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xe0],
+        b"MTrk",
+        &(track.len() as u32).to_be_bytes(),
+        &track,
+    ]
+    .concat();
+    let mut player = Player::default();
+    player.set_song(loader::parse_midi(&bytes, "note.mid").unwrap());
+
+    let time = |millis| LoopPoint::Time(Duration::from_millis(millis));
+    player.set_loop(time(500), time(3000)).unwrap();
+    assert_eq!(
+        player.song_settings().loop_region(),
+        Some(LoopRegion {
+            start: Duration::from_millis(500),
+            end: Duration::from_secs(1),
+        })
+    );
+    assert!(matches!(
+        player.set_loop(time(1000), time(3000)),
+        Err(PlayerError::EmptyLoop)
+    ));
 }
@@ -0,0 +1,377 @@
+//! Loading MIDI files and SoundFonts: Parsing a big SoundFont takes a while, so these functions
+//! don't touch the [`Player`](super::Player) and can run on any thread, for example with
+//! `tokio::task::spawn_blocking`. The result is then handed over with [`Player::set_song`] or
+//! [`Player::set_soundfont`].
+//!
+//! [`Player::set_song`]: super::Player::set_song
+//! [`Player::set_soundfont`]: super::Player::set_soundfont
+
+use midi_msg::{Meta, MidiFile, MidiMsg};
+use rustysynth::SoundFont;
+use std::{path::Path, sync::Arc, time::Duration};
+
+use super::{
+    error::PlayerError,
+    midi_sequencer::{MidiSequencer, split_sequences},
+};
+
+/// A parsed MIDI file and the name it is shown as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Song {
+    pub name: String,
+    /// Identifies the song for its remembered [`SongSettings`](super::SongSettings): The
+    /// canonical path of its file, or its name if it was parsed from bytes. Sequences add their
+    /// number.
+    pub key: String,
+    pub midi_file: MidiFile,
+}
+
+impl Song {
+    /// The songs to list for this file: One per track of a format 2 file, which holds
+    /// independent sequences, or just `self` for formats 0 and 1.
+    ///
+    /// Sequences are named after the file, numbered from 1, and followed by their track name if
+    /// they have one.
+    pub fn into_sequences(self) -> Vec<Song> {
+        let mut sequences = split_sequences(self.midi_file);
+        if sequences.len() == 1 {
+            let midi_file = sequences.swap_remove(0);
+            return vec![Song {
+                name: self.name,
+                key: self.key,
+                midi_file,
+            }];
+        }
+        sequences
+            .into_iter()
+            .enumerate()
+            .map(|(idx, midi_file)| {
+                let number = idx + 1;
+                let name = match track_name(&midi_file) {
+                    Some(track_name) => format!("{} #{number}: {track_name}", self.name),
+                    None => format!("{} #{number}", self.name),
+                };
+                Song {
+                    name,
+                    key: format!("{} #{number}", self.key),
+                    midi_file,
+                }
+            })
+            .collect()
+    }
+
+    /// Time from the start to the last event, as played. Only the first sequence of a format 2
+    /// file counts, see [`Self::into_sequences`].
+    pub fn length(&self) -> Duration {
+        let mut sequencer = MidiSequencer::new();
+        sequencer.play(self.midi_file.clone());
+        sequencer.song_length()
+    }
+}
+
+/// The first non-empty track name meta event of the first track.
+fn track_name(midi_file: &MidiFile) -> Option<&str> {
+    midi_file
+        .tracks
+        .first()?
+        .events()
+        .iter()
+        .find_map(|event| match &event.event {
+            MidiMsg::Meta {
+                msg: Meta::TrackName(name),
+            } if !name.trim().is_empty() => Some(name.trim()),
+            _ => None,
+        })
+}
+
+/// Extensions of the MIDI files the loader reads, lowercase: Standard MIDI files, karaoke files
+/// and RIFF MIDI files.
+pub const MIDI_EXTENSIONS: [&str; 4] = ["mid", "midi", "kar", "rmi"];
+
+/// Is `path` a MIDI file, judging by its extension?
+pub fn is_midi<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    path.as_ref().extension().is_some_and(|extension| {
+        MIDI_EXTENSIONS
+            .iter()
+            .any(|midi| extension.eq_ignore_ascii_case(midi))
+    })
+}
+
+/// Summary of a MIDI file, see [`read_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiInfo {
+    /// Tracks of the file, which are the sequences of a format 2 file
+    pub tracks: usize,
+    /// Of all sequences, one after the other
+    pub duration: Duration,
+}
+
+/// Read the MIDI file at `path` for its [`MidiInfo`].
+pub fn read_info<P>(path: P) -> Result<MidiInfo, PlayerError>
+where
+    P: AsRef<Path>,
+{
+    let song = read_midi(path)?;
+    let tracks = song.midi_file.tracks.len();
+    let duration = song.into_sequences().iter().map(Song::length).sum();
+    Ok(MidiInfo { tracks, duration })
+}
+
+/// Read and parse the MIDI file at `path`, named after the file.
+pub fn read_midi<P>(path: P) -> Result<Song, PlayerError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|source| PlayerError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let midi_file = MidiFile::from_midi(unwrap_rmid(&bytes)).map_err(|err| PlayerError::Midi {
+        path: Some(path.to_path_buf()),
+        offset: err.offset,
+        source: err.error,
+    })?;
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    Ok(Song {
+        name: name.to_string_lossy().into_owned(),
+        key: canonical.to_string_lossy().into_owned(),
+        midi_file,
+    })
+}
+
+/// Parse MIDI file `bytes`, named `name`.
+pub fn parse_midi(bytes: &[u8], name: &str) -> Result<Song, PlayerError> {
+    let midi_file = MidiFile::from_midi(unwrap_rmid(bytes)).map_err(|err| PlayerError::Midi {
+        path: None,
+        offset: err.offset,
+        source: err.error,
+    })?;
+    Ok(Song {
+        name: name.to_string(),
+        key: name.to_string(),
+        midi_file,
+    })
+}
+
+/// The Standard MIDI file in `bytes`: Unwrapped from the `data` chunk of a RIFF MIDI file, else
+/// `bytes` as they are. Error offsets are relative to the unwrapped data.
+fn unwrap_rmid(bytes: &[u8]) -> &[u8] {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"RMID" {
+        return bytes;
+    }
+    let mut chunks = &bytes[12..];
+    while chunks.len() >= 8 {
+        let size = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+        let data = &chunks[8..];
+        let size = size.min(data.len());
+        if &chunks[..4] == b"data" {
+            return &data[..size];
+        }
+        // Chunks are padded to an even size.
+        chunks = &data[(size + size % 2).min(data.len())..];
+    }
+    bytes
+}
+
+/// Read and parse the SoundFont at `path`.
+pub fn read_soundfont<P>(path: P) -> Result<Arc<SoundFont>, PlayerError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file = std::fs::File::open(path).map_err(|source| PlayerError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let soundfont = SoundFont::new(&mut std::io::BufReader::new(file)).map_err(|source| {
+        PlayerError::SoundFont {
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    })?;
+    Ok(Arc::new(soundfont))
+}
+
+/// Parse SoundFont `bytes`.
+pub fn parse_soundfont(mut bytes: &[u8]) -> Result<Arc<SoundFont>, PlayerError> {
+    let soundfont = SoundFont::new(&mut bytes)
+        .map_err(|source| PlayerError::SoundFont { path: None, source })?;
+    Ok(Arc::new(soundfont))
+}
+
+/// A SoundFont with a preset per `(bank, program, audible)`: Audible presets play a square wave
+/// with a release of a second, the others silence.
+#[cfg(test)]
+pub(crate) fn test_soundfont(presets: &[(u16, u8, bool)]) -> Arc<SoundFont> {
+    const INSTRUMENT: u16 = 41;
+    const RELEASE_VOL_ENV: u16 = 38;
+    const SAMPLE_ID: u16 = 53;
+    const SAMPLE_MODES: u16 = 54;
+    const PERIOD: i32 = 100;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        [id, &(data.len() as u32).to_le_bytes(), data].concat()
+    }
+    fn list(kind: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        chunk(b"LIST", &[kind, &chunks.concat()].concat())
+    }
+    fn name(name: &str) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+    let u16s =
+        |values: &[u16]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+
+    // A silent and a square wave period, each followed by the padding the spec asks for.
+    let mut samples = vec![0i16; PERIOD as usize + 46];
+    samples.extend((0..PERIOD).map(|idx| if idx < PERIOD / 2 { 8000 } else { -8000 }));
+    samples.extend([0; 46]);
+    let smpl: Vec<u8> = samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect();
+
+    let mut phdr = vec![];
+    let mut pbag = vec![];
+    let mut pgen = vec![];
+    for (idx, &(bank, program, audible)) in presets.iter().enumerate() {
+        phdr.extend(name(&format!("Preset {idx}")));
+        phdr.extend(u16s(&[program.into(), bank, idx as u16]));
+        phdr.extend([0; 12]);
+        pbag.extend(u16s(&[idx as u16, 0]));
+        pgen.extend(u16s(&[INSTRUMENT, audible.into()]));
+    }
+    phdr.extend(name("EOP"));
+    phdr.extend(u16s(&[0, 0, presets.len() as u16]));
+    phdr.extend([0; 12]);
+    pbag.extend(u16s(&[presets.len() as u16, 0]));
+    pgen.extend([0; 4]);
+
+    let mut inst = vec![];
+    let mut igen = vec![];
+    for (idx, instrument) in ["Silence", "Square"].into_iter().enumerate() {
+        inst.extend(name(instrument));
+        inst.extend(u16s(&[idx as u16]));
+        igen.extend(u16s(&[
+            RELEASE_VOL_ENV,
+            0,
+            SAMPLE_MODES,
+            1,
+            SAMPLE_ID,
+            idx as u16,
+        ]));
+    }
+    inst.extend(name("EOI"));
+    inst.extend(u16s(&[2]));
+    igen.extend([0; 4]);
+    let ibag = u16s(&[0, 0, 3, 0, 6, 0]);
+
+    let mut shdr = vec![];
+    for (idx, sample) in ["Silence", "Square"].into_iter().enumerate() {
+        let start = idx as i32 * (PERIOD + 46);
+        shdr.extend(name(sample));
+        for value in [start, start + PERIOD, start, start + PERIOD, 44100] {
+            shdr.extend(value.to_le_bytes());
+        }
+        shdr.extend([60, 0, 0, 0, 1, 0]);
+    }
+    shdr.extend([0; 46]);
+
+    let body = [
+        b"sfbk".to_vec(),
+        list(b"INFO", &[chunk(b"ifil", &u16s(&[2, 1]))]),
+        list(b"sdta", &[chunk(b"smpl", &smpl)]),
+        list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &phdr),
+                chunk(b"pbag", &pbag),
+                chunk(b"pgen", &pgen),
+                chunk(b"inst", &inst),
+                chunk(b"ibag", &ibag),
+                chunk(b"igen", &igen),
+                chunk(b"shdr", &shdr),
+            ],
+        ),
+    ]
+    .concat();
+    parse_soundfont(&chunk(b"RIFF", &body)).unwrap()
+}
+
+#[test]
+fn test_parse_midi_error_offset() {
+    // Valid header chunk, followed by a track chunk that ends early.
+    let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60".to_vec();
+    bytes.extend_from_slice(b"MTrk\x00\x00\x00\x08\x00\x90");
+    let Err(PlayerError::Midi { path, offset, .. }) = parse_midi(&bytes, "broken.mid") else {
+        panic!("Expected a MIDI parse error");
+    };
+    assert_eq!(path, None);
+    assert!(offset >= 14, "offset {offset} should point into the track");
+}
+
+#[test]
+fn test_rmid() {
+    let smf = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk\x00\x00\x00\x04\x00\xff\x2f\x00";
+    let mut rmid = b"RIFF\x00\x00\x00\x00RMIDINFO\x01\x00\x00\x00x\x00data".to_vec();
+    rmid.extend_from_slice(&(smf.len() as u32).to_le_bytes());
+    rmid.extend_from_slice(smf);
+    let song = parse_midi(&rmid, "song.rmi").unwrap();
+    assert_eq!(song.midi_file.tracks.len(), 1);
+    assert!(is_midi("Song.RMI") && is_midi("song.kar") && !is_midi("song.wav"));
+}
+
+#[test]
+fn test_format_2_sequences() {
+    let track = |name: &[u8], note: u8| {
+        let mut events = vec![0x00, 0xff, 0x03, name.len() as u8];
+        events.extend_from_slice(name);
+        events.extend_from_slice(&[0x00, 0x90, note, 0x40, 0x60, 0x80, note, 0x00]);
+        events.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+        [
+            b"MTrk".as_slice(),
+            &(events.len() as u32).to_be_bytes(),
+            &events,
+        ]
+        .concat()
+    };
+    let header =
+        |format: u8| [b"MThd".as_slice(), &[0, 0, 0, 6, 0, format, 0, 2, 0, 0x60]].concat();
+    let tracks = [track(b"Intro", 60), track(b"", 62)].concat();
+
+    let song = parse_midi(&[header(2), tracks.clone()].concat(), "song.mid").unwrap();
+    let sequences = song.into_sequences();
+    let names: Vec<_> = sequences.iter().map(|song| song.name.as_str()).collect();
+    assert_eq!(names, ["song.mid #1: Intro", "song.mid #2"]);
+    assert_eq!(sequences[1].key, "song.mid #2");
+    // A beat at 120 BPM
+    assert_eq!(sequences[0].length(), Duration::from_millis(500));
+    assert!(
+        sequences
+            .iter()
+            .all(|song| song.midi_file.tracks.len() == 1)
+    );
+
+    // The tracks of a format 1 file play together.
+    let song = parse_midi(&[header(1), tracks].concat(), "song.mid").unwrap();
+    let sequences = song.clone().into_sequences();
+    assert_eq!(sequences, [song]);
+}
+
+#[test]
+fn test_test_soundfont() {
+    let soundfont = test_soundfont(&[(0, 0, false), (128, 25, true)]);
+    let presets: Vec<_> = soundfont
+        .get_presets()
+        .iter()
+        .map(|preset| (preset.get_bank_number(), preset.get_patch_number()))
+        .collect();
+    assert_eq!(presets, [(0, 0), (128, 25)]);
+    assert_eq!(soundfont.get_instruments().len(), 2);
+}
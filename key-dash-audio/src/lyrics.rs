@@ -0,0 +1,223 @@
+//! Lyrics: Timed lines of syllables for karaoke display.
+//!
+//! Lyrics come from [`Meta::Lyric`](midi_msg::Meta::Lyric) events, or from the text events of
+//! .kar files when there are none. Both may use the KAR conventions: A syllable starting with `/`
+//! starts a new line, one starting with `\` a new paragraph, and text events starting with `@`
+//! are headers, `@T` for the title and author. Line ends of RP-026 (CR, LF) are understood too.
+
+use std::time::Duration;
+
+use super::metadata::{MetaEvent, MetaKind};
+
+/// A piece of text sung from `time`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Syllable {
+    pub time: Duration,
+    pub text: String,
+}
+
+/// A line of lyrics, as shown on one row.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LyricLine {
+    /// Whether the line starts a new verse or paragraph
+    pub paragraph: bool,
+    pub syllables: Vec<Syllable>,
+}
+
+impl LyricLine {
+    /// When the first syllable is sung.
+    pub fn time(&self) -> Option<Duration> {
+        self.syllables.first().map(|syllable| syllable.time)
+    }
+
+    pub fn text(&self) -> String {
+        self.syllables
+            .iter()
+            .map(|syllable| syllable.text.as_str())
+            .collect()
+    }
+}
+
+/// Position in [`Lyrics::lines`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LyricPosition {
+    pub line: usize,
+    pub syllable: usize,
+}
+
+/// Lyrics of a song, empty if it has none.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lyrics {
+    /// `@T` headers of a .kar file: Title, then author and other credits
+    pub headers: Vec<String>,
+    /// Ordered by time, every line has at least one syllable.
+    pub lines: Vec<LyricLine>,
+}
+
+impl Lyrics {
+    /// Collect lyrics from `events`, ordered by time.
+    pub fn new(events: &[MetaEvent]) -> Self {
+        let mut lyrics = Self::default();
+        let has_lyric_events = events
+            .iter()
+            .any(|event| matches!(&event.kind, MetaKind::Lyric(text) if !text.trim().is_empty()));
+        let words_track = Self::words_track(events);
+
+        let mut line = LyricLine::default();
+        for event in events {
+            let text = match &event.kind {
+                MetaKind::Lyric(text) if has_lyric_events => text,
+                MetaKind::Text(text) => {
+                    if let Some(header) = text.strip_prefix("@T") {
+                        lyrics.headers.push(header.trim().to_string());
+                    }
+                    if has_lyric_events || Some(event.track) != words_track || text.starts_with('@')
+                    {
+                        continue;
+                    }
+                    text
+                }
+                _ => continue,
+            };
+
+            let (break_before, text) = match text.as_bytes().first() {
+                Some(b'\\') => (Some(true), &text[1..]),
+                Some(b'/') => (Some(false), &text[1..]),
+                _ => (None, text.as_str()),
+            };
+            if let Some(paragraph) = break_before {
+                lyrics.push_line(std::mem::take(&mut line));
+                line.paragraph = paragraph;
+            }
+            let break_after = text.ends_with(['\r', '\n']);
+            let paragraph_after = text.ends_with('\n');
+            let text: String = text.chars().filter(|c| !c.is_control()).collect();
+            if !text.is_empty() {
+                line.syllables.push(Syllable {
+                    time: event.time,
+                    text,
+                });
+            }
+            if break_after {
+                lyrics.push_line(std::mem::take(&mut line));
+                line.paragraph = paragraph_after;
+            }
+        }
+        lyrics.push_line(line);
+        lyrics
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The syllable being sung at `time`, `None` before the first one.
+    pub fn position_at(&self, time: Duration) -> Option<LyricPosition> {
+        let line = self
+            .lines
+            .partition_point(|line| line.time().is_some_and(|start| start <= time))
+            .checked_sub(1)?;
+        let syllable = self.lines[line]
+            .syllables
+            .partition_point(|syllable| syllable.time <= time)
+            .saturating_sub(1);
+        Some(LyricPosition { line, syllable })
+    }
+
+    /// The track of a .kar file holding the words: The one with the most text events, if the file
+    /// has a KAR header.
+    fn words_track(events: &[MetaEvent]) -> Option<usize> {
+        let is_kar = events
+            .iter()
+            .any(|event| matches!(&event.kind, MetaKind::Text(text) if text.starts_with("@K")));
+        if !is_kar {
+            return None;
+        }
+        let mut counts = std::collections::BTreeMap::new();
+        for event in events {
+            if let MetaKind::Text(text) = &event.kind
+                && !text.starts_with('@')
+            {
+                *counts.entry(event.track).or_insert(0) += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(track, _)| track)
+    }
+
+    fn push_line(&mut self, line: LyricLine) {
+        if !line.syllables.is_empty() {
+            self.lines.push(line);
+        }
+    }
+}
+
+#[test]
+fn test_kar_lyrics() {
+    let event = |millis, track, kind| MetaEvent {
+        time: Duration::from_millis(millis),
+        track,
+        kind,
+    };
+    let text = |text: &str| MetaKind::Text(text.to_string());
+    let events = [
+        event(0, 0, text("@KMIDI KARAOKE FILE")),
+        event(0, 0, text("Piano")),
+        event(0, 2, text("@TTwinkle Twinkle")),
+        event(0, 2, text("@TTraditional")),
+        event(1000, 2, text("\\Twin")),
+        event(1500, 2, text("kle ")),
+        event(2000, 2, text("star")),
+        event(3000, 2, text("/How ")),
+        event(3500, 2, text("I")),
+    ];
+    let lyrics = Lyrics::new(&events);
+    assert_eq!(lyrics.headers, ["Twinkle Twinkle", "Traditional"]);
+    let lines: Vec<_> = lyrics.lines.iter().map(LyricLine::text).collect();
+    assert_eq!(lines, ["Twinkle star", "How I"]);
+    assert!(lyrics.lines[0].paragraph);
+    assert!(!lyrics.lines[1].paragraph);
+
+    assert_eq!(lyrics.position_at(Duration::from_millis(999)), None);
+    assert_eq!(
+        lyrics.position_at(Duration::from_millis(1700)),
+        Some(LyricPosition {
+            line: 0,
+            syllable: 1
+        })
+    );
+    assert_eq!(
+        lyrics.position_at(Duration::from_secs(10)),
+        Some(LyricPosition {
+            line: 1,
+            syllable: 1
+        })
+    );
+}
+
+#[test]
+fn test_lyric_events() {
+    let lyric = |millis, text: &str| MetaEvent {
+        time: Duration::from_millis(millis),
+        track: 1,
+        kind: MetaKind::Lyric(text.to_string()),
+    };
+    // Text events are ignored when there are lyric events.
+    let events = [
+        MetaEvent {
+            time: Duration::ZERO,
+            track: 0,
+            kind: MetaKind::Text("Comment".to_string()),
+        },
+        lyric(0, "Hel"),
+        lyric(500, "lo\r"),
+        lyric(1000, "world\n"),
+        lyric(1500, "again"),
+    ];
+    let lyrics = Lyrics::new(&events);
+    let lines: Vec<_> = lyrics.lines.iter().map(LyricLine::text).collect();
+    assert_eq!(lines, ["Hello", "world", "again"]);
+    assert!(lyrics.lines[2].paragraph);
+}
@@ -0,0 +1,305 @@
+//! M3U playlists: [`PlaylistEntry`]s as M3U or M3U8 files, with `#EXTINF` titles and durations.
+//!
+//! Paths are relative to the playlist file unless they are absolute. M3U8 files are UTF-8, plain
+//! M3U files are read as UTF-8 if they are valid, else as Latin-1.
+
+use std::{
+    fmt::Write as _,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use super::{error::PlayerError, loader, playlist::PlaylistEntry};
+
+/// Is `path` an M3U or M3U8 playlist, judging by its extension?
+pub fn is_playlist<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    path.as_ref().extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("m3u") || extension.eq_ignore_ascii_case("m3u8")
+    })
+}
+
+/// Read the entries of the playlist at `path`.
+pub fn read_m3u<P>(path: P) -> Result<Vec<PlaylistEntry>, PlayerError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|source| PlayerError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let text = String::from_utf8(bytes)
+        .unwrap_or_else(|err| err.into_bytes().into_iter().map(char::from).collect());
+    Ok(parse_m3u(&text, path.parent().unwrap_or(Path::new(""))))
+}
+
+/// Parse the entries of playlist `text`, with relative paths resolved against `base`.
+///
+/// Directives other than `#EXTINF` and URLs other than `file://` ones are skipped.
+pub fn parse_m3u(text: &str, base: &Path) -> Vec<PlaylistEntry> {
+    let mut entries = vec![];
+    let mut info: Option<(Option<Duration>, String)> = None;
+    for line in text.trim_start_matches('\u{feff}').lines() {
+        let line = line.trim();
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = Some(parse_extinf(extinf));
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let location = line.strip_prefix("file://").unwrap_or(line);
+        if location.contains("://") {
+            info = None;
+            continue;
+        }
+        let mut entry = PlaylistEntry::new(normalize(&base.join(location)));
+        if let Some((duration, title)) = info.take() {
+            entry.duration = duration;
+            if !title.is_empty() {
+                entry.title = title;
+            }
+        }
+        entries.push(entry);
+    }
+    entries
+}
+
+/// `path` without `.` and with `..` applied to the directory before it, as written by
+/// [`write_m3u`].
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Duration and title of `#EXTINF:<seconds> [attributes],<title>`, -1 seconds if unknown.
+fn parse_extinf(extinf: &str) -> (Option<Duration>, String) {
+    let (seconds, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+    let seconds = seconds.split_whitespace().next().unwrap_or_default();
+    let duration = seconds
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.)
+        .map(Duration::from_secs_f64);
+    (duration, title.trim().to_string())
+}
+
+/// Write `entries` into the playlist at `path`. Durations that aren't known yet are computed
+/// from the MIDI files.
+///
+/// Paths are written relative to the playlist, going up with `..` where needed, and absolute with
+/// `absolute` or if they are on another drive.
+pub fn write_m3u<P>(path: P, entries: &[PlaylistEntry], absolute: bool) -> Result<(), PlayerError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let write_error = |source| PlayerError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut entries = merge_sequences(entries);
+    for entry in &mut entries {
+        if entry.duration.is_none() {
+            entry.duration = loader::read_info(&entry.path)
+                .ok()
+                .map(|info| info.duration);
+        }
+    }
+    let base = if absolute {
+        None
+    } else {
+        let path = std::path::absolute(path).map_err(write_error)?;
+        path.parent().map(Path::to_path_buf)
+    };
+    let text = format_m3u(&entries, base.as_deref());
+    std::fs::write(path, text).map_err(write_error)
+}
+
+/// Playlist text of `entries`, with paths relative to `base` where they can be, see
+/// [`relative_path`].
+pub fn format_m3u(entries: &[PlaylistEntry], base: Option<&Path>) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for entry in entries {
+        let seconds = entry
+            .duration
+            .map_or(-1, |duration| duration.as_secs_f64().round() as i64);
+        let path = std::path::absolute(&entry.path).unwrap_or_else(|_| entry.path.clone());
+        let path = base
+            .and_then(|base| relative_path(&path, base))
+            .unwrap_or(path);
+        // Titles can't span lines.
+        let title = entry.title.replace(['\r', '\n'], " ");
+        let _ = writeln!(text, "#EXTINF:{seconds},{title}");
+        let _ = writeln!(text, "{}", path.display());
+    }
+    text
+}
+
+/// Absolute `path` relative to the absolute directory `base`, going up with `..` where needed.
+/// `None` if they don't share a root, like paths on different drives.
+fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    let is_root =
+        |component: &Component| matches!(component, Component::Prefix(_) | Component::RootDir);
+    // Roots must match, the rest is shared as far as it goes.
+    while let Some(component) = base_components.peek() {
+        if path_components.peek() != Some(component) {
+            if is_root(component) || path_components.peek().is_some_and(is_root) {
+                return None;
+            }
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(path_components);
+    Some(relative)
+}
+
+/// One entry per file: The sequences of a format 2 file are listed again when it's loaded.
+fn merge_sequences(entries: &[PlaylistEntry]) -> Vec<PlaylistEntry> {
+    let mut merged: Vec<PlaylistEntry> = vec![];
+    for entry in entries {
+        let Some(sequence) = entry.sequence else {
+            merged.push(entry.clone());
+            continue;
+        };
+        match merged.last_mut() {
+            // The file plays its sequences one after the other.
+            Some(last) if sequence > 0 && last.path == entry.path => {
+                last.duration = last.duration.zip(entry.duration).map(|(a, b)| a + b);
+            }
+            _ => merged.push(PlaylistEntry {
+                duration: entry.duration,
+                ..PlaylistEntry::new(entry.path.clone())
+            }),
+        }
+    }
+    merged
+}
+
+#[test]
+fn test_parse_m3u() {
+    let text = "\u{feff}#EXTM3U\r\n\
+        #EXTINF:123 tvg-id=\"x\",Artist - Song\r\n\
+        song.mid\r\n\
+        \r\n\
+        # A comment\n\
+        /music/other.mid\n\
+        #EXTINF:-1,\n\
+        http://example.com/stream.mid\n\
+        file:///music/third.mid\n";
+    let entries = parse_m3u(text, Path::new("/lists"));
+    let summary: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.path.to_str().unwrap(),
+                entry.title.as_str(),
+                entry.duration,
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (
+                "/lists/song.mid",
+                "Artist - Song",
+                Some(Duration::from_secs(123))
+            ),
+            ("/music/other.mid", "other.mid", None),
+            ("/music/third.mid", "third.mid", None),
+        ]
+    );
+}
+
+#[test]
+fn test_format_m3u() {
+    use std::path::PathBuf;
+
+    let sequence = |sequence, seconds| PlaylistEntry {
+        sequence: Some(sequence),
+        title: format!("medley.mid #{}", sequence + 1),
+        duration: Some(Duration::from_secs(seconds)),
+        ..PlaylistEntry::new("/lists/medley.mid".into())
+    };
+    let entries = [
+        sequence(0, 10),
+        sequence(1, 20),
+        PlaylistEntry {
+            duration: Some(Duration::from_millis(61_600)),
+            ..PlaylistEntry::new("/music/song.mid".into())
+        },
+    ];
+    let text = format_m3u(&merge_sequences(&entries), Some(Path::new("/lists")));
+    assert_eq!(
+        text,
+        "#EXTM3U\n\
+        #EXTINF:30,medley.mid\n\
+        medley.mid\n\
+        #EXTINF:62,song.mid\n\
+        ../music/song.mid\n"
+    );
+    let paths: Vec<_> = parse_m3u(&text, Path::new("/lists"))
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            PathBuf::from("/lists/medley.mid"),
+            PathBuf::from("/music/song.mid")
+        ]
+    );
+}
+
+#[test]
+fn test_relative_path() {
+    let relative = |path, base| relative_path(Path::new(path), Path::new(base));
+    assert_eq!(relative("/a/b/song.mid", "/a/b"), Some("song.mid".into()));
+    assert_eq!(
+        relative("/a/b/c/song.mid", "/a/b"),
+        Some("c/song.mid".into())
+    );
+    assert_eq!(
+        relative("/a/song.mid", "/a/b/c"),
+        Some("../../song.mid".into())
+    );
+    assert_eq!(relative("/x/song.mid", "/a"), Some("../x/song.mid".into()));
+    assert_eq!(relative("/a/b/song.mid", "/"), Some("a/b/song.mid".into()));
+    // No shared root
+    assert_eq!(relative("/a/song.mid", "a"), None);
+}
+
+#[test]
+fn test_normalize() {
+    assert_eq!(
+        normalize(Path::new("/a/./b/../../x/song.mid")),
+        Path::new("/x/song.mid")
+    );
+    assert_eq!(
+        normalize(Path::new("../song.mid")),
+        Path::new("../song.mid")
+    );
+}
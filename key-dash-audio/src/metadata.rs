@@ -0,0 +1,302 @@
+//! Song metadata: The texts, markers and signatures that meta events add to a [`MidiFile`].
+//!
+
+use midi_msg::{Meta, MidiFile, MidiMsg};
+use std::{fmt::Display, time::Duration};
+
+use super::{
+    lyrics::Lyrics,
+    tempo_map::{BarBeat, TempoMap},
+};
+
+/// Time signature as it would be notated, such as 6/8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    pub denominator: u16,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        let (numerator, denominator) = TempoMap::DEFAULT_TIME_SIGNATURE;
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+}
+
+impl Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Key signature: Number of sharps, or flats if negative, and the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySignature {
+    pub sharps: i8,
+    pub minor: bool,
+}
+
+impl Display for KeySignature {
+    /// The tonic in chord symbol notation, such as `Bb` for B♭ major or `F#m` for F♯ minor.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Circle of fifths from 7 flats to 7 sharps, major keys first.
+        const MAJOR: [&str; 15] = [
+            "Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#",
+        ];
+        const MINOR: [&str; 15] = [
+            "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#",
+        ];
+        let idx = (i16::from(self.sharps.clamp(-7, 7)) + 7) as usize;
+        if self.minor {
+            write!(f, "{}m", MINOR[idx])
+        } else {
+            write!(f, "{}", MAJOR[idx])
+        }
+    }
+}
+
+/// What a [`MetaEvent`] says.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaKind {
+    /// Any text, often credits or comments at the start of the song
+    Text(String),
+    Copyright(String),
+    /// Name of the track, or of the whole song in the first track
+    TrackName(String),
+    InstrumentName(String),
+    /// A syllable or word of the lyrics
+    Lyric(String),
+    /// Name of a section of the song, such as "Chorus"
+    Marker(String),
+    /// Something happening on stage or screen
+    CuePoint(String),
+    TimeSignature(TimeSignature),
+    KeySignature(KeySignature),
+}
+
+/// A meta event and when it happens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetaEvent {
+    /// Time from song start
+    pub time: Duration,
+    /// Index of the track the event is in
+    pub track: usize,
+    pub kind: MetaKind,
+}
+
+/// Metadata of a song, collected once when it is loaded.
+///
+/// Events are ordered by time, simultaneous events keep their track order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SongMetadata {
+    events: Vec<MetaEvent>,
+    tempo_map: TempoMap,
+    lyrics: Lyrics,
+}
+
+impl SongMetadata {
+    pub fn new(midi_file: &MidiFile) -> Self {
+        let tempo_map = TempoMap::new(midi_file);
+        let division = midi_file.header.division;
+
+        let mut events = vec![];
+        for (track, track_events) in midi_file.tracks.iter().enumerate() {
+            for event in track_events.events() {
+                let MidiMsg::Meta { msg } = &event.event else {
+                    continue;
+                };
+                let kind = match msg {
+                    Meta::Text(text) => MetaKind::Text(text.clone()),
+                    Meta::Copyright(text) => MetaKind::Copyright(text.clone()),
+                    Meta::TrackName(text) => MetaKind::TrackName(text.clone()),
+                    Meta::InstrumentName(text) => MetaKind::InstrumentName(text.clone()),
+                    Meta::Lyric(text) => MetaKind::Lyric(text.clone()),
+                    Meta::Marker(text) => MetaKind::Marker(text.clone()),
+                    Meta::CuePoint(text) => MetaKind::CuePoint(text.clone()),
+                    Meta::TimeSignature(signature) => MetaKind::TimeSignature(TimeSignature {
+                        numerator: signature.numerator,
+                        denominator: signature.denominator,
+                    }),
+                    Meta::KeySignature(signature) => MetaKind::KeySignature(KeySignature {
+                        sharps: signature.key,
+                        minor: signature.scale != 0,
+                    }),
+                    _ => continue,
+                };
+                let tick = f64::from(division.beat_or_frame_to_tick(event.beat_or_frame));
+                events.push(MetaEvent {
+                    time: tempo_map.tick_to_time(tick),
+                    track,
+                    kind,
+                });
+            }
+        }
+        // Stable: Simultaneous events keep their track and in-track order.
+        events.sort_by_key(|event| event.time);
+
+        let lyrics = Lyrics::new(&events);
+        Self {
+            events,
+            tempo_map,
+            lyrics,
+        }
+    }
+
+    pub fn events(&self) -> &[MetaEvent] {
+        &self.events
+    }
+
+    pub const fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// Name of the first track, which names the whole song in most files, or else the first
+    /// `@T` header of a .kar file.
+    pub fn title(&self) -> Option<&str> {
+        self.find_text(|event| match &event.kind {
+            MetaKind::TrackName(name) if event.track == 0 => Some(name),
+            _ => None,
+        })
+        .or_else(|| self.kar_header(0))
+    }
+
+    /// The copyright notice, which usually credits the composer, or else the second `@T` header
+    /// of a .kar file, or else the first text of the first track.
+    pub fn composer(&self) -> Option<&str> {
+        self.find_text(|event| match &event.kind {
+            MetaKind::Copyright(text) => Some(text),
+            _ => None,
+        })
+        .or_else(|| self.kar_header(1))
+        .or_else(|| {
+            self.find_text(|event| match &event.kind {
+                MetaKind::Text(text) if event.track == 0 && !text.starts_with('@') => Some(text),
+                _ => None,
+            })
+        })
+    }
+
+    /// Markers and cue points, which divide the song into sections.
+    pub fn markers(&self) -> impl Iterator<Item = &MetaEvent> {
+        self.events
+            .iter()
+            .filter(|event| matches!(event.kind, MetaKind::Marker(_) | MetaKind::CuePoint(_)))
+    }
+
+    /// The last marker or cue point at or before `time`.
+    pub fn marker_at(&self, time: Duration) -> Option<&MetaEvent> {
+        self.markers().take_while(|event| event.time <= time).last()
+    }
+
+    /// Lyric lines for karaoke display, see [`Lyrics`].
+    pub const fn lyrics(&self) -> &Lyrics {
+        &self.lyrics
+    }
+
+    /// Time signature in effect at `time`.
+    pub fn time_signature_at(&self, time: Duration) -> TimeSignature {
+        self.last_before(time, |kind| match kind {
+            MetaKind::TimeSignature(signature) => Some(*signature),
+            _ => None,
+        })
+        .unwrap_or_default()
+    }
+
+    /// Key signature in effect at `time`, if the song has any.
+    pub fn key_signature_at(&self, time: Duration) -> Option<KeySignature> {
+        self.last_before(time, |kind| match kind {
+            MetaKind::KeySignature(signature) => Some(*signature),
+            _ => None,
+        })
+    }
+
+    /// Bar and beat at `time`, see [`TempoMap::time_to_bar_beat`].
+    pub fn bar_beat_at(&self, time: Duration) -> BarBeat {
+        self.tempo_map.time_to_bar_beat(time)
+    }
+
+    fn kar_header(&self, idx: usize) -> Option<&str> {
+        self.lyrics
+            .headers
+            .get(idx)
+            .map(String::as_str)
+            .filter(|header| !header.is_empty())
+    }
+
+    /// First non-empty text found by `f`, trimmed.
+    fn find_text<'a, F>(&'a self, f: F) -> Option<&'a str>
+    where
+        F: FnMut(&'a MetaEvent) -> Option<&'a String>,
+    {
+        self.events
+            .iter()
+            .filter_map(f)
+            .map(|text| text.trim())
+            .find(|text| !text.is_empty())
+    }
+
+    fn last_before<T, F>(&self, time: Duration, f: F) -> Option<T>
+    where
+        F: FnMut(&MetaKind) -> Option<T>,
+    {
+        self.events
+            .iter()
+            .take_while(|event| event.time <= time)
+            .map(|event| &event.kind)
+            .filter_map(f)
+            .last()
+    }
+}
+
+#[test]
+fn test_song_metadata() {
+    use midi_msg::{FileTimeSignature, Track};
+
+    let meta = |msg| MidiMsg::Meta { msg };
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    midi_file.add_track(Track::default());
+    midi_file.extend_track(0, meta(Meta::TrackName("Song".to_string())), 0.);
+    midi_file.extend_track(0, meta(Meta::Text("By Someone".to_string())), 0.);
+    midi_file.extend_track(1, meta(Meta::TrackName("Piano".to_string())), 0.);
+    midi_file.extend_track(0, meta(Meta::Marker("Verse".to_string())), 0.);
+    midi_file.extend_track(0, meta(Meta::Marker("Chorus".to_string())), 8.);
+    let time_signature = Meta::TimeSignature(FileTimeSignature {
+        numerator: 3,
+        denominator: 4,
+        clocks_per_metronome_tick: 24,
+        thirty_second_notes_per_24_clocks: 8,
+    });
+    midi_file.extend_track(0, meta(time_signature), 8.);
+    let key_signature = Meta::KeySignature(midi_msg::KeySignature { key: -2, scale: 0 });
+    midi_file.extend_track(1, meta(key_signature), 0.);
+
+    let metadata = SongMetadata::new(&midi_file);
+    assert_eq!(metadata.title(), Some("Song"));
+    assert_eq!(metadata.composer(), Some("By Someone"));
+    // At 120 BPM, beat 8 is at 4s.
+    let chorus = metadata.marker_at(Duration::from_secs(5)).unwrap();
+    assert_eq!(chorus.time, Duration::from_secs(4));
+    assert_eq!(chorus.kind, MetaKind::Marker("Chorus".to_string()));
+    assert_eq!(metadata.markers().count(), 2);
+
+    assert_eq!(
+        metadata.time_signature_at(Duration::ZERO).to_string(),
+        "4/4"
+    );
+    assert_eq!(
+        metadata
+            .time_signature_at(Duration::from_secs(4))
+            .to_string(),
+        "3/4"
+    );
+    let key = metadata.key_signature_at(Duration::ZERO).unwrap();
+    assert_eq!(key.to_string(), "Bb");
+    assert_eq!(
+        metadata.bar_beat_at(Duration::from_millis(4500)),
+        BarBeat { bar: 3, beat: 2. }
+    );
+}
@@ -1,6 +1,15 @@
-use midi_msg::{ChannelVoiceMsg, Division, Meta, MidiFile, MidiMsg, TimeCodeType, TrackEvent};
+use midi_msg::{Header, Meta, MidiFile, MidiMsg, SMFFormat, Track, TrackEvent};
 use std::{fmt::Display, time::Duration};
 
+use super::{
+    channel_state::{ChannelStates, channel_message, split_messages},
+    events::{EventSender, PlayerEvent},
+    song_settings::LoopRegion,
+    sysex::SysEx,
+    tempo_map::TempoMap,
+    transpose::Transposer,
+};
+
 /// Ability to receive messages
 pub trait MidiSink {
     /// Returns Err if event couldn't be used.
@@ -8,14 +17,31 @@ pub trait MidiSink {
     fn reset(&mut self);
 }
 
+/// Keeps the raw bytes of the messages it receives, until it's reset.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct Recorder(pub Vec<Vec<u8>>);
+
+#[cfg(test)]
+impl MidiSink for Recorder {
+    fn receive_midi(&mut self, msg: &MidiMsg) -> Result<(), ()> {
+        self.0.push(msg.to_midi());
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.0.clear();
+    }
+}
+
 /// [`TrackEvent`] wrapper with some context for debugging.
-struct TrackEventWrap {
-    track_event: TrackEvent,
+struct TrackEventWrap<'a> {
+    track_event: &'a TrackEvent,
     track_idx: usize,
     event_idx: usize,
 }
 
-impl Display for TrackEventWrap {
+impl Display for TrackEventWrap<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let trk = self.track_idx;
         let ev = self.event_idx;
@@ -25,28 +51,168 @@ impl Display for TrackEventWrap {
     }
 }
 
+/// Everything the sequencer sends passes through here: Notes are transposed, then reported to
+/// `events` along with program and controller changes. System exclusive messages update the drum
+/// channels and master tuning of the transposer.
+struct SequencerSink<'a, R> {
+    inner: &'a mut R,
+    transposer: &'a mut Transposer,
+    events: Option<&'a EventSender>,
+}
+
+impl<R> MidiSink for SequencerSink<'_, R>
+where
+    R: MidiSink,
+{
+    fn receive_midi(&mut self, msg: &MidiMsg) -> Result<(), ()> {
+        if let Some(sysex) = SysEx::from_midi(msg) {
+            self.transposer.apply_sysex(sysex);
+            match (sysex, self.events) {
+                (SysEx::Reset, Some(events)) => events.send(PlayerEvent::Reset),
+                (SysEx::DrumPart { channel, drums }, Some(events)) => {
+                    events.send(PlayerEvent::DrumPart { channel, drums });
+                }
+                _ => (),
+            }
+        }
+        if !(msg.is_channel_voice() || msg.is_channel_mode()) {
+            return self.inner.receive_midi(msg);
+        }
+
+        let mut result = Ok(());
+        for message in split_messages(&msg.to_midi()) {
+            let Some(message) = self.transposer.apply(message) else {
+                continue;
+            };
+            result = result.and(self.inner.receive_midi(&channel_message(message)));
+
+            let Some(events) = self.events else {
+                continue;
+            };
+            let [status, data1, data2] = message;
+            let channel = status & 0x0f;
+            match status & 0xf0 {
+                0x90 if data2 > 0 => events.send(PlayerEvent::NoteOn {
+                    channel,
+                    note: data1,
+                    velocity: data2,
+                }),
+                0x80 | 0x90 => events.send(PlayerEvent::NoteOff {
+                    channel,
+                    note: data1,
+                }),
+                0xb0 => events.send(PlayerEvent::ControlChange {
+                    channel,
+                    control: data1,
+                    value: data2,
+                }),
+                0xc0 => events.send(PlayerEvent::ProgramChange {
+                    channel,
+                    program: data1,
+                }),
+                _ => (),
+            }
+        }
+        result
+    }
+
+    fn reset(&mut self) {
+        self.transposer.reset();
+        self.inner.reset();
+        if let Some(events) = self.events {
+            events.send(PlayerEvent::Reset);
+        }
+    }
+}
+
+/// Split `midi_file` into the sequences it contains.
+///
+/// The tracks of a format 2 file are independent sequences, each one becomes a single track file.
+/// Formats 0 and 1 are a single sequence of simultaneous tracks and are returned as they are.
+pub(crate) fn split_sequences(midi_file: MidiFile) -> Vec<MidiFile> {
+    if midi_file.header.format != SMFFormat::MultiSong {
+        return vec![midi_file];
+    }
+    let division = midi_file.header.division;
+    let sequences: Vec<_> = midi_file
+        .tracks
+        .iter()
+        .filter(|track| matches!(track, Track::Midi(_)))
+        .map(|track| MidiFile {
+            header: Header {
+                format: SMFFormat::SingleTrack,
+                num_tracks: 1,
+                division,
+            },
+            tracks: vec![track.clone()],
+        })
+        .collect();
+    if sequences.is_empty() {
+        vec![midi_file]
+    } else {
+        sequences
+    }
+}
+
+/// Position of a [`TrackEvent`] in the merged event list of all tracks.
+#[derive(Debug, Clone, Copy)]
+struct ScheduledEvent {
+    tick: usize,
+    track_idx: usize,
+    event_idx: usize,
+}
+
+/// Channel states at a point of the song, so seeking doesn't need to replay from the start.
+#[derive(Debug)]
+struct Checkpoint {
+    /// Index of the first event in `schedule` not included in `channels`
+    next_event: usize,
+    position: Duration,
+    channels: ChannelStates,
+}
+
 /// MIDI Sequencer
 #[derive(Debug)]
 pub struct MidiSequencer {
     midi_file: Option<MidiFile>,
-    bpm: f64,
-    /// Index of next event for each track
-    track_positions: Vec<usize>,
-    /// Song position
-    tick: usize,
-    since_last_tick: Duration,
+    tempo_map: TempoMap,
+    /// Events of all tracks, ordered by tick
+    schedule: Vec<ScheduledEvent>,
+    /// Index of next event in `schedule`
+    next_event: usize,
+    /// Ordered by position, the first one is at the start of the song
+    checkpoints: Vec<Checkpoint>,
+    /// Start notes that are held at the seek target again
+    retrigger_on_seek: bool,
+    events: Option<EventSender>,
+    /// Factor of the song's tempo
+    speed: f64,
+    transposer: Transposer,
+    loop_region: Option<LoopRegion>,
+    /// Start over at the end of sequence
+    repeat: bool,
     song_length: Duration,
     song_position: Duration,
 }
 
 impl MidiSequencer {
+    const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+    /// Rounding allowed when comparing ticks converted from time.
+    const TICK_TOLERANCE: f64 = 1e-6;
+
     pub const fn new() -> Self {
         Self {
             midi_file: None,
-            bpm: 120.,
-            track_positions: vec![],
-            tick: 0,
-            since_last_tick: Duration::ZERO,
+            tempo_map: TempoMap::empty(),
+            schedule: vec![],
+            next_event: 0,
+            checkpoints: vec![],
+            retrigger_on_seek: false,
+            events: None,
+            speed: 1.,
+            transposer: Transposer::new(),
+            loop_region: None,
+            repeat: false,
             song_length: Duration::ZERO,
             song_position: Duration::ZERO,
         }
@@ -54,253 +220,223 @@ impl MidiSequencer {
 
     /// Are there no more messages left?
     pub fn end_of_sequence(&self) -> bool {
-        let Some(midi_file) = &self.midi_file else {
-            println!("bailed: no midi");
-            return true;
-        };
-        for (i, track) in midi_file.tracks.iter().enumerate() {
-            if self.track_positions[i] < track.events().len() {
-                return false;
-            }
-        }
-        println!("bailed: end reached");
-        for (i, track) in midi_file.tracks.iter().enumerate() {
-            println!(
-                "Track {i:02?} - len: {} position: {}",
-                track.events().len(),
-                self.track_positions[i]
-            );
-        }
-        true
+        self.next_event >= self.schedule.len()
     }
 
+    /// Play `midi_file` from the start. Only the first sequence of a format 2 file is played, see
+    /// [`split_sequences`] for the others.
     pub fn play(&mut self, midi_file: MidiFile) {
-        self.tick = 0;
-        self.track_positions = vec![0; midi_file.tracks.len()];
+        let midi_file = match midi_file.header.format {
+            SMFFormat::MultiSong => split_sequences(midi_file).swap_remove(0),
+            SMFFormat::SingleTrack | SMFFormat::MultiTrack => midi_file,
+        };
+        let division = midi_file.header.division;
+        let mut schedule = vec![];
+        for (track_idx, track) in midi_file.tracks.iter().enumerate() {
+            for (event_idx, event) in track.events().iter().enumerate() {
+                schedule.push(ScheduledEvent {
+                    tick: division.beat_or_frame_to_tick(event.beat_or_frame) as usize,
+                    track_idx,
+                    event_idx,
+                });
+            }
+        }
+        // Stable: Simultaneous events keep their track and in-track order.
+        schedule.sort_by_key(|event| event.tick);
+
+        self.tempo_map = TempoMap::new(&midi_file);
+        self.song_length = schedule.last().map_or(Duration::ZERO, |event| {
+            self.tempo_map.tick_to_time(event.tick as f64)
+        });
+        self.schedule = schedule;
+        self.next_event = 0;
+        self.song_position = Duration::ZERO;
         self.midi_file = Some(midi_file);
 
-        self.update_song_length();
+        self.update_checkpoints();
     }
 
-    pub fn update_events<R>(&mut self, event_sink: &mut R, delta: Duration)
-    where
-        R: MidiSink,
-    {
-        let Some(events) = self.events() else {
+    /// Take a [`Checkpoint`] every [`Self::CHECKPOINT_INTERVAL`] of song time.
+    fn update_checkpoints(&mut self) {
+        self.checkpoints.clear();
+        let Some(midi_file) = &self.midi_file else {
             return;
         };
 
-        self.song_position += delta;
-        self.since_last_tick += delta;
-        let tick_duration = self.current_tick_duration();
-        if self.since_last_tick >= tick_duration {
-            self.since_last_tick -= tick_duration;
-            self.tick += 1;
+        let mut channels = ChannelStates::default();
+        let mut next_position = Duration::ZERO;
+        for (idx, scheduled) in self.schedule.iter().enumerate() {
+            let position = self.tempo_map.tick_to_time(scheduled.tick as f64);
+            if position >= next_position {
+                self.checkpoints.push(Checkpoint {
+                    next_event: idx,
+                    position,
+                    channels: channels.clone(),
+                });
+                next_position = position + Self::CHECKPOINT_INTERVAL;
+            }
+            channels.apply(&Self::event(midi_file, scheduled).event);
         }
+    }
 
-        for wrap in events {
-            match wrap.track_event.event {
-                MidiMsg::ChannelVoice { .. }
-                | MidiMsg::RunningChannelVoice { .. }
-                | MidiMsg::ChannelMode { .. }
-                | MidiMsg::RunningChannelMode { .. } => {
-                    if event_sink.receive_midi(&wrap.track_event.event).is_err() {
-                        println!("Unhandled: {wrap}");
-                    }
-                }
+    fn event<'a>(midi_file: &'a MidiFile, scheduled: &ScheduledEvent) -> &'a TrackEvent {
+        &midi_file.tracks[scheduled.track_idx].events()[scheduled.event_idx]
+    }
 
-                midi_msg::MidiMsg::Meta { msg } => self.handle_meta_event(&msg),
-                _ => (),
-            }
-        }
+    /// Start notes that are held at the seek target again, instead of waiting for the next ones.
+    pub const fn set_retrigger_on_seek(&mut self, value: bool) {
+        self.retrigger_on_seek = value;
     }
 
-    /// For seeking. Ignore `NoteOn`.
-    fn update_events_quiet<R>(&mut self, event_sink: &mut R)
-    where
-        R: MidiSink,
-    {
-        let Some(events) = self.events() else {
-            return;
-        };
+    /// Report notes, tempo changes, lyrics and errors to `events` while playing.
+    pub(crate) fn set_event_sender(&mut self, events: EventSender) {
+        self.events = Some(events);
+    }
 
-        self.song_position += self.current_tick_duration();
-        self.tick += 1;
+    /// Play faster or slower by `speed`, 1.0 is the song's own tempo.
+    pub const fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
 
-        for wrap in events {
-            match wrap.track_event.event {
-                MidiMsg::ChannelVoice { msg, .. } | MidiMsg::RunningChannelVoice { msg, .. } => {
-                    match msg {
-                        ChannelVoiceMsg::NoteOn { .. } | ChannelVoiceMsg::HighResNoteOn { .. } => {}
-                        _ => {
-                            let _ = event_sink.receive_midi(&wrap.track_event.event);
-                        }
-                    }
-                }
-                MidiMsg::ChannelMode { .. } | MidiMsg::RunningChannelMode { .. } => {
-                    let _ = event_sink.receive_midi(&wrap.track_event.event);
-                }
-                midi_msg::MidiMsg::Meta { msg } => self.handle_meta_event(&msg),
-                _ => (),
-            }
-        }
+    pub const fn speed(&self) -> f64 {
+        self.speed
     }
 
-    fn events(&mut self) -> Option<Vec<TrackEventWrap>> {
-        let Some(midi_file) = &self.midi_file else {
-            return None;
-        };
+    /// Shift notes of non-drum channels by `semitones`, starting with the next note on.
+    pub const fn set_transpose(&mut self, semitones: i8) {
+        self.transposer.set_semitones(semitones);
+    }
 
-        let mut events = vec![];
-        for (track_idx, track) in midi_file.tracks.iter().enumerate() {
-            loop {
-                let event_idx = self.track_positions[track_idx];
-                if event_idx >= track.len() {
-                    break;
-                }
-                let event = &track.events()[event_idx];
-                let event_tick = midi_file
-                    .header
-                    .division
-                    .beat_or_frame_to_tick(event.beat_or_frame)
-                    as usize;
-                if self.tick >= event_tick {
-                    events.push(TrackEventWrap {
-                        track_event: event.clone(),
-                        track_idx,
-                        event_idx: self.track_positions[track_idx],
-                    });
-                    if self.tick > event_tick {
-                        let late = self.tick - event_tick;
-                        println!(
-                            "Somehow an event was missed! Playing it late ({late} ticks). {event:?}"
-                        );
-                    }
-                    self.track_positions[track_idx] += 1;
-                } else {
-                    break;
-                }
-            }
+    /// Jump back to the start of `region` whenever its end is reached.
+    pub const fn set_loop_region(&mut self, region: Option<LoopRegion>) {
+        self.loop_region = region;
+    }
+
+    /// Start over at the end of sequence, unless a loop region ends there first.
+    pub const fn set_repeat(&mut self, value: bool) {
+        self.repeat = value;
+    }
+
+    /// Playing time left until the next event or loop end is due, or `None` at the end of
+    /// sequence.
+    pub fn time_to_next_event(&self) -> Option<Duration> {
+        let next = self.schedule.get(self.next_event)?;
+        let mut due = self.tempo_map.tick_to_time(next.tick as f64);
+        if let Some(region) = self.loop_region
+            && region.end > self.song_position
+        {
+            due = due.min(region.end);
         }
-        Some(events)
+        Some(due.saturating_sub(self.song_position).div_f64(self.speed))
     }
 
-    fn handle_meta_event(&mut self, msg: &Meta) {
-        if let Meta::SetTempo(tempo) = msg {
-            self.bpm = 60_000_000. / f64::from(*tempo);
+    /// Advance by `delta` of playing time, sending every event that became due to `event_sink`.
+    ///
+    /// Wraps around at the end of the loop region, and at the end of sequence if it is looped or
+    /// repeated.
+    pub fn update_events<R>(&mut self, event_sink: &mut R, delta: Duration)
+    where
+        R: MidiSink,
+    {
+        self.song_position += delta.mul_f64(self.speed);
+
+        if let Some(region) = self.loop_region
+            && self.song_position >= region.end
+        {
+            let overshoot = self.song_position - region.end;
+            // Events on the loop end belong to what follows the loop.
+            let end = self.tempo_map.time_to_tick(region.end) - Self::TICK_TOLERANCE;
+            self.send_events_until(event_sink, end);
+            self.wrap_to(event_sink, region.start);
+            // Events on the loop start are due now, so the overshoot must not skip them.
+            self.song_position += overshoot;
+        }
+        self.send_due_events(event_sink);
+
+        if self.end_of_sequence() {
+            let start = match self.loop_region {
+                Some(region) if region.start < self.song_length => region.start,
+                _ if self.repeat => Duration::ZERO,
+                _ => return,
+            };
+            self.wrap_to(event_sink, start);
+            self.send_due_events(event_sink);
         }
     }
 
-    fn current_tick_duration(&self) -> Duration {
-        let Some(midi_file) = &self.midi_file else {
-            return Duration::ZERO;
-        };
-        let in_secs = match midi_file.header.division {
-            Division::TicksPerQuarterNote(ticks) => 60. / self.bpm / f64::from(ticks),
-            Division::TimeCode {
-                frames_per_second,
-                ticks_per_frame,
-            } => {
-                let fps = match frames_per_second {
-                    TimeCodeType::FPS24 => 24.,
-                    TimeCodeType::FPS25 => 25.,
-                    TimeCodeType::DF30 | TimeCodeType::NDF30 => 30.,
-                };
-                1. / fps / f64::from(ticks_per_frame)
-            }
-        };
-        Duration::from_secs_f64(in_secs)
+    fn send_due_events<R>(&mut self, event_sink: &mut R)
+    where
+        R: MidiSink,
+    {
+        // Tolerate rounding when the position was converted from the event's own tick.
+        let until = self.tempo_map.time_to_tick(self.song_position) + Self::TICK_TOLERANCE;
+        self.send_events_until(event_sink, until);
     }
 
-    /// Updates the `song_length` field by calculating the total duration of the MIDI file.
-    ///
-    /// This function iterates through all events in all tracks of the loaded MIDI file,
-    /// respecting tempo changes ([`Meta::SetTempo`] meta messages) and the timing division format
-    /// specified in the file header. It simulates playback tick-by-tick and accumulates
-    /// the real-world duration based on current tempo and time division.
-    ///
-    /// If no MIDI file is loaded, sets the duration to `Duration::ZERO`.
-    ///
-    /// ### Tempo Handling
-    ///
-    /// - Default tempo is 120 BPM.
-    /// - If a [Meta::SetTempo] meta message is encountered, the tempo is updated accordingly.
-    ///
-    /// ### Time Division Handling
-    ///
-    /// Supports both:
-    ///
-    /// - [`Division::TicksPerQuarterNote`]: standard MIDI ticks.
-    /// - [`Division::TimeCode`]: SMPTE timecode format.
-    ///
-    /// ### Performance Note
-    ///
-    /// This method simulates playback at single-tick resolution,
-    /// which may be inefficient for very long MIDI files.
-    fn update_song_length(&mut self) {
+    /// Send every event up to and including tick `until`.
+    fn send_events_until<R>(&mut self, event_sink: &mut R, until: f64)
+    where
+        R: MidiSink,
+    {
         let Some(midi_file) = &self.midi_file else {
-            self.song_length = Duration::ZERO;
             return;
         };
+        let mut event_sink = SequencerSink {
+            inner: event_sink,
+            transposer: &mut self.transposer,
+            events: self.events.as_ref(),
+        };
 
-        let mut track_positions = vec![0; midi_file.tracks.len()];
-        let mut tick = 0usize;
-        let mut duration = Duration::ZERO;
-        let mut bpm = 120.0;
-
-        loop {
-            let mut done = true;
-
-            for (i, track) in midi_file.tracks.iter().enumerate() {
-                let events = track.events();
-
-                while let Some(event) = events.get(track_positions[i]) {
-                    let event_tick = midi_file
-                        .header
-                        .division
-                        .beat_or_frame_to_tick(event.beat_or_frame)
-                        as usize;
-
-                    if tick < event_tick {
-                        break;
-                    }
-
-                    track_positions[i] += 1;
-                    done = false;
-
-                    if let MidiMsg::Meta {
-                        msg: Meta::SetTempo(tempo),
-                    } = &event.event
-                    {
-                        bpm = 60_000_000. / f64::from(*tempo);
-                    }
-                }
-            }
-
-            if done {
+        while let Some(&ScheduledEvent {
+            tick,
+            track_idx,
+            event_idx,
+        }) = self.schedule.get(self.next_event)
+        {
+            if tick as f64 > until {
                 break;
             }
+            self.next_event += 1;
 
-            let tick_duration = match midi_file.header.division {
-                Division::TicksPerQuarterNote(ticks) => 60. / bpm / f64::from(ticks),
-                Division::TimeCode {
-                    frames_per_second,
-                    ticks_per_frame,
-                } => {
-                    let fps = match frames_per_second {
-                        TimeCodeType::FPS24 => 24.,
-                        TimeCodeType::FPS25 => 25.,
-                        TimeCodeType::DF30 | TimeCodeType::NDF30 => 30.,
+            let track_event = &midi_file.tracks[track_idx].events()[event_idx];
+            match &track_event.event {
+                MidiMsg::ChannelVoice { .. }
+                | MidiMsg::RunningChannelVoice { .. }
+                | MidiMsg::ChannelMode { .. }
+                | MidiMsg::RunningChannelMode { .. } => {
+                    let result = event_sink.receive_midi(&track_event.event);
+                    if let (Err(()), Some(events)) = (result, &self.events) {
+                        let wrap = TrackEventWrap {
+                            track_event,
+                            track_idx,
+                            event_idx,
+                        };
+                        events.send(PlayerEvent::Error(format!("Unhandled: {wrap}")));
+                    }
+                }
+                // Most system exclusive messages don't apply to the synthesizer, so they are
+                // dropped without an error.
+                MidiMsg::SystemExclusive { .. } | MidiMsg::Invalid { .. } => {
+                    let _ = event_sink.receive_midi(&track_event.event);
+                }
+                MidiMsg::Meta { msg } => {
+                    let Some(events) = &self.events else {
+                        continue;
                     };
-                    1. / fps / f64::from(ticks_per_frame)
+                    match msg {
+                        Meta::SetTempo(_) => events.send(PlayerEvent::TempoChange {
+                            bpm: self.tempo_map.bpm_at(tick as f64),
+                        }),
+                        Meta::Lyric(text) => events.send(PlayerEvent::Lyric(text.clone())),
+                        Meta::Marker(text) | Meta::CuePoint(text) => {
+                            events.send(PlayerEvent::Marker(text.clone()));
+                        }
+                        _ => (),
+                    }
                 }
-            };
-
-            duration += Duration::from_secs_f64(tick_duration);
-            tick += 1;
+                _ => (),
+            }
         }
-
-        self.song_length = duration;
     }
 
     pub const fn song_length(&self) -> Duration {
@@ -311,26 +447,137 @@ impl MidiSequencer {
         self.song_position
     }
 
+    /// Jump to `position`: Reset `event_sink` and restore the channel states at `position`.
     pub fn seek_to<R>(&mut self, event_sink: &mut R, position: Duration)
     where
         R: MidiSink,
     {
-        let Some(midi_file) = &self.midi_file else {
+        let Some((channels, next_event)) = self.channels_at(position) else {
             return;
         };
 
-        if position < self.song_position {
-            self.bpm = 120.;
-            self.track_positions = vec![0; midi_file.tracks.len()];
-            self.tick = 0;
-            self.song_position = Duration::ZERO;
-            event_sink.reset();
-        }
+        let mut event_sink = SequencerSink {
+            inner: event_sink,
+            transposer: &mut self.transposer,
+            events: self.events.as_ref(),
+        };
+        event_sink.reset();
+        channels.restore(&mut event_sink, self.retrigger_on_seek);
+        self.next_event = next_event;
+        self.song_position = position;
+    }
 
-        self.since_last_tick = Duration::ZERO;
+    /// Jump to `position` without a reset, so sounding notes ring out instead of being cut off.
+    fn wrap_to<R>(&mut self, event_sink: &mut R, position: Duration)
+    where
+        R: MidiSink,
+    {
+        let Some((channels, next_event)) = self.channels_at(position) else {
+            return;
+        };
 
-        while self.song_position < position {
-            self.update_events_quiet(event_sink);
+        let mut event_sink = SequencerSink {
+            inner: event_sink,
+            transposer: &mut self.transposer,
+            events: self.events.as_ref(),
+        };
+        for channel in 0..16 {
+            // All Notes Off, Reset All Controllers
+            for control in [0x7b, 0x79] {
+                let _ = event_sink.receive_midi(&channel_message([0xb0 | channel, control, 0]));
+            }
         }
+        channels.restore(&mut event_sink, self.retrigger_on_seek);
+        self.next_event = next_event;
+        self.song_position = position;
     }
+
+    /// Channel states at `position` and the index of the first event not included in them.
+    ///
+    /// Starts from the nearest [`Checkpoint`] before `position`, then catches up to it.
+    fn channels_at(&self, position: Duration) -> Option<(ChannelStates, usize)> {
+        let midi_file = self.midi_file.as_ref()?;
+        let idx = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.position <= position);
+        let checkpoint = self.checkpoints.get(idx.saturating_sub(1))?;
+
+        let target = self.tempo_map.time_to_tick(position);
+        let mut channels = checkpoint.channels.clone();
+        let mut next_event = checkpoint.next_event;
+        while let Some(scheduled) = self.schedule.get(next_event) {
+            if scheduled.tick as f64 >= target {
+                break;
+            }
+            channels.apply(&Self::event(midi_file, scheduled).event);
+            next_event += 1;
+        }
+        Some((channels, next_event))
+    }
+}
+
+#[test]
+fn test_seek_restores_controllers() {
+    use midi_msg::{Channel, ChannelVoiceMsg, Track};
+
+    let voice = |msg| MidiMsg::ChannelVoice {
+        channel: Channel::Ch2,
+        msg,
+    };
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    midi_file.extend_track(0, voice(ChannelVoiceMsg::ProgramChange { program: 5 }), 0.);
+    midi_file.extend_track(0, voice(ChannelVoiceMsg::PitchBend { bend: 0x2100 }), 1.);
+    let note_on = ChannelVoiceMsg::NoteOn {
+        note: 60,
+        velocity: 100,
+    };
+    midi_file.extend_track(0, voice(note_on), 2.);
+    midi_file.extend_track(0, voice(ChannelVoiceMsg::ProgramChange { program: 7 }), 60.);
+
+    let mut sequencer = MidiSequencer::new();
+    let mut sink = Recorder::default();
+    sequencer.play(midi_file);
+    sequencer.set_retrigger_on_seek(true);
+    sequencer.seek_to(&mut sink, Duration::from_secs(40));
+    sequencer.seek_to(&mut sink, Duration::from_secs(2));
+
+    // At 120 BPM, beat 2 is at 1s.
+    assert_eq!(
+        sink.0,
+        [vec![0xc1, 5], vec![0xe1, 0x00, 0x42], vec![0x91, 60, 100]]
+    );
+}
+
+#[test]
+fn test_loop_region_wraps() {
+    use midi_msg::{Channel, ChannelVoiceMsg, Track};
+
+    let note_on = |note| MidiMsg::ChannelVoice {
+        channel: Channel::Ch1,
+        msg: ChannelVoiceMsg::NoteOn {
+            note,
+            velocity: 100,
+        },
+    };
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    midi_file.extend_track(0, note_on(60), 0.);
+    midi_file.extend_track(0, note_on(62), 2.);
+
+    let mut sequencer = MidiSequencer::new();
+    let mut sink = Recorder::default();
+    sequencer.play(midi_file);
+    // At 120 BPM, beat 2 is at 1s.
+    sequencer.set_loop_region(Some(LoopRegion {
+        start: Duration::ZERO,
+        end: Duration::from_secs(1),
+    }));
+    sequencer.update_events(&mut sink, Duration::ZERO);
+    assert_eq!(sequencer.time_to_next_event(), Some(Duration::from_secs(1)));
+    sequencer.update_events(&mut sink, Duration::from_millis(1250));
+
+    let notes: Vec<_> = sink.0.iter().filter(|raw| raw[0] == 0x90).collect();
+    assert_eq!(notes, [&vec![0x90, 60, 100], &vec![0x90, 60, 100]]);
+    assert_eq!(sequencer.song_position(), Duration::from_millis(250));
 }
@@ -1,8 +1,21 @@
 use midi_msg::MidiFile;
-use rustysynth::{SoundFont, Synthesizer, SynthesizerSettings};
-use std::{sync::Arc, time::Duration};
+use rustysynth::{SoundFont, Synthesizer, SynthesizerError};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
-use super::midi_sequencer::MidiSequencer;
+use super::{
+    events::{EventSender, PlayerEvent},
+    layers::{ChannelOverride, LayeredSynth},
+    midi_sequencer::MidiSequencer,
+    midi_synth::MidiSynth,
+    mixer::{LiveMixer, Mixer},
+    song_settings::SongSettings,
+};
 
 #[derive(PartialEq)]
 enum Channel {
@@ -10,93 +23,282 @@ enum Channel {
     R,
 }
 
+/// Song position of the playing [`MidiSource`], readable from other threads.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SharedPosition(Arc<AtomicU64>);
+
+impl SharedPosition {
+    pub fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    fn set(&self, position: Duration) {
+        self.0.store(position.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
 /// Audio source for Rodio. This takes in soundfont and midi_file, and generates audio samples from
 /// them. The disposable struct is consumed by audio sink for each song.
+///
+/// Audio is rendered up to the frame the next MIDI event is due on, then the event is sent
+/// before rendering goes on. After the last event, notes still releasing are rendered until they
+/// fall silent.
 pub struct MidiSource {
-    /// The actual audio generator
-    synthesizer: Synthesizer,
+    /// The actual audio generator, one synthesizer per SoundFont
+    synthesizer: LayeredSynth,
     /// The midi file sequencer
     sequencer: MidiSequencer,
-    /// Sample time
-    delta: Duration,
-    /// We need to cache the R channel sample.
-    cached_sample: f32,
-    /// Which channel was played last
+    /// Applied to everything the sequencer sends
+    mixer: LiveMixer,
+    /// Speed, transposition and loop region, picked up before every block
+    song_settings: Arc<Mutex<SongSettings>>,
+    /// Start over at the end, picked up before every block
+    repeat: Arc<AtomicBool>,
+    position: SharedPosition,
+    /// Rendered L channel block
+    left: Vec<f32>,
+    /// Rendered R channel block
+    right: Vec<f32>,
+    /// Number of valid frames in the current block
+    block_len: usize,
+    /// Index of the frame to be played next
+    block_pos: usize,
+    /// Which channel is played next
     next_channel: Channel,
+    events: Option<EventSender>,
+    /// Song position of the last [`PlayerEvent::Position`]
+    reported_position: Option<Duration>,
+    /// Frames of the tail left to render after the end of sequence, `None` before it
+    tail_left: Option<usize>,
 }
 
 impl MidiSource {
-    const DEFAULT_SAMPLE_RATE: i32 = 44100;
+    pub(crate) const DEFAULT_SAMPLE_RATE: i32 = 44100;
+    /// Upper bound of frames rendered at once, limits latency of seeking.
+    const MAX_BLOCK_LEN: usize = 512;
+    /// Minimum song time between two [`PlayerEvent::Position`]s.
+    const POSITION_INTERVAL: Duration = Duration::from_millis(20);
+    /// Synthesizer master volume at 0 dB master gain, leaves room for dense arrangements.
+    const HEADROOM: f32 = 0.1;
+    /// Longest tail rendered after the last event, for sounds that never fade out
+    pub(crate) const MAX_TAIL: Duration = Duration::from_secs(10);
+    /// Level the tail ends below, -80 dBFS
+    const SILENCE: f32 = 1e-4;
 
     /// New `MidiSource` that immediately starts playing.
-    pub fn new(soundfont: &Arc<SoundFont>, midi_file: MidiFile) -> Self {
+    pub fn new(soundfont: &Arc<SoundFont>, midi_file: MidiFile) -> Result<Self, SynthesizerError> {
         Self::with_sample_rate(soundfont, midi_file, Self::DEFAULT_SAMPLE_RATE)
     }
 
     /// New `MidiSource` that immediately starts playing.
-    fn with_sample_rate(soundfont: &Arc<SoundFont>, midi_file: MidiFile, sample_rate: i32) -> Self {
-        let settings = SynthesizerSettings::new(sample_rate);
+    pub(crate) fn with_sample_rate(
+        soundfont: &Arc<SoundFont>,
+        midi_file: MidiFile,
+        sample_rate: i32,
+    ) -> Result<Self, SynthesizerError> {
+        let settings = MidiSynth::settings(sample_rate);
         let mut synthesizer =
-            Synthesizer::new(soundfont, &settings).expect("Could not create synthesizer");
-        synthesizer.set_master_volume(1.0);
+            LayeredSynth::new(MidiSynth::new(Synthesizer::new(soundfont, &settings)?));
+        synthesizer.set_master_volume(Self::HEADROOM);
         let mut sequencer = MidiSequencer::new();
         sequencer.play(midi_file);
 
-        let delta_t = Duration::from_secs_f64(1. / f64::from(synthesizer.get_sample_rate()));
-        Self {
+        Ok(Self {
             synthesizer,
-            delta: delta_t,
             sequencer,
+            mixer: LiveMixer::new(Arc::default()),
+            song_settings: Arc::default(),
+            repeat: Arc::default(),
+            position: SharedPosition::default(),
+            left: vec![0.; Self::MAX_BLOCK_LEN],
+            right: vec![0.; Self::MAX_BLOCK_LEN],
+            block_len: 0,
+            block_pos: 0,
             next_channel: Channel::L,
-            cached_sample: 0.,
-        }
+            events: None,
+            reported_position: None,
+            tail_left: None,
+        })
     }
 
     pub const fn song_length(&self) -> Duration {
         self.sequencer.song_length()
     }
-}
 
-// Rodio requires Iterator implementation.
-// This is where whe generate the next samples.
-impl Iterator for MidiSource {
-    type Item = f32;
+    /// Start notes that are held at the seek target again, instead of waiting for the next ones.
+    pub const fn set_retrigger_on_seek(&mut self, value: bool) {
+        self.sequencer.set_retrigger_on_seek(value);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.sequencer.end_of_sequence() {
-            return None;
+    /// Play channels with other SoundFonts or presets, see [`ChannelOverride`].
+    pub(crate) fn set_channel_overrides(
+        &mut self,
+        overrides: &[Option<ChannelOverride>; 16],
+    ) -> Result<(), SynthesizerError> {
+        self.synthesizer.set_overrides(overrides)
+    }
+
+    /// Take master gain and channel settings from `mixer`, also while playing.
+    pub(crate) fn set_mixer(&mut self, mixer: Arc<Mutex<Mixer>>) {
+        self.mixer = LiveMixer::new(mixer);
+    }
+
+    /// Take speed, transposition and loop region from `song_settings`, also while playing.
+    pub(crate) fn set_song_settings(&mut self, song_settings: Arc<Mutex<SongSettings>>) {
+        self.song_settings = song_settings;
+    }
+
+    /// Start over at the end of sequence while `repeat` is set, instead of ending.
+    pub(crate) fn set_repeat(&mut self, repeat: Arc<AtomicBool>) {
+        self.repeat = repeat;
+    }
+
+    /// Keep `position` up to date with the song position.
+    pub(crate) fn set_shared_position(&mut self, position: SharedPosition) {
+        self.position = position;
+    }
+
+    /// Report playback progress and MIDI events to `events`.
+    pub(crate) fn set_event_sender(&mut self, events: EventSender) {
+        self.sequencer.set_event_sender(events.clone());
+        self.events = Some(events);
+    }
+
+    /// Send a [`PlayerEvent::Position`] if the last one is [`Self::POSITION_INTERVAL`] old.
+    fn report_position(&mut self) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        let position = self.sequencer.song_position();
+        let due = self.reported_position.is_none_or(|reported| {
+            position < reported || position - reported >= Self::POSITION_INTERVAL
+        });
+        if due {
+            events.send(PlayerEvent::Position(position));
+            self.reported_position = Some(position);
         }
+    }
 
-        // The midi synth generates bot L and R samples simultaneously, but Rodio polls samples
-        // separately for each channel.
+    /// Frames to render when the next event is due `until_next`: Up to the first frame at or
+    /// after it, at least one and at most [`Self::MAX_BLOCK_LEN`].
+    fn block_len(until_next: Option<Duration>, sample_rate: f64) -> usize {
+        until_next
+            .map_or(Self::MAX_BLOCK_LEN, |until_next| {
+                (until_next.as_secs_f64() * sample_rate).ceil() as usize
+            })
+            .clamp(1, Self::MAX_BLOCK_LEN)
+    }
 
-        // Left: generate both channels and store R channel sample.
-        if self.next_channel == Channel::L {
-            self.next_channel = Channel::R;
+    /// Render frames up to the next event, at most [`Self::MAX_BLOCK_LEN`].
+    ///
+    /// Returns `false` if the sequence has ended.
+    fn render_block(&mut self) -> bool {
+        self.mixer.sync(&mut self.synthesizer);
+        self.synthesizer
+            .set_master_volume(Self::HEADROOM * self.mixer.master_amplitude());
+        // Never block the audio thread, changes are picked up on the next block instead.
+        if let Ok(song_settings) = self.song_settings.try_lock() {
+            self.sequencer.set_speed(song_settings.speed());
+            self.sequencer.set_transpose(song_settings.transpose());
+            self.sequencer.set_loop_region(song_settings.loop_region());
+        }
+        self.sequencer
+            .set_repeat(self.repeat.load(Ordering::Relaxed));
+
+        // Events due right now must reach the synthesizer before rendering.
+        self.sequencer
+            .update_events(&mut self.mixer.sink(&mut self.synthesizer), Duration::ZERO);
+        if self.sequencer.end_of_sequence() {
+            return self.render_tail();
+        }
 
-            self.sequencer
-                .update_events(&mut self.synthesizer, self.delta);
+        let sample_rate = f64::from(self.synthesizer.get_sample_rate());
+        let frames = Self::block_len(self.sequencer.time_to_next_event(), sample_rate);
 
-            let mut left = [0.];
-            let mut right = [0.];
-            self.synthesizer.render(&mut left, &mut right);
+        self.synthesizer
+            .render(&mut self.left[..frames], &mut self.right[..frames]);
+        let rendered = Duration::from_secs_f64(frames as f64 / sample_rate);
+        self.sequencer
+            .update_events(&mut self.mixer.sink(&mut self.synthesizer), rendered);
 
-            self.cached_sample = right[0] / 10.;
-            Some(left[0] / 10.)
+        self.block_len = frames;
+        self.block_pos = 0;
+        self.position.set(self.sequencer.song_position());
+        self.report_position();
+        true
+    }
+
+    /// Render what still sounds after the end of sequence, such as releasing notes, until a
+    /// block is silent or [`Self::MAX_TAIL`] is rendered.
+    ///
+    /// Returns `false` once the tail has ended.
+    fn render_tail(&mut self) -> bool {
+        let sample_rate = f64::from(self.synthesizer.get_sample_rate());
+        let tail_left = self
+            .tail_left
+            .get_or_insert((Self::MAX_TAIL.as_secs_f64() * sample_rate) as usize);
+        if *tail_left == 0 {
+            if let Some(events) = &self.events {
+                events.send(PlayerEvent::EndOfSong);
+            }
+            return false;
         }
-        // Right: Generate nothing and return cached R ch. sample.
-        else {
-            self.next_channel = Channel::L;
-            Some(self.cached_sample)
+
+        let frames = (*tail_left).min(Self::MAX_BLOCK_LEN);
+        let (left, right) = (&mut self.left[..frames], &mut self.right[..frames]);
+        self.synthesizer.render(left, right);
+        let silent = left
+            .iter()
+            .chain(right.iter())
+            .all(|sample| sample.abs() < Self::SILENCE);
+        *tail_left = if silent { 0 } else { *tail_left - frames };
+
+        self.block_len = frames;
+        self.block_pos = 0;
+        true
+    }
+}
+
+// Rodio requires Iterator implementation.
+// This is where whe generate the next samples.
+impl Iterator for MidiSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The midi synth generates both L and R samples simultaneously, but Rodio polls samples
+        // separately for each channel.
+        match self.next_channel {
+            // Left: Render a new block if the current one is used up.
+            // Only stop on frame boundaries, so the stream stays channel aligned.
+            Channel::L => {
+                if self.block_pos >= self.block_len && !self.render_block() {
+                    return None;
+                }
+                self.next_channel = Channel::R;
+                Some(self.left[self.block_pos])
+            }
+            // Right: Advance to the next frame.
+            Channel::R => {
+                self.next_channel = Channel::L;
+                let sample = self.right[self.block_pos];
+                self.block_pos += 1;
+                Some(sample)
+            }
         }
     }
 }
 
 impl rodio::Source for MidiSource {
     fn current_frame_len(&self) -> Option<usize> {
-        let time_left = self.sequencer.song_length() - self.sequencer.song_position();
+        let time_left = (self.sequencer.song_length())
+            .saturating_sub(self.sequencer.song_position())
+            .div_f64(self.sequencer.speed());
         let samples_left = time_left.as_secs_f64() * f64::from(self.synthesizer.get_sample_rate());
-        Some(samples_left as usize)
+        // The tail goes on after the end of sequence.
+        let tail = self
+            .tail_left
+            .map_or(0, |frames| 2 * (frames + self.block_len - self.block_pos));
+        Some(samples_left as usize + tail)
     }
 
     fn channels(&self) -> u16 {
@@ -104,15 +306,109 @@ impl rodio::Source for MidiSource {
     }
 
     fn sample_rate(&self) -> u32 {
-        Self::DEFAULT_SAMPLE_RATE as u32
+        self.synthesizer.get_sample_rate() as u32
     }
 
     fn total_duration(&self) -> Option<Duration> {
-        Some(self.sequencer.song_length())
+        // Depends on the speed, which can change while playing.
+        None
     }
 
     fn try_seek(&mut self, position: Duration) -> Result<(), rodio::source::SeekError> {
-        self.sequencer.seek_to(&mut self.synthesizer, position);
+        self.sequencer
+            .seek_to(&mut self.mixer.sink(&mut self.synthesizer), position);
+        self.position.set(position);
+        self.tail_left = None;
+        // Drop the rest of the rendered block, except for a pending R sample.
+        self.block_len = self.block_pos + usize::from(self.next_channel == Channel::R);
         Ok(())
     }
 }
+
+#[test]
+fn test_block_len() {
+    let block_len = |micros| MidiSource::block_len(Some(Duration::from_micros(micros)), 48000.);
+    // Rendering stops on the frame an event is due on, events due now wait for one frame.
+    assert_eq!(block_len(0), 1);
+    assert_eq!(block_len(1000), 48);
+    assert_eq!(block_len(1010), 49);
+    assert_eq!(block_len(1_000_000), MidiSource::MAX_BLOCK_LEN);
+    assert_eq!(
+        MidiSource::block_len(None, 48000.),
+        MidiSource::MAX_BLOCK_LEN
+    );
+}
+
+#[test]
+fn test_blocks_end_on_events() {
+    use midi_msg::{Channel, ChannelVoiceMsg, MidiMsg, Track};
+
+    // A note from 7 ticks, 583.3 frames at 96 ticks per beat, 120 BPM and 16 kHz, to 1 beat
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    let voice = |msg| MidiMsg::ChannelVoice {
+        channel: Channel::Ch1,
+        msg,
+    };
+    let note_on = ChannelVoiceMsg::NoteOn {
+        note: 60,
+        velocity: 100,
+    };
+    midi_file.extend_track(0, voice(note_on), 7. / 96.);
+    let note_off = ChannelVoiceMsg::NoteOff {
+        note: 60,
+        velocity: 0,
+    };
+    midi_file.extend_track(0, voice(note_off), 1.);
+    let soundfont = super::loader::test_soundfont(&[(0, 0, true)]);
+    let mut source = MidiSource::with_sample_rate(&soundfont, midi_file, 16000).unwrap();
+
+    // Rendering stops on the note on, then goes on up to the note off.
+    let mut blocks = vec![];
+    while blocks.iter().sum::<usize>() < 8000 {
+        assert!(source.render_block());
+        blocks.push(source.block_len);
+    }
+    assert_eq!(blocks, [[512, 72].as_slice(), &[512; 14], &[248]].concat());
+}
+
+#[test]
+fn test_end_of_song_after_the_tail() {
+    use midi_msg::{Channel, ChannelVoiceMsg, MidiMsg, Track};
+
+    // A note of 2 ticks, 0.01s at 96 ticks per beat and 120 BPM, which takes a second to release
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    let voice = |msg| MidiMsg::ChannelVoice {
+        channel: Channel::Ch1,
+        msg,
+    };
+    let note_on = ChannelVoiceMsg::NoteOn {
+        note: 60,
+        velocity: 100,
+    };
+    midi_file.extend_track(0, voice(note_on), 0.);
+    let note_off = ChannelVoiceMsg::NoteOff {
+        note: 60,
+        velocity: 0,
+    };
+    midi_file.extend_track(0, voice(note_off), 2. / 96.);
+    let soundfont = super::loader::test_soundfont(&[(0, 0, true)]);
+    let mut source = MidiSource::with_sample_rate(&soundfont, midi_file, 16000).unwrap();
+    let events = EventSender::default();
+    let mut receiver = events.subscribe();
+    source.set_event_sender(events);
+
+    let mut ended = || {
+        std::iter::from_fn(|| receiver.try_recv().ok()).any(|event| event == PlayerEvent::EndOfSong)
+    };
+    let mut frames = 0;
+    while source.render_block() {
+        frames += source.block_len;
+        assert!(!ended());
+    }
+    // The release is rendered until it falls silent, then the song ends.
+    assert!(frames > 16000 / 2);
+    assert!(frames < 16000 * 10);
+    assert!(ended());
+}
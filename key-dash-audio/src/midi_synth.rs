@@ -1,55 +1,446 @@
 //! `RustySynth` integration: This makes [`rustysynth::Synthesizer`] compatible
 //! with `MidiSequencer` and `midi_msg` crate's event format.
 //!
+//! `midi_msg` may merge several messages into one, see [`split_messages`]. These are split, then
+//! pass a per channel controller state machine that fills the gaps of `rustysynth`: Data
+//! increment and decrement of RPNs, NRPNs that must not change RPNs, 14-bit controllers whose
+//! LSB is cleared by a new MSB, master fine tuning on top of channel fine tuning, and drum parts
+//! that keep their drum bank.
+//!
 
 use midi_msg::MidiMsg;
-use rustysynth::Synthesizer;
+use rustysynth::{Synthesizer, SynthesizerSettings};
+use std::ops::{Deref, DerefMut};
 
-use super::midi_sequencer::MidiSink;
+use super::{
+    channel_state::{Parameter, ParameterSelection, split_messages},
+    gm,
+    midi_sequencer::MidiSink,
+    sysex::SysEx,
+};
 
-impl MidiSink for Synthesizer {
-    fn receive_midi(&mut self, msg: &MidiMsg) -> Result<(), ()> {
-        let raw = msg.to_midi();
+/// [`Synthesizer`] that keeps track of the controller state of every channel.
+pub(crate) struct MidiSynth {
+    synthesizer: Synthesizer,
+    channels: [ControllerState; 16],
+}
 
-        if let 2..=3 = raw.len() {
-            send_raw_event(self, &raw);
-            return Ok(());
+impl MidiSynth {
+    /// Frames the synthesizer renders at once, the fewest `rustysynth` allows. Messages take
+    /// effect from its next block on.
+    pub const BLOCK_LEN: usize = 8;
+
+    /// Settings of the synthesizers, rendering [`Self::BLOCK_LEN`] frames at once.
+    pub fn settings(sample_rate: i32) -> SynthesizerSettings {
+        let mut settings = SynthesizerSettings::new(sample_rate);
+        settings.block_size = Self::BLOCK_LEN;
+        settings
+    }
+
+    pub fn new(synthesizer: Synthesizer) -> Self {
+        Self {
+            synthesizer,
+            channels: [ControllerState::default(); 16],
         }
+    }
 
-        if raw.len() == 5 {
-            // Break a message that contains MSB and LSB in one into two
-            // separate ones for rustysynth consumption.
-            if let 0x62 | 0x64 = raw[1] {
-                let msb = vec![raw[0], raw[3], raw[4]];
-                let lsb = &raw[0..3];
-                send_raw_event(self, &msb);
-                send_raw_event(self, lsb);
-                return Ok(());
+    /// Apply the parts of `sysex` the synthesizer is responsible for.
+    ///
+    /// Master volume and coarse tuning are applied by the mixer and transposer before messages
+    /// get here.
+    fn apply_sysex(&mut self, sysex: SysEx) {
+        match sysex {
+            SysEx::Reset => MidiSink::reset(self),
+            // Banks above 127 hold the drum kits, they can't be selected with Bank Select.
+            // Channel 10 always plays drums, `rustysynth` offers no way to change that.
+            // The song's Bank Select can't change the drum bank back, see `ControllerState`.
+            SysEx::DrumPart { channel, drums }
+                if usize::from(channel) != Synthesizer::PERCUSSION_CHANNEL =>
+            {
+                self.channels[usize::from(channel)].drums = drums;
+                let bank = if drums { gm::DRUM_BANK } else { 0 };
+                self.synthesizer
+                    .process_midi_message(channel.into(), 0xb0, 0x00, bank.into());
+            }
+            SysEx::MasterFineTuning(semitones) => {
+                let offset = (semitones * 8192.).round().clamp(-8192., 8191.) as i16;
+                for channel in 0..16u8 {
+                    if usize::from(channel) == Synthesizer::PERCUSSION_CHANNEL {
+                        continue;
+                    }
+                    let synthesizer = &mut self.synthesizer;
+                    self.channels[usize::from(channel)].set_master_fine_tuning(
+                        channel,
+                        offset,
+                        |message| send_raw_event(synthesizer, message),
+                    );
+                }
             }
+            SysEx::DrumPart { .. } | SysEx::MasterVolume(_) | SysEx::MasterCoarseTuning(_) => (),
+        }
+    }
+
+    /// Pass a `[status, data1, data2]` message through the controller state of its channel.
+    fn send(&mut self, message: [u8; 3]) {
+        let synthesizer = &mut self.synthesizer;
+        self.channels[usize::from(message[0] & 0x0f)].process(message, |message| {
+            send_raw_event(synthesizer, message);
+        });
+    }
+}
+
+impl Deref for MidiSynth {
+    type Target = Synthesizer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.synthesizer
+    }
+}
+
+impl DerefMut for MidiSynth {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.synthesizer
+    }
+}
+
+impl MidiSink for MidiSynth {
+    fn receive_midi(&mut self, msg: &MidiMsg) -> Result<(), ()> {
+        if let Some(sysex) = SysEx::from_midi(msg) {
+            self.apply_sysex(sysex);
+            return Ok(());
+        }
+        if !(msg.is_channel_voice() || msg.is_channel_mode()) {
+            return Err(());
         }
 
-        Err(())
+        for message in split_messages(&msg.to_midi()) {
+            self.send(message);
+        }
+        Ok(())
     }
+
     fn reset(&mut self) {
-        self.reset();
+        self.synthesizer.reset();
+        self.channels = [ControllerState::default(); 16];
     }
 }
 
-fn send_raw_event(synth: &mut Synthesizer, raw: &[u8]) {
-    let channel = raw[0] & 0x0f;
-    let command = raw[0] & 0xf0;
-    let data1;
-    let data2;
-    match raw.len() {
-        2 => {
-            data1 = raw[1];
-            data2 = 0;
+/// Controller state of one channel, as far as `rustysynth` doesn't keep it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ControllerState {
+    selection: ParameterSelection,
+    /// Pitch Bend Sensitivity, Channel Fine Tuning and Channel Coarse Tuning as 14-bit values
+    registered: [u16; 3],
+    /// Bit mask of the 14-bit controllers (0-31) whose LSB was set since their MSB
+    lsb_set: u32,
+    /// Added to the Channel Fine Tuning of the song, from the master fine tuning
+    master_fine_tuning: i16,
+    /// Drum part set by a system exclusive message, which Bank Select must not change
+    drums: bool,
+}
+
+impl Default for ControllerState {
+    fn default() -> Self {
+        Self {
+            selection: ParameterSelection::default(),
+            registered: [
+                Parameter::PITCH_BEND_SENSITIVITY,
+                Parameter::FINE_TUNING,
+                Parameter::COARSE_TUNING,
+            ]
+            .map(Parameter::default_value),
+            lsb_set: 0,
+            master_fine_tuning: 0,
+            drums: false,
+        }
+    }
+}
+
+impl ControllerState {
+    const BANK_SELECT: u8 = 0x00;
+    const BANK_SELECT_LSB: u8 = 0x20;
+    const RESET_ALL_CONTROLLERS: u8 = 0x79;
+
+    /// Value of the selected RPN, if it is one `rustysynth` knows.
+    fn registered_mut(&mut self) -> Option<(Parameter, &mut u16)> {
+        match self.selection.selected()? {
+            parameter @ Parameter::Registered(number) => self
+                .registered
+                .get_mut(usize::from(number))
+                .map(|value| (parameter, value)),
+            Parameter::NonRegistered(_) => None,
+        }
+    }
+
+    /// Value of the selected RPN as `rustysynth` gets it: Channel Fine Tuning includes the master
+    /// fine tuning.
+    fn registered_value(&self) -> Option<u16> {
+        match self.selection.selected()? {
+            Parameter::FINE_TUNING => {
+                let tuning = i32::from(self.registered[1]) + i32::from(self.master_fine_tuning);
+                Some(tuning.clamp(0, 0x3fff) as u16)
+            }
+            Parameter::Registered(number) => self.registered.get(usize::from(number)).copied(),
+            Parameter::NonRegistered(_) => None,
+        }
+    }
+
+    /// Send the selected RPN as data entry MSB and LSB, `rustysynth` doesn't understand anything
+    /// else.
+    fn send_data_entry<F>(&self, status: u8, send: &mut F)
+    where
+        F: FnMut([u8; 3]),
+    {
+        if let Some(value) = self.registered_value() {
+            send([status, ParameterSelection::DATA_ENTRY, (value >> 7) as u8]);
+            send([
+                status,
+                ParameterSelection::DATA_ENTRY_LSB,
+                (value & 0x7f) as u8,
+            ]);
+        }
+    }
+
+    /// Send the controllers that make `self.selection` on `status`.
+    fn send_selection<F>(&self, status: u8, send: &mut F)
+    where
+        F: FnMut([u8; 3]),
+    {
+        for (control, value) in self.selection.controls() {
+            send([status, control, value]);
         }
-        3 => {
-            data1 = raw[1];
-            data2 = raw[2];
+    }
+
+    /// Add `offset` to the Channel Fine Tuning of `channel`, calling `send` with the messages
+    /// that retune it. The parameter selected by the song is selected again afterwards.
+    fn set_master_fine_tuning<F>(&mut self, channel: u8, offset: i16, mut send: F)
+    where
+        F: FnMut([u8; 3]),
+    {
+        self.master_fine_tuning = offset;
+        let status = 0xb0 | channel;
+        let selection = self.selection;
+        self.selection.select(Parameter::FINE_TUNING);
+        self.send_selection(status, &mut send);
+        self.send_data_entry(status, &mut send);
+
+        self.selection = selection;
+        self.send_selection(status, &mut send);
+    }
+
+    /// Update the state with `[status, data1, data2]`, calling `send` with the messages that
+    /// bring `rustysynth` to the same state.
+    fn process<F>(&mut self, [status, data1, data2]: [u8; 3], mut send: F)
+    where
+        F: FnMut([u8; 3]),
+    {
+        if status & 0xf0 != 0xb0 {
+            send([status, data1, data2]);
+            return;
+        }
+        let (control, value) = (data1 & 0x7f, data2 & 0x7f);
+        if self.selection.apply(control, value) {
+            send([status, control, value]);
+            return;
+        }
+        // Data entry of the RPNs `rustysynth` knows is sent as the whole value, since it can't
+        // step values and fine tuning is combined with the master fine tuning.
+        if let Some((parameter, registered)) = self.registered_mut()
+            && let Some(new_value) = parameter.data_entry(*registered, control, value)
+        {
+            *registered = new_value;
+            self.send_data_entry(status, &mut send);
+            return;
         }
-        _ => panic!("This shouldn't happen. Check length before calling."),
+
+        match control {
+            Self::RESET_ALL_CONTROLLERS => {
+                self.selection = ParameterSelection::default();
+                self.lsb_set = 0;
+            }
+            Self::BANK_SELECT | Self::BANK_SELECT_LSB if self.drums => return,
+            // 14-bit controller MSB: A new MSB clears the LSB.
+            0x00..=0x1f => {
+                send([status, control, value]);
+                if self.lsb_set & 1 << control != 0 {
+                    self.lsb_set &= !(1 << control);
+                    send([status, control + 0x20, 0]);
+                }
+                return;
+            }
+            0x20..=0x3f => self.lsb_set |= 1 << (control - 0x20),
+            _ => (),
+        }
+        send([status, control, value]);
     }
+}
+
+fn send_raw_event(synth: &mut Synthesizer, [status, data1, data2]: [u8; 3]) {
+    let channel = status & 0x0f;
+    let command = status & 0xf0;
     synth.process_midi_message(channel.into(), command.into(), data1.into(), data2.into());
 }
+
+#[cfg(test)]
+fn process_bytes(state: &mut ControllerState, raw: &[u8]) -> Vec<u8> {
+    let mut sent = vec![];
+    for message in split_messages(raw) {
+        state.process(message, |message| sent.extend(message));
+    }
+    sent
+}
+
+/// `raw` with the status byte repeated for every message.
+#[cfg(test)]
+fn raw_messages(raw: &[u8]) -> Vec<u8> {
+    split_messages(raw).flatten().collect()
+}
+
+#[test]
+fn test_rpn_data_entry() {
+    let mut state = ControllerState::default();
+    // Pitch Bend Sensitivity of 12 semitones, merged by `midi_msg` with running status. Each
+    // data entry is sent as the whole value.
+    let raw = [0xb0, 0x65, 0x00, 0x64, 0x00, 0x06, 0x0c, 0x26, 0x00];
+    assert_eq!(
+        process_bytes(&mut state, &raw),
+        [
+            0xb0, 0x65, 0x00, 0xb0, 0x64, 0x00, 0xb0, 0x06, 0x0c, 0xb0, 0x26, 0x00, 0xb0, 0x06,
+            0x0c, 0xb0, 0x26, 0x00
+        ]
+    );
+    assert_eq!(state.registered[0], 12 << 7);
+
+    // Coarse tuning increments by semitones, into data entry.
+    let raw = [0xb0, 0x65, 0x00, 0x64, 0x02, 0x60, 0x00];
+    assert_eq!(
+        process_bytes(&mut state, &raw),
+        [
+            0xb0, 0x65, 0x00, 0xb0, 0x64, 0x02, 0xb0, 0x06, 0x41, 0xb0, 0x26, 0x00
+        ]
+    );
+
+    // Fine tuning decrements by its LSB.
+    let raw = [0xb0, 0x64, 0x01, 0x61, 0x00];
+    assert_eq!(
+        process_bytes(&mut state, &raw),
+        [0xb0, 0x64, 0x01, 0xb0, 0x06, 0x3f, 0xb0, 0x26, 0x7f]
+    );
+
+    // Deselected by the null function, so data entry no longer applies.
+    let raw = [0xb0, 0x65, 0x7f, 0x64, 0x7f, 0x06, 0x00];
+    process_bytes(&mut state, &raw);
+    assert_eq!(state.registered, [12 << 7, 0x1fff, 0x2080]);
+}
+
+#[test]
+fn test_nrpn_passthrough() {
+    let mut state = ControllerState::default();
+    state.registered[0] = 7 << 7;
+    // Vibrato rate (GS/XG NRPN 01 08), then an increment.
+    let raw = [0xb3, 0x63, 0x01, 0x62, 0x08, 0x06, 0x50, 0x60, 0x00];
+    assert_eq!(process_bytes(&mut state, &raw), raw_messages(&raw));
+    assert_eq!(
+        state.selection.selected(),
+        Some(Parameter::NonRegistered(0x88))
+    );
+    assert_eq!(state.registered[0], 7 << 7);
+}
+
+#[test]
+fn test_14_bit_controllers() {
+    let mut state = ControllerState::default();
+    // Volume MSB and LSB, merged by `midi_msg`.
+    let raw = [0xb0, 0x07, 0x64, 0x27, 0x10];
+    assert_eq!(process_bytes(&mut state, &raw), raw_messages(&raw));
+    // A new MSB clears the LSB, once.
+    assert_eq!(
+        process_bytes(&mut state, &[0xb0, 0x07, 0x50]),
+        [0xb0, 0x07, 0x50, 0xb0, 0x27, 0x00]
+    );
+    assert_eq!(
+        process_bytes(&mut state, &[0xb0, 0x07, 0x40]),
+        [0xb0, 0x07, 0x40]
+    );
+
+    // High resolution note on: The velocity LSB is sent as controller 88.
+    let raw = [0x90, 0x3c, 0x40, 0xb0, 0x58, 0x10];
+    assert_eq!(process_bytes(&mut state, &raw), raw);
+}
+
+#[test]
+fn test_master_fine_tuning() {
+    let mut state = ControllerState::default();
+    // The song selects Pitch Bend Sensitivity, and tunes the channel a bit sharp.
+    process_bytes(&mut state, &[0xb0, 0x65, 0x00, 0x64, 0x01, 0x06, 0x41]);
+    process_bytes(&mut state, &[0xb0, 0x64, 0x00]);
+
+    let mut sent = vec![];
+    state.set_master_fine_tuning(0, -0x100, |message| sent.extend(message));
+    // Fine tuning of both, then the song's parameter is selected again.
+    assert_eq!(
+        sent,
+        [
+            0xb0, 0x65, 0x00, 0xb0, 0x64, 0x01, 0xb0, 0x06, 0x3f, 0xb0, 0x26, 0x00, 0xb0, 0x65,
+            0x00, 0xb0, 0x64, 0x00
+        ]
+    );
+    assert_eq!(state.selection.selected(), Some(Parameter::Registered(0)));
+
+    // Later fine tuning of the song keeps the master fine tuning.
+    let raw = [0xb0, 0x64, 0x01, 0x06, 0x40];
+    assert_eq!(
+        process_bytes(&mut state, &raw),
+        [0xb0, 0x64, 0x01, 0xb0, 0x06, 0x3e, 0xb0, 0x26, 0x00]
+    );
+}
+
+#[test]
+fn test_drum_part_bank() {
+    let mut state = ControllerState {
+        drums: true,
+        ..ControllerState::default()
+    };
+    // Bank Select can't take the drum kit away, other controllers pass.
+    let raw = [0xb1, 0x00, 0x01, 0x20, 0x00, 0x07, 0x64];
+    assert_eq!(process_bytes(&mut state, &raw), [0xb1, 0x07, 0x64]);
+}
+
+#[test]
+fn test_seek_across_data_increment() {
+    use super::{loader::test_soundfont, midi_sequencer::MidiSequencer};
+    use midi_msg::{Channel, ChannelVoiceMsg, ControlChange, MidiFile, Track};
+    use std::time::Duration;
+
+    let cc = |control, value| MidiMsg::ChannelVoice {
+        channel: Channel::Ch1,
+        msg: ChannelVoiceMsg::ControlChange {
+            control: ControlChange::CC { control, value },
+        },
+    };
+    // Pitch Bend Sensitivity of 12 semitones, then one cent more.
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    for control in [0x65, 0x64] {
+        midi_file.extend_track(0, cc(control, 0), 0.);
+    }
+    midi_file.extend_track(0, cc(0x06, 12), 0.);
+    midi_file.extend_track(0, cc(0x60, 0), 1.);
+    midi_file.extend_track(0, cc(0x07, 100), 4.);
+
+    let soundfont = test_soundfont(&[(0, 0, false)]);
+    let synth =
+        || MidiSynth::new(Synthesizer::new(&soundfont, &MidiSynth::settings(44100)).unwrap());
+    // Played through, and seeked past the increment at 0.5s.
+    let mut played = synth();
+    let mut sequencer = MidiSequencer::new();
+    sequencer.play(midi_file.clone());
+    sequencer.update_events(&mut played, Duration::from_secs(1));
+    let mut seeked = synth();
+    let mut sequencer = MidiSequencer::new();
+    sequencer.play(midi_file);
+    sequencer.seek_to(&mut seeked, Duration::from_secs(1));
+
+    assert_eq!(played.channels[0].registered[0], 12 << 7 | 1);
+    assert_eq!(seeked.channels[0], played.channels[0]);
+}
@@ -0,0 +1,296 @@
+//! Live mixing: Master gain and per channel volume, mute and solo, adjustable while playing.
+//!
+//! Channel settings are applied in the [`MidiSink`] path: Note ons of silent channels are
+//! dropped, and Channel Volume (CC 7) is scaled before it reaches the synthesizer. The master
+//! volume set by the song's system exclusive messages is combined with the master gain.
+//!
+
+use midi_msg::{Channel, ChannelVoiceMsg, ControlChange, MidiMsg};
+use std::sync::{Arc, Mutex};
+
+use super::{
+    channel_state::{channel_message, split_messages},
+    midi_sequencer::MidiSink,
+    sysex::SysEx,
+};
+
+/// Mix settings of one MIDI channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelMix {
+    /// Scale of the song's channel volume, between 0.0 and [`Mixer::MAX_CHANNEL_VOLUME`].
+    pub volume: f32,
+    pub mute: bool,
+    pub solo: bool,
+}
+
+impl Default for ChannelMix {
+    fn default() -> Self {
+        Self {
+            volume: 1.,
+            mute: false,
+            solo: false,
+        }
+    }
+}
+
+/// Mix settings of the [`Player`](super::Player).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mixer {
+    master_gain_db: f32,
+    channels: [ChannelMix; 16],
+}
+
+impl Mixer {
+    /// Gain at or below this is silence.
+    pub const MIN_GAIN_DB: f32 = -60.;
+    pub const MAX_GAIN_DB: f32 = 12.;
+    pub const MAX_CHANNEL_VOLUME: f32 = 2.;
+
+    pub const fn master_gain_db(&self) -> f32 {
+        self.master_gain_db
+    }
+
+    /// Clamped to [`Self::MIN_GAIN_DB`]..=[`Self::MAX_GAIN_DB`].
+    pub fn set_master_gain_db(&mut self, gain_db: f32) {
+        self.master_gain_db = gain_db.clamp(Self::MIN_GAIN_DB, Self::MAX_GAIN_DB);
+    }
+
+    /// Master gain as a linear factor.
+    pub fn master_amplitude(&self) -> f32 {
+        if self.master_gain_db <= Self::MIN_GAIN_DB {
+            0.
+        } else {
+            10f32.powf(self.master_gain_db / 20.)
+        }
+    }
+
+    /// Settings of `channel` (0-15), `None` if it is out of range.
+    pub fn channel(&self, channel: u8) -> Option<&ChannelMix> {
+        self.channels.get(usize::from(channel))
+    }
+
+    /// Clamped to 0.0..=[`Self::MAX_CHANNEL_VOLUME`], out of range channels are ignored.
+    pub fn set_channel_volume(&mut self, channel: u8, volume: f32) {
+        if let Some(mix) = self.channels.get_mut(usize::from(channel)) {
+            mix.volume = volume.clamp(0., Self::MAX_CHANNEL_VOLUME);
+        }
+    }
+
+    pub fn set_channel_mute(&mut self, channel: u8, mute: bool) {
+        if let Some(mix) = self.channels.get_mut(usize::from(channel)) {
+            mix.mute = mute;
+        }
+    }
+
+    pub fn set_channel_solo(&mut self, channel: u8, solo: bool) {
+        if let Some(mix) = self.channels.get_mut(usize::from(channel)) {
+            mix.solo = solo;
+        }
+    }
+
+    /// A channel is audible unless it is muted, or another channel is soloed.
+    pub fn is_audible(&self, channel: u8) -> bool {
+        let any_solo = self.channels.iter().any(|mix| mix.solo);
+        self.channel(channel)
+            .is_some_and(|mix| !mix.mute && (mix.solo || !any_solo))
+    }
+
+    /// Factor applied to the channel volume of `channel`.
+    fn channel_gain(&self, channel: u8) -> f32 {
+        match self.channel(channel) {
+            Some(mix) if self.is_audible(channel) => mix.volume,
+            _ => 0.,
+        }
+    }
+}
+
+/// Audio thread end of a [`Mixer`] shared with the [`Player`](super::Player).
+#[derive(Debug)]
+pub(crate) struct LiveMixer {
+    shared: Arc<Mutex<Mixer>>,
+    /// Settings currently applied to the sink
+    applied: Mixer,
+    /// Channel volume (CC 7) as set by the song
+    song_volumes: [u8; 16],
+    /// Master volume as set by the song, see [`SysEx::MasterVolume`]
+    song_master_volume: f32,
+}
+
+impl LiveMixer {
+    /// Channel volume after a reset, as defined by General MIDI.
+    const DEFAULT_VOLUME: u8 = 100;
+    const CHANNEL_VOLUME: u8 = 0x07;
+
+    pub fn new(shared: Arc<Mutex<Mixer>>) -> Self {
+        Self {
+            shared,
+            applied: Mixer::default(),
+            song_volumes: [Self::DEFAULT_VOLUME; 16],
+            song_master_volume: 1.,
+        }
+    }
+
+    /// Pick up changes of the shared [`Mixer`], resending the volume of changed channels.
+    ///
+    /// Never blocks: If the [`Player`](super::Player) holds the lock, changes are picked up on
+    /// the next call.
+    pub fn sync<R>(&mut self, event_sink: &mut R)
+    where
+        R: MidiSink,
+    {
+        let Ok(shared) = self.shared.try_lock() else {
+            return;
+        };
+        if *shared == self.applied {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.applied, shared.clone());
+        drop(shared);
+
+        for channel in 0..16 {
+            if previous.channel_gain(channel) != self.applied.channel_gain(channel) {
+                self.send_volume(event_sink, channel);
+            }
+        }
+    }
+
+    pub fn master_amplitude(&self) -> f32 {
+        self.applied.master_amplitude() * self.song_master_volume
+    }
+
+    /// Wrap `event_sink`, so messages sent to it are mixed.
+    pub fn sink<'a, R>(&'a mut self, event_sink: &'a mut R) -> MixingSink<'a, R>
+    where
+        R: MidiSink,
+    {
+        MixingSink {
+            mixer: self,
+            inner: event_sink,
+        }
+    }
+
+    fn send_volume<R>(&self, event_sink: &mut R, channel: u8)
+    where
+        R: MidiSink,
+    {
+        let song_volume = f32::from(self.song_volumes[usize::from(channel)]);
+        let value = (song_volume * self.applied.channel_gain(channel))
+            .round()
+            .min(127.) as u8;
+        let msg = ChannelVoiceMsg::ControlChange {
+            control: ControlChange::CC {
+                control: Self::CHANNEL_VOLUME,
+                value,
+            },
+        };
+        let _ = event_sink.receive_midi(&MidiMsg::ChannelVoice {
+            channel: Channel::from_u8(channel),
+            msg,
+        });
+    }
+}
+
+/// [`MidiSink`] that applies a [`LiveMixer`] before passing messages on.
+///
+/// Channel messages are split into single messages, see [`split_messages`].
+pub(crate) struct MixingSink<'a, R> {
+    mixer: &'a mut LiveMixer,
+    inner: &'a mut R,
+}
+
+impl<R> MidiSink for MixingSink<'_, R>
+where
+    R: MidiSink,
+{
+    fn receive_midi(&mut self, msg: &MidiMsg) -> Result<(), ()> {
+        if let Some(sysex) = SysEx::from_midi(msg) {
+            let result = self.inner.receive_midi(msg);
+            match sysex {
+                SysEx::Reset => self.restore_defaults(),
+                SysEx::MasterVolume(volume) => self.mixer.song_master_volume = volume,
+                _ => (),
+            }
+            return result;
+        }
+        if !(msg.is_channel_voice() || msg.is_channel_mode()) {
+            return self.inner.receive_midi(msg);
+        }
+
+        let mut result = Ok(());
+        for [status, data1, data2] in split_messages(&msg.to_midi()) {
+            let channel = status & 0x0f;
+            match status & 0xf0 {
+                0x90 if data2 > 0 && !self.mixer.applied.is_audible(channel) => (),
+                0xb0 if data1 == LiveMixer::CHANNEL_VOLUME => {
+                    self.mixer.song_volumes[usize::from(channel)] = data2;
+                    self.mixer.send_volume(self.inner, channel);
+                }
+                _ => {
+                    let msg = channel_message([status, data1, data2]);
+                    result = result.and(self.inner.receive_midi(&msg));
+                }
+            }
+        }
+        result
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.restore_defaults();
+    }
+}
+
+impl<R> MixingSink<'_, R>
+where
+    R: MidiSink,
+{
+    /// Forget the volumes set by the song once `inner` was reset, and mix its defaults.
+    fn restore_defaults(&mut self) {
+        self.mixer.song_volumes = [LiveMixer::DEFAULT_VOLUME; 16];
+        self.mixer.song_master_volume = 1.;
+        for channel in 0..16 {
+            if self.mixer.applied.channel_gain(channel) != 1. {
+                self.mixer.send_volume(self.inner, channel);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_mixing_sink() {
+    use super::midi_sequencer::Recorder;
+
+    let shared = Arc::new(Mutex::new(Mixer::default()));
+    let mut mixer = LiveMixer::new(shared.clone());
+    let mut recorder = Recorder::default();
+    {
+        let mut shared = shared.lock().unwrap();
+        shared.set_channel_volume(0, 0.5);
+        shared.set_channel_solo(0, true);
+    }
+    mixer.sync(&mut recorder);
+
+    let voice = |channel, msg| MidiMsg::ChannelVoice { channel, msg };
+    let note_on = ChannelVoiceMsg::NoteOn {
+        note: 60,
+        velocity: 100,
+    };
+    let volume = ChannelVoiceMsg::ControlChange {
+        control: ControlChange::CC {
+            control: 0x07,
+            value: 80,
+        },
+    };
+    let mut sink = mixer.sink(&mut recorder);
+    let _ = sink.receive_midi(&voice(Channel::Ch1, volume));
+    let _ = sink.receive_midi(&voice(Channel::Ch1, note_on));
+    let _ = sink.receive_midi(&voice(Channel::Ch2, note_on));
+
+    // Sync: Channel 1 at half volume, all others silent.
+    assert_eq!(recorder.0[0], [0xb0, 0x07, 50]);
+    assert!((1..16).all(|channel| recorder.0[channel] == [0xb0 | channel as u8, 0x07, 0]));
+    assert_eq!(
+        recorder.0[16..],
+        [vec![0xb0, 0x07, 40], vec![0x90, 60, 100]]
+    );
+}
@@ -0,0 +1,176 @@
+//! Note list: Every note of a song with its start and end, for visualizations such as a piano
+//! roll.
+
+use midi_msg::{ChannelVoiceMsg, MidiFile, MidiMsg};
+use std::{collections::VecDeque, time::Duration};
+
+use super::tempo_map::TempoMap;
+
+/// A note from its note on to its note off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    /// MIDI channel, 0-15
+    pub channel: u8,
+    pub key: u8,
+    pub velocity: u8,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// The notes of a song, ordered by start.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NoteList {
+    notes: Vec<Note>,
+    /// Length of the longest note, bounds the search for notes sounding at some time.
+    longest: Duration,
+}
+
+impl NoteList {
+    /// Pair the note ons and note offs of `midi_file`. A note off ends the earliest note on of
+    /// its key, notes that are never released end with the song.
+    pub fn new(midi_file: &MidiFile, tempo_map: &TempoMap) -> Self {
+        let division = midi_file.header.division;
+        let mut events = vec![];
+        for track in &midi_file.tracks {
+            for event in track.events() {
+                let MidiMsg::ChannelVoice { channel, msg } = event.event else {
+                    continue;
+                };
+                let (key, velocity) = match msg {
+                    ChannelVoiceMsg::NoteOn { note, velocity } => (note, velocity),
+                    // 14-bit velocity, see MIDI CA-031
+                    ChannelVoiceMsg::HighResNoteOn { note, velocity } => {
+                        (note, (velocity >> 7) as u8)
+                    }
+                    ChannelVoiceMsg::NoteOff { note, .. }
+                    | ChannelVoiceMsg::HighResNoteOff { note, .. } => (note, 0),
+                    _ => continue,
+                };
+                let tick = division.beat_or_frame_to_tick(event.beat_or_frame);
+                events.push((tick, channel as u8, key & 0x7f, velocity));
+            }
+        }
+        // Stable: Simultaneous events keep their track and in-track order.
+        events.sort_by_key(|event| event.0);
+
+        let mut notes: Vec<Note> = vec![];
+        let mut sounding = vec![VecDeque::new(); 16 * 128];
+        let mut end = Duration::ZERO;
+        for (tick, channel, key, velocity) in events {
+            let time = tempo_map.tick_to_time(f64::from(tick));
+            end = end.max(time);
+            let open = &mut sounding[usize::from(channel) * 128 + usize::from(key)];
+            if velocity > 0 {
+                open.push_back(notes.len());
+                notes.push(Note {
+                    channel,
+                    key,
+                    velocity,
+                    start: time,
+                    end: time,
+                });
+            } else if let Some(idx) = open.pop_front() {
+                notes[idx].end = time;
+            }
+        }
+        for idx in sounding.into_iter().flatten() {
+            notes[idx].end = end;
+        }
+
+        let longest = notes
+            .iter()
+            .map(|note| note.end - note.start)
+            .max()
+            .unwrap_or_default();
+        Self { notes, longest }
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    /// Notes sounding at some time between `start` and `end`.
+    pub fn between(&self, start: Duration, end: Duration) -> impl Iterator<Item = &Note> {
+        let first = self
+            .notes
+            .partition_point(|note| note.start + self.longest < start);
+        self.notes[first..]
+            .iter()
+            .take_while(move |note| note.start < end)
+            .filter(move |note| note.end > start || note.start >= start)
+    }
+
+    /// Notes sounding at `time`.
+    pub fn sounding_at(&self, time: Duration) -> impl Iterator<Item = &Note> {
+        self.between(time, time + Duration::from_nanos(1))
+            .filter(move |note| note.start <= time && time < note.end)
+    }
+
+    /// Lowest and highest key played, if any.
+    pub fn key_range(&self) -> Option<(u8, u8)> {
+        let low = self.notes.iter().map(|note| note.key).min()?;
+        let high = self.notes.iter().map(|note| note.key).max()?;
+        Some((low, high))
+    }
+}
+
+#[test]
+fn test_note_list() {
+    use midi_msg::{Channel, Track};
+
+    let voice = |channel, msg| MidiMsg::ChannelVoice { channel, msg };
+    let note_on = |note| ChannelVoiceMsg::NoteOn {
+        note,
+        velocity: 100,
+    };
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    midi_file.add_track(Track::default());
+    midi_file.extend_track(0, voice(Channel::Ch1, note_on(60)), 0.);
+    midi_file.extend_track(0, voice(Channel::Ch1, note_on(60)), 1.);
+    // Velocity 0 releases, the earliest note on is ended first.
+    let release = ChannelVoiceMsg::NoteOn {
+        note: 60,
+        velocity: 0,
+    };
+    midi_file.extend_track(0, voice(Channel::Ch1, release), 2.);
+    midi_file.extend_track(0, voice(Channel::Ch1, release), 4.);
+    // Never released
+    midi_file.extend_track(1, voice(Channel::Ch10, note_on(36)), 3.);
+
+    let notes = NoteList::new(&midi_file, &TempoMap::new(&midi_file));
+    // At 120 BPM, a beat is half a second.
+    let span = |note: &Note| (note.channel, note.start.as_millis(), note.end.as_millis());
+    let spans: Vec<_> = notes.notes().iter().map(span).collect();
+    assert_eq!(spans, [(0, 0, 1000), (0, 500, 2000), (9, 1500, 2000)]);
+    assert_eq!(notes.key_range(), Some((36, 60)));
+
+    let sounding: Vec<_> = notes
+        .sounding_at(Duration::from_millis(1600))
+        .map(span)
+        .collect();
+    assert_eq!(sounding, [(0, 500, 2000), (9, 1500, 2000)]);
+    let between: Vec<_> = notes
+        .between(Duration::from_millis(1000), Duration::from_millis(1500))
+        .map(span)
+        .collect();
+    assert_eq!(between, [(0, 500, 2000)]);
+
+    // High resolution velocities keep their MSB.
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    let high_res = ChannelVoiceMsg::HighResNoteOn {
+        note: 64,
+        velocity: 3 << 7 | 0x40,
+    };
+    midi_file.extend_track(0, voice(Channel::Ch2, high_res), 0.);
+    let note_off = ChannelVoiceMsg::NoteOff {
+        note: 64,
+        velocity: 0,
+    };
+    midi_file.extend_track(0, voice(Channel::Ch2, note_off), 1.);
+    let notes = NoteList::new(&midi_file, &TempoMap::new(&midi_file));
+    let note = notes.notes()[0];
+    assert_eq!(notes.notes().len(), 1);
+    assert_eq!((note.velocity, note.end.as_millis()), (3, 500));
+}
@@ -0,0 +1,536 @@
+//! Where the [`Player`](super::Player) sends its audio.
+//!
+//! [`RodioBackend`] plays on a sound device. [`NullBackend`] and [`FileBackend`] need no hardware:
+//! They pull samples on a thread of their own at the pace a device would, so playback behaves the
+//! same on build servers and in tests.
+
+use hound::WavWriter;
+use rodio::{
+    OutputStream, Sink, Source, StreamError,
+    source::{SeekError, UniformSourceIterator},
+};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::{
+    PlayerError,
+    render::{RenderOptions, SampleFormat, quantize},
+};
+
+/// Audio as handed to an [`OutputBackend`].
+pub type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Plays the sources of the [`Player`](super::Player), modeled after [`rodio::Sink`].
+pub trait OutputBackend {
+    /// Queue `source` after what is already playing.
+    fn append(&self, source: BoxedSource);
+
+    /// Resume playing.
+    fn play(&self);
+
+    /// Pause playing, keeping the position.
+    fn pause(&self);
+
+    fn is_paused(&self) -> bool;
+
+    /// Whether every queued source has finished.
+    fn empty(&self) -> bool;
+
+    /// Drop every queued source and pause.
+    fn clear(&self);
+
+    /// Move the playing source to `position`.
+    fn try_seek(&self, position: Duration) -> Result<(), PlayerError>;
+}
+
+/// Plays on a sound device through rodio.
+pub struct RodioBackend {
+    sink: Sink,
+    /// Keeps the device open, if it was opened by [`RodioBackend::open_default`]
+    _stream: Option<OutputStream>,
+}
+
+impl RodioBackend {
+    /// Open the default sound device.
+    ///
+    /// Fails with [`PlayerError::NoDevice`] if there is none.
+    pub fn open_default() -> Result<Self, PlayerError> {
+        let (stream, handle) = OutputStream::try_default().map_err(|err| match err {
+            StreamError::NoDevice => PlayerError::NoDevice,
+            err => PlayerError::AudioOutput(Box::new(err)),
+        })?;
+        let sink = Sink::try_new(&handle).map_err(|err| PlayerError::AudioOutput(Box::new(err)))?;
+        Ok(Self {
+            sink,
+            _stream: Some(stream),
+        })
+    }
+}
+
+/// Play on a sink whose device is kept open by the caller.
+impl From<Sink> for RodioBackend {
+    fn from(sink: Sink) -> Self {
+        Self {
+            sink,
+            _stream: None,
+        }
+    }
+}
+
+impl OutputBackend for RodioBackend {
+    fn append(&self, source: BoxedSource) {
+        self.sink.append(source);
+    }
+
+    fn play(&self) {
+        self.sink.play();
+    }
+
+    fn pause(&self) {
+        self.sink.pause();
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    fn empty(&self) -> bool {
+        self.sink.empty()
+    }
+
+    fn clear(&self) {
+        self.sink.clear();
+    }
+
+    fn try_seek(&self, position: Duration) -> Result<(), PlayerError> {
+        self.sink.try_seek(position).map_err(seek_error)
+    }
+}
+
+/// Consumes samples in real time and discards them.
+pub struct NullBackend(ThreadedBackend);
+
+impl Default for NullBackend {
+    fn default() -> Self {
+        Self(ThreadedBackend::spawn(
+            Discard,
+            |source| source,
+            RealTime::default(),
+        ))
+    }
+}
+
+/// Writes samples into a WAV file in real time, recording what would have been heard.
+pub struct FileBackend(ThreadedBackend);
+
+impl FileBackend {
+    /// Create (or truncate) the WAV file at `path`. Sources are converted to the sample rate of
+    /// `options`.
+    pub fn create<P>(path: P, options: RenderOptions) -> Result<Self, PlayerError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::create_with_clock(path, options, RealTime::default())
+    }
+
+    fn create_with_clock<P, C>(
+        path: P,
+        options: RenderOptions,
+        clock: C,
+    ) -> Result<Self, PlayerError>
+    where
+        P: AsRef<Path>,
+        C: Clock,
+    {
+        let path = path.as_ref().to_path_buf();
+        let spec = options.sample_format.spec(options.sample_rate);
+        let writer =
+            WavWriter::create(&path, spec).map_err(|err| PlayerError::wav(Some(&path), err))?;
+        let writer = WavFile {
+            writer,
+            path,
+            sample_format: options.sample_format,
+        };
+        let sample_rate = options.sample_rate;
+        let convert = move |source| -> BoxedSource {
+            Box::new(UniformSourceIterator::new(
+                source,
+                spec.channels,
+                sample_rate,
+            ))
+        };
+        Ok(Self(ThreadedBackend::spawn(writer, convert, clock)))
+    }
+
+    /// Stop writing and finalize the file.
+    ///
+    /// Dropping a `FileBackend` does the same but ignores errors.
+    pub fn finish(mut self) -> Result<(), PlayerError> {
+        self.0.close()
+    }
+}
+
+macro_rules! delegate_backend {
+    ($backend:ty) => {
+        impl OutputBackend for $backend {
+            fn append(&self, source: BoxedSource) {
+                self.0.append(source);
+            }
+
+            fn play(&self) {
+                self.0.update(|state| state.paused = false);
+            }
+
+            fn pause(&self) {
+                self.0.update(|state| state.paused = true);
+            }
+
+            fn is_paused(&self) -> bool {
+                self.0.update(|state| state.paused)
+            }
+
+            fn empty(&self) -> bool {
+                self.0
+                    .update(|state| state.queue.is_empty() && !state.rendering)
+            }
+
+            fn clear(&self) {
+                self.0.update(|state| {
+                    state.queue.clear();
+                    state.paused = true;
+                    state.generation += 1;
+                });
+            }
+
+            fn try_seek(&self, position: Duration) -> Result<(), PlayerError> {
+                self.0.try_seek(position)
+            }
+        }
+    };
+}
+
+delegate_backend!(NullBackend);
+delegate_backend!(FileBackend);
+
+/// Receives the samples pulled by a [`ThreadedBackend`].
+trait SampleWriter: Send + 'static {
+    fn write(&mut self, samples: &[f32]) -> Result<(), PlayerError>;
+
+    fn finish(self) -> Result<(), PlayerError>;
+}
+
+struct Discard;
+
+impl SampleWriter for Discard {
+    fn write(&mut self, _samples: &[f32]) -> Result<(), PlayerError> {
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), PlayerError> {
+        Ok(())
+    }
+}
+
+struct WavFile {
+    writer: WavWriter<BufWriter<File>>,
+    path: PathBuf,
+    sample_format: SampleFormat,
+}
+
+impl SampleWriter for WavFile {
+    fn write(&mut self, samples: &[f32]) -> Result<(), PlayerError> {
+        for &sample in samples {
+            match self.sample_format {
+                SampleFormat::Int16 => self.writer.write_sample(quantize(sample, 16)),
+                SampleFormat::Int24 => self.writer.write_sample(quantize(sample, 24)),
+                SampleFormat::Float32 => self.writer.write_sample(sample),
+            }
+            .map_err(|err| PlayerError::wav(Some(&self.path), err))?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), PlayerError> {
+        let path = self.path;
+        self.writer
+            .finalize()
+            .map_err(|err| PlayerError::wav(Some(&path), err))
+    }
+}
+
+/// Seek errors of rodio are not `Sync`, so only their message is kept.
+fn seek_error(err: SeekError) -> PlayerError {
+    PlayerError::AudioOutput(err.to_string().into())
+}
+
+#[derive(Default)]
+struct BackendState {
+    /// Playing source first, then the queued ones
+    queue: VecDeque<BoxedSource>,
+    /// The playing source is taken out of `queue` to be rendered
+    rendering: bool,
+    /// Counts the clears, so a source rendered meanwhile is dropped
+    generation: u64,
+    paused: bool,
+    /// The thread stopped, or is asked to
+    closed: bool,
+}
+
+/// [`BackendState`] and the thread handing back the playing source.
+#[derive(Default)]
+struct Shared {
+    state: Mutex<BackendState>,
+    rendered: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, BackendState> {
+        // The thread never panics while holding the lock.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Time passing for a [`ThreadedBackend`].
+trait Clock: Send + 'static {
+    /// Wait until it's time to pull samples, returns how much time passed. `None` stops the
+    /// thread.
+    fn tick(&mut self) -> Option<Duration>;
+}
+
+/// Ticks every [`ThreadedBackend::PERIOD`] in real time.
+struct RealTime {
+    last_tick: Instant,
+}
+
+impl Default for RealTime {
+    fn default() -> Self {
+        Self {
+            last_tick: Instant::now(),
+        }
+    }
+}
+
+impl Clock for RealTime {
+    fn tick(&mut self) -> Option<Duration> {
+        thread::sleep(ThreadedBackend::PERIOD);
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+        Some(elapsed)
+    }
+}
+
+/// Ticks by the durations sent, stops once the sender is dropped.
+#[cfg(test)]
+impl Clock for std::sync::mpsc::Receiver<Duration> {
+    fn tick(&mut self) -> Option<Duration> {
+        self.recv().ok()
+    }
+}
+
+/// Pulls samples from its source on a thread, as fast as a sound device would.
+struct ThreadedBackend {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<Result<(), PlayerError>>>,
+    /// Adapts appended sources to the writer
+    convert: Box<dyn Fn(BoxedSource) -> BoxedSource + Send + Sync>,
+}
+
+impl ThreadedBackend {
+    /// How often samples are pulled
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    fn spawn<W, F, C>(writer: W, convert: F, clock: C) -> Self
+    where
+        W: SampleWriter,
+        F: Fn(BoxedSource) -> BoxedSource + Send + Sync + 'static,
+        C: Clock,
+    {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = shared.clone();
+        Self {
+            shared,
+            thread: Some(thread::spawn(move || {
+                Self::run(&thread_shared, writer, clock)
+            })),
+            convert: Box::new(convert),
+        }
+    }
+
+    fn append(&self, source: BoxedSource) {
+        let source = (self.convert)(source);
+        self.update(|state| {
+            if !state.closed {
+                state.queue.push_back(source);
+            }
+        });
+    }
+
+    fn update<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut BackendState) -> T,
+    {
+        f(&mut self.shared.lock())
+    }
+
+    /// Seek the playing source, once the thread handed it back.
+    fn try_seek(&self, position: Duration) -> Result<(), PlayerError> {
+        let state = self.shared.lock();
+        let mut state = self
+            .shared
+            .rendered
+            .wait_while(state, |state| state.rendering)
+            .unwrap_or_else(PoisonError::into_inner);
+        match state.queue.front_mut() {
+            Some(source) => source.try_seek(position).map_err(seek_error),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the thread and return the first error it ran into.
+    fn close(&mut self) -> Result<(), PlayerError> {
+        self.update(|state| state.closed = true);
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(PlayerError::AudioOutput("output thread panicked".into())),
+            None => Ok(()),
+        }
+    }
+
+    fn run<W, C>(shared: &Shared, mut writer: W, mut clock: C) -> Result<(), PlayerError>
+    where
+        W: SampleWriter,
+        C: Clock,
+    {
+        // Frames owed to the clock that did not fill a whole one yet
+        let mut owed = 0.;
+        let mut samples = Vec::new();
+
+        while let Some(elapsed) = clock.tick() {
+            let mut state = shared.lock();
+            if state.closed {
+                break;
+            }
+            let Some(source) = state.queue.front().filter(|_| !state.paused) else {
+                owed = 0.;
+                continue;
+            };
+            owed += elapsed.as_secs_f64() * f64::from(source.sample_rate());
+            let wanted = owed as usize * usize::from(source.channels());
+            owed = owed.fract();
+
+            // Sources are rendered outside of the lock, so the player is never kept waiting.
+            samples.clear();
+            while samples.len() < wanted {
+                let Some(mut source) = state.queue.pop_front() else {
+                    break;
+                };
+                state.rendering = true;
+                let generation = state.generation;
+                drop(state);
+
+                samples.extend(source.by_ref().take(wanted - samples.len()));
+
+                state = shared.lock();
+                state.rendering = false;
+                shared.rendered.notify_all();
+                if state.generation != generation {
+                    samples.clear();
+                    break;
+                }
+                if samples.len() == wanted {
+                    state.queue.push_front(source);
+                }
+            }
+            drop(state);
+
+            if let Err(err) = writer.write(&samples) {
+                let mut state = shared.lock();
+                state.queue.clear();
+                state.closed = true;
+                return Err(err);
+            }
+        }
+        writer.finish()
+    }
+}
+
+impl Drop for ThreadedBackend {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// `duration` of silence at 1 kHz stereo
+#[cfg(test)]
+fn silence(duration: Duration) -> BoxedSource {
+    let frames = (duration.as_secs_f64() * 1000.) as usize;
+    Box::new(rodio::buffer::SamplesBuffer::new(
+        2,
+        1000,
+        vec![0.; frames * 2],
+    ))
+}
+
+/// Let `elapsed` pass on the clock of a [`ThreadedBackend`], returns once the thread pulled for
+/// it, as it waits for the next tick then.
+#[cfg(test)]
+fn advance(clock: &std::sync::mpsc::SyncSender<Duration>, elapsed: Duration) {
+    clock.send(elapsed).unwrap();
+    clock.send(Duration::ZERO).unwrap();
+}
+
+#[test]
+fn test_null_backend_follows_the_clock() {
+    let (clock, ticks) = std::sync::mpsc::sync_channel(0);
+    let advance = |elapsed| advance(&clock, elapsed);
+    let backend = NullBackend(ThreadedBackend::spawn(Discard, |source| source, ticks));
+    backend.append(silence(Duration::from_millis(200)));
+    assert!(!backend.empty());
+    assert!(!backend.is_paused());
+    advance(Duration::from_millis(150));
+    assert!(!backend.empty());
+    advance(Duration::from_millis(60));
+    assert!(backend.empty());
+
+    backend.pause();
+    backend.append(silence(Duration::from_millis(50)));
+    advance(Duration::from_millis(100));
+    assert!(!backend.empty());
+    backend.try_seek(Duration::from_millis(20)).unwrap();
+    backend.clear();
+    assert!(backend.empty());
+    assert!(backend.is_paused());
+    drop(clock);
+}
+
+#[test]
+fn test_file_backend_writes_wav() {
+    let path = std::env::temp_dir().join(format!(
+        "key-dash-test-file-backend-{}.wav",
+        std::process::id()
+    ));
+    let options = RenderOptions {
+        sample_rate: 2000,
+        sample_format: SampleFormat::Float32,
+    };
+    let (clock, ticks) = std::sync::mpsc::sync_channel(0);
+    let backend = FileBackend::create_with_clock(&path, options, ticks).unwrap();
+    backend.append(silence(Duration::from_millis(100)));
+    advance(&clock, Duration::from_millis(150));
+    drop(clock);
+    backend.finish().unwrap();
+
+    let reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(reader.spec().sample_rate, 2000);
+    // Resampled from 1 kHz
+    assert!((195..=205).contains(&reader.duration()));
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,346 @@
+//! Playlist: An ordered queue of MIDI files with shuffle and repeat modes.
+//!
+//! The playlist only decides what plays next, loading and playing an entry is up to its owner,
+//! see [`loader`](super::loader) and [`Player::set_song`](super::Player::set_song).
+
+use std::{
+    hash::{BuildHasher, RandomState},
+    path::PathBuf,
+    time::Duration,
+};
+use strum::Display;
+
+/// What happens at the end of a song.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Display)]
+pub enum RepeatMode {
+    /// Stop after the last entry.
+    #[default]
+    Off,
+    /// Play the current entry over and over, see [`Player::set_repeat_one`](super::Player::set_repeat_one).
+    One,
+    /// Start over after the last entry.
+    All,
+}
+
+impl RepeatMode {
+    /// Off, all, one, then off again.
+    pub const fn cycle(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+}
+
+/// A song of the playlist: A MIDI file, or one sequence of a format 2 file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    pub path: PathBuf,
+    /// Index of the sequence of a format 2 file, see [`Song::into_sequences`](super::Song::into_sequences).
+    /// `None` until the file is loaded for the first time.
+    pub sequence: Option<usize>,
+    /// Shown instead of the path, the file name until the song is loaded
+    pub title: String,
+    /// Known once the song was loaded
+    pub duration: Option<Duration>,
+}
+
+impl PlaylistEntry {
+    pub fn new(path: PathBuf) -> Self {
+        let title = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        Self {
+            path,
+            sequence: None,
+            title,
+            duration: None,
+        }
+    }
+}
+
+/// Ordered entries, the current one and the order they play in.
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    entries: Vec<PlaylistEntry>,
+    /// Indices of `entries` in play order: In order, or shuffled.
+    order: Vec<usize>,
+    /// Index into `order` of the current entry
+    position: Option<usize>,
+    shuffle: bool,
+    repeat: RepeatMode,
+    /// Xorshift state of the shuffle, never zero
+    seed: u64,
+}
+
+impl Default for Playlist {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            order: vec![],
+            position: None,
+            shuffle: false,
+            repeat: RepeatMode::Off,
+            seed: RandomState::new().hash_one(0u64) | 1,
+        }
+    }
+}
+
+impl Playlist {
+    pub fn entries(&self) -> &[PlaylistEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Index of the current entry in [`Self::entries`].
+    pub fn current(&self) -> Option<usize> {
+        self.position.map(|position| self.order[position])
+    }
+
+    pub fn current_entry(&self) -> Option<&PlaylistEntry> {
+        self.current().map(|idx| &self.entries[idx])
+    }
+
+    /// Update the current entry once it's loaded.
+    pub fn current_entry_mut(&mut self) -> Option<&mut PlaylistEntry> {
+        self.current().map(|idx| &mut self.entries[idx])
+    }
+
+    pub const fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    pub const fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub const fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
+
+    /// Play in random order, starting from the current entry, or in order again.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = shuffle;
+        let current = self.current();
+        self.order = (0..self.entries.len()).collect();
+        if shuffle {
+            self.shuffle_from(0);
+            if let Some(current) = current {
+                let position = self.order.iter().position(|idx| *idx == current);
+                self.order.swap(0, position.unwrap_or(0));
+            }
+        }
+        self.position = current.and_then(|current| self.order.iter().position(|i| *i == current));
+    }
+
+    /// Add `entry` at the end. With shuffle on, it plays at some random time after the current
+    /// entry.
+    pub fn push(&mut self, entry: PlaylistEntry) {
+        let idx = self.entries.len();
+        self.entries.push(entry);
+        let first = self.position.map_or(0, |position| position + 1);
+        let at = if self.shuffle {
+            first + self.random_below(self.order.len() + 1 - first)
+        } else {
+            self.order.len()
+        };
+        self.order.insert(at, idx);
+    }
+
+    /// Remove entry `idx`, the one after it becomes current if `idx` was.
+    pub fn remove(&mut self, idx: usize) -> Option<PlaylistEntry> {
+        if idx >= self.entries.len() {
+            return None;
+        }
+        let entry = self.entries.remove(idx);
+        let position = self.order.iter().position(|i| *i == idx);
+        self.order.retain(|i| *i != idx);
+        for i in &mut self.order {
+            if *i > idx {
+                *i -= 1;
+            }
+        }
+        if let (Some(current), Some(removed)) = (self.position, position) {
+            self.position = match current.cmp(&removed) {
+                std::cmp::Ordering::Less => Some(current),
+                std::cmp::Ordering::Equal => (current < self.order.len()).then_some(current),
+                std::cmp::Ordering::Greater => Some(current - 1),
+            };
+        }
+        Some(entry)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.position = None;
+    }
+
+    /// Replace entry `idx` with `entries`, such as the sequences of a format 2 file. The first one
+    /// becomes current if `idx` was.
+    pub fn expand(&mut self, idx: usize, entries: Vec<PlaylistEntry>) {
+        if idx >= self.entries.len() || entries.is_empty() {
+            return;
+        }
+        let added = entries.len() - 1;
+        self.entries.splice(idx..=idx, entries);
+        let at = self.order.iter().position(|i| *i == idx).unwrap_or(0);
+        for i in &mut self.order {
+            if *i > idx {
+                *i += added;
+            }
+        }
+        // The sequences play in order, also with shuffle on.
+        self.order.splice(at + 1..at + 1, idx + 1..=idx + added);
+        if self.position.is_some_and(|position| position > at) {
+            self.position = self.position.map(|position| position + added);
+        }
+    }
+
+    /// Make entry `idx` current.
+    pub fn jump(&mut self, idx: usize) -> Option<&PlaylistEntry> {
+        let position = self.order.iter().position(|i| *i == idx)?;
+        self.position = Some(position);
+        self.current_entry()
+    }
+
+    /// Move on to the following entry as the user asked, past the end only with
+    /// [`RepeatMode::All`]. Stays on the last entry and returns `None` at the end.
+    pub fn skip_forward(&mut self) -> Option<&PlaylistEntry> {
+        if self.order.is_empty() {
+            return None;
+        }
+        let next = self.position.map_or(0, |position| position + 1);
+        let next = if next < self.order.len() {
+            next
+        } else if self.repeat == RepeatMode::All {
+            if self.shuffle {
+                self.reshuffle();
+            }
+            0
+        } else {
+            return None;
+        };
+        self.position = Some(next);
+        self.current_entry()
+    }
+
+    /// Move back to the preceding entry, to the last one from the first with
+    /// [`RepeatMode::All`].
+    pub fn skip_back(&mut self) -> Option<&PlaylistEntry> {
+        let position = self.position?;
+        let previous = match position.checked_sub(1) {
+            Some(previous) => previous,
+            None if self.repeat == RepeatMode::All => self.order.len() - 1,
+            None => return None,
+        };
+        self.position = Some(previous);
+        self.current_entry()
+    }
+
+    /// The entry to play after the current one ended: The same one with [`RepeatMode::One`],
+    /// else as [`Self::skip_forward`].
+    pub fn advance(&mut self) -> Option<&PlaylistEntry> {
+        if self.repeat == RepeatMode::One && self.position.is_some() {
+            return self.current_entry();
+        }
+        self.skip_forward()
+    }
+
+    /// A new random order for the next round.
+    fn reshuffle(&mut self) {
+        self.shuffle_from(0);
+        self.position = None;
+    }
+
+    /// Fisher-Yates shuffle of `order[first..]`.
+    fn shuffle_from(&mut self, first: usize) {
+        for i in (first + 1..self.order.len()).rev() {
+            let j = first + self.random_below(i - first + 1);
+            self.order.swap(i, j);
+        }
+    }
+
+    /// Xorshift64, good enough to shuffle songs.
+    fn random_below(&mut self, bound: usize) -> usize {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed % bound.max(1) as u64) as usize
+    }
+}
+
+#[cfg(test)]
+fn test_playlist_of(len: usize) -> Playlist {
+    let mut playlist = Playlist::default();
+    for i in 0..len {
+        playlist.push(PlaylistEntry::new(format!("{i}.mid").into()));
+    }
+    playlist
+}
+
+#[test]
+fn test_playlist_repeat() {
+    let mut playlist = test_playlist_of(3);
+    let title = |entry: Option<&PlaylistEntry>| entry.map(|entry| entry.title.clone());
+    assert_eq!(title(playlist.skip_forward()), Some("0.mid".to_string()));
+    assert_eq!(title(playlist.jump(2)), Some("2.mid".to_string()));
+    assert_eq!(playlist.advance(), None);
+    assert_eq!(playlist.current(), Some(2));
+
+    playlist.set_repeat(RepeatMode::One);
+    assert_eq!(title(playlist.advance()), Some("2.mid".to_string()));
+    playlist.set_repeat(RepeatMode::All);
+    assert_eq!(title(playlist.advance()), Some("0.mid".to_string()));
+    assert_eq!(title(playlist.skip_back()), Some("2.mid".to_string()));
+
+    // Removing the current entry makes the following one current.
+    playlist.jump(1);
+    playlist.remove(1);
+    assert_eq!(title(playlist.current_entry()), Some("2.mid".to_string()));
+    assert_eq!(playlist.entries().len(), 2);
+}
+
+#[test]
+fn test_playlist_shuffle() {
+    let mut playlist = test_playlist_of(20);
+    playlist.jump(7);
+    playlist.set_shuffle(true);
+    assert_eq!(playlist.current(), Some(7));
+    // Every entry plays once per round.
+    let mut played = vec![7];
+    while let Some(entry) = playlist.skip_forward() {
+        played.push(entry.title.trim_end_matches(".mid").parse().unwrap());
+    }
+    played.sort_unstable();
+    assert_eq!(played, (0..20).collect::<Vec<_>>());
+
+    let last = playlist.current();
+    playlist.set_shuffle(false);
+    assert_eq!(playlist.current(), last);
+    assert_eq!(playlist.order, (0..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_playlist_expand() {
+    let mut playlist = test_playlist_of(2);
+    playlist.jump(0);
+    let sequences = (0..3)
+        .map(|i| PlaylistEntry {
+            sequence: Some(i),
+            ..PlaylistEntry::new("0.mid".into())
+        })
+        .collect();
+    playlist.expand(0, sequences);
+    assert_eq!(playlist.entries().len(), 4);
+    assert_eq!(playlist.current_entry().unwrap().sequence, Some(0));
+    assert_eq!(playlist.skip_forward().unwrap().sequence, Some(1));
+    assert_eq!(playlist.skip_forward().unwrap().sequence, Some(2));
+    assert_eq!(playlist.skip_forward().unwrap().title, "1.mid");
+}
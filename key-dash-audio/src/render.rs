@@ -0,0 +1,207 @@
+//! Offline rendering: Drives a [`MidiSource`] as fast as possible without an
+//! audio device and writes the result into a WAV file.
+//!
+
+use hound::{WavSpec, WavWriter};
+use midi_msg::MidiFile;
+use rustysynth::SoundFont;
+use std::{
+    io::{Seek, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use super::{error::PlayerError, midi_source::MidiSource};
+
+/// Sample encoding of the rendered WAV file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 16-bit signed integer PCM
+    #[default]
+    Int16,
+    /// 24-bit signed integer PCM
+    Int24,
+    /// 32-bit IEEE float
+    Float32,
+}
+
+impl SampleFormat {
+    pub(crate) fn spec(self, sample_rate: u32) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            Self::Int16 => (16, hound::SampleFormat::Int),
+            Self::Int24 => (24, hound::SampleFormat::Int),
+            Self::Float32 => (32, hound::SampleFormat::Float),
+        };
+        WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+/// Options for [`render_to_wav`] and [`render_to_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    pub sample_rate: u32,
+    pub sample_format: SampleFormat,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: MidiSource::DEFAULT_SAMPLE_RATE as u32,
+            sample_format: SampleFormat::default(),
+        }
+    }
+}
+
+/// Renders `midi_file` with `soundfont` into `writer` as a stereo WAV stream.
+///
+/// Notes still releasing after the last event are rendered until they fall silent, see
+/// [`MidiSource`]. Returns the duration of the rendered audio.
+pub fn render_to_wav<W>(
+    soundfont: &Arc<SoundFont>,
+    midi_file: MidiFile,
+    writer: W,
+    options: RenderOptions,
+) -> Result<Duration, PlayerError>
+where
+    W: Write + Seek,
+{
+    write_wav(soundfont, midi_file, writer, options, None)
+}
+
+/// Like [`render_to_wav`], but creates (or truncates) the file at `path`.
+pub fn render_to_file<P>(
+    soundfont: &Arc<SoundFont>,
+    midi_file: MidiFile,
+    path: P,
+    options: RenderOptions,
+) -> Result<Duration, PlayerError>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file = std::fs::File::create(path).map_err(|source| PlayerError::Write {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    write_wav(
+        soundfont,
+        midi_file,
+        std::io::BufWriter::new(file),
+        options,
+        Some(path),
+    )
+}
+
+/// [`render_to_wav`] into the file at `path`, if `writer` writes one.
+fn write_wav<W>(
+    soundfont: &Arc<SoundFont>,
+    midi_file: MidiFile,
+    writer: W,
+    options: RenderOptions,
+    path: Option<&Path>,
+) -> Result<Duration, PlayerError>
+where
+    W: Write + Seek,
+{
+    let wav_error = |err| PlayerError::wav(path, err);
+    let source = MidiSource::with_sample_rate(soundfont, midi_file, options.sample_rate as i32)?;
+    let mut wav = WavWriter::new(writer, options.sample_format.spec(options.sample_rate))
+        .map_err(wav_error)?;
+    let mut write = |sample: f32| match options.sample_format {
+        SampleFormat::Int16 => wav.write_sample(quantize(sample, 16)),
+        SampleFormat::Int24 => wav.write_sample(quantize(sample, 24)),
+        SampleFormat::Float32 => wav.write_sample(sample),
+    };
+
+    for sample in source {
+        write(sample).map_err(wav_error)?;
+    }
+
+    let frames = wav.duration();
+    wav.finalize().map_err(wav_error)?;
+    Ok(Duration::from_secs_f64(
+        f64::from(frames) / f64::from(options.sample_rate),
+    ))
+}
+
+/// Convert a float sample into a signed integer of `bits` width, clipping out of range values.
+pub(crate) fn quantize(sample: f32, bits: u32) -> i32 {
+    let max = ((1i32 << (bits - 1)) - 1) as f32;
+    (sample.clamp(-1., 1.) * max).round() as i32
+}
+
+#[test]
+fn test_quantize() {
+    assert_eq!(quantize(0., 16), 0);
+    assert_eq!(quantize(1., 16), i32::from(i16::MAX));
+    assert_eq!(quantize(-1., 16), -i32::from(i16::MAX));
+    assert_eq!(quantize(0.5, 24), 4_194_304);
+    // Out of range samples clip.
+    assert_eq!(quantize(1.5, 24), (1 << 23) - 1);
+    assert_eq!(quantize(-2., 24), -((1 << 23) - 1));
+}
+
+#[test]
+fn test_render_to_wav_renders_the_tail() {
+    // A note of 100 ms, which takes a second to release
+    let track = [
+        0x00, 0x90, 60, 100, 0x60, 0x80, 60, 0, 0x00, 0xff, 0x2f, 0x00,
+    ];
+    let bytes = [
+        b"MThd".as_slice(),
+        &[0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xe0],
+        b"MTrk",
+        &(track.len() as u32).to_be_bytes(),
+        &track,
+    ]
+    .concat();
+    let midi_file = super::loader::parse_midi(&bytes, "note.mid")
+        .unwrap()
+        .midi_file;
+    let soundfont = super::loader::test_soundfont(&[(0, 0, true)]);
+    let options = RenderOptions {
+        sample_rate: 16000,
+        sample_format: SampleFormat::Int16,
+    };
+
+    let mut wav = std::io::Cursor::new(vec![]);
+    let duration = render_to_wav(&soundfont, midi_file, &mut wav, options).unwrap();
+    assert!(duration > Duration::from_millis(500), "{duration:?}");
+    assert!(duration < MidiSource::MAX_TAIL);
+
+    wav.set_position(0);
+    let mut reader = hound::WavReader::new(wav).unwrap();
+    assert_eq!(reader.spec(), SampleFormat::Int16.spec(16000));
+    assert_eq!(
+        Duration::from_secs_f64(f64::from(reader.duration()) / 16000.),
+        duration
+    );
+    // The note sounds, and the tail ends in silence.
+    let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+    assert!(samples.iter().any(|&sample| sample.abs() > 100));
+    assert!(
+        samples[samples.len() - 16..]
+            .iter()
+            .all(|&sample| sample.abs() < 4)
+    );
+}
+
+#[test]
+fn test_render_to_file_error() {
+    let soundfont = super::loader::test_soundfont(&[(0, 0, true)]);
+    let path = std::env::temp_dir().join("key-dash-missing/out.wav");
+    let err = render_to_file(
+        &soundfont,
+        MidiFile::default(),
+        &path,
+        RenderOptions::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(err, PlayerError::Write { path: err_path, .. } if err_path == path));
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use super::{error::PlayerError, tempo_map::BarBeat};
+
+/// Section of the song that is played over and over, in song time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopRegion {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// Start or end of a [`LoopRegion`], as given by the user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopPoint {
+    Time(Duration),
+    BarBeat(BarBeat),
+}
+
+/// Playback settings that belong to a song, remembered by the [`Player`](super::Player).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SongSettings {
+    speed: f64,
+    transpose: i8,
+    loop_region: Option<LoopRegion>,
+}
+
+impl Default for SongSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.,
+            transpose: 0,
+            loop_region: None,
+        }
+    }
+}
+
+impl SongSettings {
+    pub const MIN_SPEED: f64 = 0.25;
+    pub const MAX_SPEED: f64 = 4.;
+    /// Transposition limit in semitones, in both directions.
+    pub const MAX_TRANSPOSE: i8 = 24;
+
+    /// Factor of the song's tempo, 1.0 is unchanged.
+    pub const fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Clamped to [`Self::MIN_SPEED`]..=[`Self::MAX_SPEED`].
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+    }
+
+    /// Semitones notes of non-drum channels are shifted by.
+    pub const fn transpose(&self) -> i8 {
+        self.transpose
+    }
+
+    /// Clamped to ±[`Self::MAX_TRANSPOSE`].
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.transpose = semitones.clamp(-Self::MAX_TRANSPOSE, Self::MAX_TRANSPOSE);
+    }
+
+    pub const fn loop_region(&self) -> Option<LoopRegion> {
+        self.loop_region
+    }
+
+    /// Loop `region`, or play on if `None`. Empty regions are ignored.
+    pub fn set_loop_region(&mut self, region: Option<LoopRegion>) {
+        self.loop_region = region.filter(|region| region.start < region.end);
+    }
+}
+
+/// [`SongSettings`] of every song they were changed for, by [`Song::key`], written into a file
+/// once there is one, see [`SettingsStore::save`].
+///
+/// The file has a line per song: Speed, transposition, loop start and end in nanoseconds or `-`,
+/// and the key, separated by tabs.
+///
+/// [`Song::key`]: super::Song::key
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SettingsStore {
+    settings: HashMap<String, SongSettings>,
+    file: Option<PathBuf>,
+    /// Time of the last change that isn't written yet
+    changed: Option<Instant>,
+}
+
+impl SettingsStore {
+    /// Settings of the song `key`, the defaults if it has none.
+    pub fn get(&self, key: &str) -> SongSettings {
+        self.settings.get(key).copied().unwrap_or_default()
+    }
+
+    /// Keep the settings in `file` from now on, reading the ones it holds. Settings changed
+    /// before take precedence. A missing file is created with the next save.
+    pub fn set_file(&mut self, file: PathBuf) -> Result<(), PlayerError> {
+        match std::fs::read_to_string(&file) {
+            Ok(text) => {
+                let mut settings = parse_settings(&text);
+                settings.extend(self.settings.drain());
+                self.settings = settings;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(source) => return Err(PlayerError::Io { path: file, source }),
+        }
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Remember `settings` for the song `key`, the file is written with the next save.
+    pub fn insert(&mut self, key: &str, settings: SongSettings) {
+        if settings == self.get(key) {
+            return;
+        }
+        if settings == SongSettings::default() {
+            self.settings.remove(key);
+        } else {
+            self.settings.insert(key.to_string(), settings);
+        }
+        self.changed = Some(Instant::now());
+    }
+
+    /// Write the file if settings changed, and the last change is at least `delay` old. A failed
+    /// write is tried again `delay` later.
+    pub fn save(&mut self, delay: Duration) -> Result<(), PlayerError> {
+        let (Some(file), Some(changed)) = (&self.file, self.changed) else {
+            return Ok(());
+        };
+        if changed.elapsed() < delay {
+            return Ok(());
+        }
+        let write = || {
+            if let Some(dir) = file.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(file, format_settings(&self.settings))
+        };
+        let result = write().map_err(|source| PlayerError::Write {
+            path: file.clone(),
+            source,
+        });
+        self.changed = result.is_err().then(Instant::now);
+        result
+    }
+}
+
+/// The settings of the lines of a [`SettingsStore`] file, skipping the ones that don't parse.
+fn parse_settings(text: &str) -> HashMap<String, SongSettings> {
+    let parse_line = |line: &str| {
+        let mut fields = line.splitn(5, '\t');
+        let mut settings = SongSettings::default();
+        settings.set_speed(fields.next()?.parse().ok()?);
+        settings.set_transpose(fields.next()?.parse().ok()?);
+        let (start, end) = (fields.next()?, fields.next()?);
+        if start != "-" {
+            settings.set_loop_region(Some(LoopRegion {
+                start: Duration::from_nanos(start.parse().ok()?),
+                end: Duration::from_nanos(end.parse().ok()?),
+            }));
+        }
+        Some((fields.next()?.to_string(), settings))
+    };
+    text.lines().filter_map(parse_line).collect()
+}
+
+/// Lines of a [`SettingsStore`] file, sorted by key. Keys that span lines are left out.
+fn format_settings(settings: &HashMap<String, SongSettings>) -> String {
+    let mut settings: Vec<_> = settings
+        .iter()
+        .filter(|(key, _)| !key.contains(['\n', '\r']))
+        .collect();
+    settings.sort_unstable_by_key(|(key, _)| *key);
+    settings
+        .into_iter()
+        .map(|(key, settings)| {
+            let (start, end) = settings.loop_region.map_or_else(
+                || ("-".to_string(), "-".to_string()),
+                |region| {
+                    (
+                        region.start.as_nanos().to_string(),
+                        region.end.as_nanos().to_string(),
+                    )
+                },
+            );
+            format!(
+                "{}\t{}\t{start}\t{end}\t{key}\n",
+                settings.speed, settings.transpose
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_settings_store() {
+    let file = std::env::temp_dir()
+        .join(format!("key-dash-test-{}", std::process::id()))
+        .join("song-settings");
+    let _ = std::fs::remove_file(&file);
+
+    let mut settings = SongSettings::default();
+    settings.set_speed(0.75);
+    settings.set_transpose(-3);
+    settings.set_loop_region(Some(LoopRegion {
+        start: Duration::from_millis(1500),
+        end: Duration::from_nanos(4_000_000_001),
+    }));
+    let mut store = SettingsStore::default();
+    store.insert("/songs/a\tb.mid", settings);
+    store.set_file(file.clone()).unwrap();
+    let mut speed = SongSettings::default();
+    speed.set_speed(2.);
+    store.insert("/songs/c.mid #2", speed);
+    // Written once the last change is old enough.
+    store.save(Duration::from_secs(60)).unwrap();
+    assert!(!file.exists());
+    store.save(Duration::ZERO).unwrap();
+    assert!(store.changed.is_none());
+
+    // Lines are sorted by key, which may hold tabs.
+    let text = std::fs::read_to_string(&file).unwrap();
+    assert!(text.starts_with("0.75\t-3\t1500000000\t4000000001\t/songs/a\tb.mid\n"));
+    assert!(text.ends_with("\t/songs/c.mid #2\n"));
+
+    // Settings are read back from the file, also by keys with tabs.
+    let mut store = SettingsStore::default();
+    store.set_file(file.clone()).unwrap();
+    assert_eq!(store.get("/songs/a\tb.mid"), settings);
+    assert_eq!(store.get("/songs/c.mid #2"), speed);
+    assert_eq!(store.get("/songs/c.mid"), SongSettings::default());
+
+    // Default settings aren't kept, broken lines are skipped.
+    store.insert("/songs/c.mid #2", SongSettings::default());
+    store.save(Duration::ZERO).unwrap();
+    let text = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(text.lines().count(), 1);
+    std::fs::write(&file, format!("{text}fast\t0\t-\t-\t/songs/d.mid\n")).unwrap();
+    let mut store = SettingsStore::default();
+    store.set_file(file.clone()).unwrap();
+    assert_eq!(store.settings.len(), 1);
+    let _ = std::fs::remove_dir_all(file.parent().unwrap());
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+use strum::Display;
+
+use super::{song_settings::LoopRegion, tempo_map::BarBeat};
+
+/// What the [`Player`](super::Player) is doing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Display)]
+pub enum PlaybackState {
+    /// Nothing is queued.
+    #[default]
+    Stopped,
+    Playing,
+    Paused,
+    /// The song played to its end.
+    Finished,
+}
+
+/// Snapshot of the [`Player`](super::Player), see [`Player::status`](super::Player::status).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PlayerStatus {
+    pub state: PlaybackState,
+    pub position: Duration,
+    /// Length of the current song, if any.
+    pub duration: Option<Duration>,
+    /// Bar and beat at `position`, if a song is loaded.
+    pub bar_beat: Option<BarBeat>,
+    /// Name of the loaded MIDI file.
+    pub midi_name: Option<String>,
+    /// Bank name of the loaded SoundFont.
+    pub soundfont_name: Option<String>,
+    /// Master gain in dB, see [`Mixer`](super::Mixer).
+    pub master_gain_db: f32,
+    /// Playback speed, 1.0 is unchanged.
+    pub speed: f64,
+    /// Transposition of non-drum channels in semitones.
+    pub transpose: i8,
+    pub loop_region: Option<LoopRegion>,
+    /// Whether the song starts over when it ends.
+    pub repeat_one: bool,
+}
+
+impl PlayerStatus {
+    /// Fraction of the song played, between 0.0 and 1.0.
+    pub fn progress(&self) -> f64 {
+        match self.duration {
+            Some(duration) if !duration.is_zero() => {
+                (self.position.as_secs_f64() / duration.as_secs_f64()).clamp(0., 1.)
+            }
+            _ => 0.,
+        }
+    }
+}
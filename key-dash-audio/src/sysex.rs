@@ -0,0 +1,199 @@
+//! System exclusive messages: The subset of GM, GS and XG messages that changes how a song sounds.
+//!
+
+use midi_msg::MidiMsg;
+
+/// A recognized system exclusive message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SysEx {
+    /// GM System On/Off, GM2 System On, GS Reset or XG System On: Every channel returns to its
+    /// defaults.
+    Reset,
+    /// Amplitude factor of the whole synthesizer, 0 to 1
+    MasterVolume(f32),
+    /// Semitones added to every non-drum note
+    MasterCoarseTuning(i8),
+    /// Fraction of a semitone added to every non-drum note, -1 to 1
+    MasterFineTuning(f32),
+    /// Play `channel` (0-15) with drum kits, or with melodic instruments.
+    DrumPart { channel: u8, drums: bool },
+}
+
+impl SysEx {
+    const UNIVERSAL_NON_REAL_TIME: u8 = 0x7e;
+    const UNIVERSAL_REAL_TIME: u8 = 0x7f;
+    const ROLAND: u8 = 0x41;
+    const YAMAHA: u8 = 0x43;
+
+    /// Recognize `msg`, `None` if it isn't a system exclusive message this module knows.
+    pub fn from_midi(msg: &MidiMsg) -> Option<Self> {
+        match msg {
+            MidiMsg::SystemExclusive { .. } => {
+                let raw = msg.to_midi();
+                Self::parse(raw.strip_prefix(&[0xf0])?)
+            }
+            // `midi_msg` can't parse most universal messages and keeps them as they are.
+            MidiMsg::Invalid { bytes, .. } => Self::parse(bytes),
+            _ => None,
+        }
+    }
+
+    /// Recognize the bytes following F0, with or without the closing F7.
+    fn parse(body: &[u8]) -> Option<Self> {
+        let body = body.strip_suffix(&[0xf7]).unwrap_or(body);
+        match *body {
+            // GM1 System On, GM System Off, GM2 System On
+            [Self::UNIVERSAL_NON_REAL_TIME, _device, 0x09, 0x01..=0x03] => Some(Self::Reset),
+            [Self::UNIVERSAL_REAL_TIME, _device, 0x04, sub_id, lsb, msb] => {
+                let value = u16::from(lsb & 0x7f) | u16::from(msb & 0x7f) << 7;
+                match sub_id {
+                    0x01 => Some(Self::MasterVolume(f32::from(value) / 16383.)),
+                    0x03 => Some(Self::MasterFineTuning((f32::from(value) - 8192.) / 8192.)),
+                    0x04 => Some(Self::MasterCoarseTuning(msb as i8 - 0x40)),
+                    _ => None,
+                }
+            }
+            [Self::ROLAND, _device, 0x42, 0x12, ref rest @ ..] => Self::parse_gs(rest),
+            [Self::YAMAHA, device, 0x4c, ref rest @ ..] if device & 0xf0 == 0x10 => {
+                Self::parse_xg(rest)
+            }
+            _ => None,
+        }
+    }
+
+    /// Roland GS data set: Address, data and checksum.
+    fn parse_gs(rest: &[u8]) -> Option<Self> {
+        let (&checksum, payload) = rest.split_last()?;
+        let sum = payload.iter().map(|byte| u32::from(*byte)).sum::<u32>() + u32::from(checksum);
+        if sum & 0x7f != 0 {
+            return None;
+        }
+        match *payload {
+            // GS Reset, and the System Mode Set of later models
+            [0x40 | 0x00, 0x00, 0x7f, _] => Some(Self::Reset),
+            [0x40, 0x00, 0x00, ref nibbles @ ..] => Self::nibble_tuning(nibbles),
+            [0x40, 0x00, 0x04, volume] => Some(Self::MasterVolume(f32::from(volume) / 127.)),
+            [0x40, 0x00, 0x05, key_shift] => Some(Self::MasterCoarseTuning(key_shift as i8 - 0x40)),
+            // Use for Rhythm Part: Blocks are numbered from part 10, then 1-9 and 11-16.
+            [0x40, block @ 0x10..=0x1f, 0x15, map] => {
+                let channel = match block & 0x0f {
+                    0 => 9,
+                    part @ 1..=9 => part - 1,
+                    part => part,
+                };
+                Some(Self::DrumPart {
+                    channel,
+                    drums: map != 0,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Yamaha XG parameter change: Address and data.
+    fn parse_xg(rest: &[u8]) -> Option<Self> {
+        match *rest {
+            // XG System On, XG All Parameter Reset
+            [0x00, 0x00, 0x7e | 0x7f, 0x00] => Some(Self::Reset),
+            [0x00, 0x00, 0x00, ref nibbles @ ..] => Self::nibble_tuning(nibbles),
+            [0x00, 0x00, 0x04, volume] => Some(Self::MasterVolume(f32::from(volume) / 127.)),
+            [0x00, 0x00, 0x06, transpose] => Some(Self::MasterCoarseTuning(transpose as i8 - 0x40)),
+            [0x08, part @ 0x00..=0x0f, 0x07, mode] => Some(Self::DrumPart {
+                channel: part,
+                drums: mode != 0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Master tune of GS and XG: Four nibbles of 0.1 cent steps, centered on 0x400.
+    fn nibble_tuning(nibbles: &[u8]) -> Option<Self> {
+        let &[n0, n1, n2, n3] = nibbles else {
+            return None;
+        };
+        let value = [n0, n1, n2, n3]
+            .iter()
+            .fold(0u16, |value, nibble| value << 4 | u16::from(nibble & 0x0f));
+        Some(Self::MasterFineTuning(
+            ((f32::from(value) - 1024.) / 1000.).clamp(-1., 1.),
+        ))
+    }
+
+    /// Whether a later `self` overrides `other`, so only the last one needs to be restored.
+    pub fn replaces(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::DrumPart { channel, .. }, Self::DrumPart { channel: other, .. }) => {
+                channel == other
+            }
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+#[test]
+fn test_sysex_parse() {
+    let parse = |raw: &[u8]| SysEx::parse(&raw[1..]);
+    assert_eq!(
+        parse(&[0xf0, 0x7e, 0x7f, 0x09, 0x01, 0xf7]),
+        Some(SysEx::Reset)
+    );
+    assert_eq!(
+        parse(&[
+            0xf0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7f, 0x00, 0x41, 0xf7
+        ]),
+        Some(SysEx::Reset)
+    );
+    // Bad checksum
+    assert_eq!(
+        parse(&[
+            0xf0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x00, 0x7f, 0x00, 0x40, 0xf7
+        ]),
+        None
+    );
+    assert_eq!(
+        parse(&[0xf0, 0x43, 0x10, 0x4c, 0x00, 0x00, 0x7e, 0x00, 0xf7]),
+        Some(SysEx::Reset)
+    );
+    assert_eq!(
+        parse(&[0xf0, 0x7f, 0x7f, 0x04, 0x01, 0x7f, 0x7f, 0xf7]),
+        Some(SysEx::MasterVolume(1.))
+    );
+    assert_eq!(
+        parse(&[0xf0, 0x7f, 0x7f, 0x04, 0x03, 0x00, 0x60, 0xf7]),
+        Some(SysEx::MasterFineTuning(0.5))
+    );
+    assert_eq!(
+        parse(&[0xf0, 0x7f, 0x7f, 0x04, 0x04, 0x00, 0x3e, 0xf7]),
+        Some(SysEx::MasterCoarseTuning(-2))
+    );
+    // GS Use for Rhythm Part of part 11
+    assert_eq!(
+        parse(&[
+            0xf0, 0x41, 0x10, 0x42, 0x12, 0x40, 0x1a, 0x15, 0x02, 0x0f, 0xf7
+        ]),
+        Some(SysEx::DrumPart {
+            channel: 10,
+            drums: true
+        })
+    );
+    // XG master tune of +10 cents
+    assert_eq!(
+        parse(&[
+            0xf0, 0x43, 0x10, 0x4c, 0x00, 0x00, 0x00, 0x00, 0x04, 0x06, 0x04, 0xf7
+        ]),
+        Some(SysEx::MasterFineTuning(0.1))
+    );
+
+    // Universal messages that `midi_msg` can't parse are still recognized.
+    let smf = [
+        b"MThd".as_slice(),
+        &[0, 0, 0, 6, 0, 0, 0, 1, 0, 96],
+        b"MTrk",
+        &[0, 0, 0, 12, 0x00, 0xf0, 0x05, 0x7e, 0x7f, 0x09, 0x01, 0xf7],
+        &[0x00, 0xff, 0x2f, 0x00],
+    ]
+    .concat();
+    let midi_file = midi_msg::MidiFile::from_midi(&smf).unwrap();
+    let msg = &midi_file.tracks[0].events()[0].event;
+    assert_eq!(SysEx::from_midi(msg), Some(SysEx::Reset));
+}
@@ -0,0 +1,323 @@
+use midi_msg::{Division, Meta, MidiFile, MidiMsg, TimeCodeType};
+use std::time::Duration;
+
+/// A section of the song with constant tempo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TempoSegment {
+    /// First tick of the segment
+    tick: f64,
+    /// Seconds from song start to `tick`
+    seconds: f64,
+    /// Beats from song start to `tick`
+    beats: f64,
+    seconds_per_tick: f64,
+    beats_per_tick: f64,
+    bpm: f64,
+}
+
+/// A section of the song with constant time signature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct MeterSegment {
+    /// First beat of the segment, in quarter notes
+    beat: f64,
+    /// Bars from song start to `beat`
+    bar: f64,
+    quarters_per_bar: f64,
+    /// Length of a beat of the time signature, in quarter notes
+    quarters_per_beat: f64,
+}
+
+/// Position in bars and beats of the time signature, both counted from 1 as in sheet music.
+///
+/// `beat` is fractional, so 2.5 is halfway through the second beat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarBeat {
+    pub bar: u32,
+    pub beat: f64,
+}
+
+/// Tempo map of a [`MidiFile`], built once when a file is loaded.
+///
+/// Converts between ticks, beats and wall-clock time in O(log n) by binary searching the
+/// [`Meta::SetTempo`] changes of all tracks. Beats are quarter notes, bars follow the
+/// [`Meta::TimeSignature`] changes.
+///
+/// ### Time Division Handling
+///
+/// - [`Division::TicksPerQuarterNote`]: Tick length depends on tempo, beat length doesn't.
+/// - [`Division::TimeCode`]: Tick length is fixed by the SMPTE rate, beat length depends on tempo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    /// Ordered by tick, seconds and beats alike. Empty if no file is loaded.
+    segments: Vec<TempoSegment>,
+    /// Ordered by beat and bar alike. Empty if no file is loaded.
+    meters: Vec<MeterSegment>,
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl TempoMap {
+    /// Tempo until the first [`Meta::SetTempo`].
+    pub const DEFAULT_BPM: f64 = 120.;
+
+    /// Time signature until the first [`Meta::TimeSignature`], as numerator and denominator.
+    pub const DEFAULT_TIME_SIGNATURE: (u8, u16) = (4, 4);
+
+    /// Tempo map without a file: every conversion yields zero.
+    pub const fn empty() -> Self {
+        Self {
+            segments: vec![],
+            meters: vec![],
+        }
+    }
+
+    pub fn new(midi_file: &MidiFile) -> Self {
+        let division = midi_file.header.division;
+
+        let mut changes = vec![];
+        let mut meter_changes = vec![];
+        for track in &midi_file.tracks {
+            for event in track.events() {
+                let tick = f64::from(division.beat_or_frame_to_tick(event.beat_or_frame));
+                match &event.event {
+                    MidiMsg::Meta {
+                        msg: Meta::SetTempo(tempo),
+                    } => changes.push((tick, 60_000_000. / f64::from((*tempo).max(1)))),
+                    MidiMsg::Meta {
+                        msg: Meta::TimeSignature(signature),
+                    } => meter_changes.push((tick, signature.numerator, signature.denominator)),
+                    _ => (),
+                }
+            }
+        }
+        // Stable: The last change on a tick wins.
+        changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        meter_changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut segments = vec![TempoSegment::new(division, 0., 0., 0., Self::DEFAULT_BPM)];
+        for (tick, bpm) in changes {
+            let last = segments[segments.len() - 1];
+            let ticks = tick - last.tick;
+            let segment = TempoSegment::new(
+                division,
+                tick,
+                last.seconds + ticks * last.seconds_per_tick,
+                last.beats + ticks * last.beats_per_tick,
+                bpm,
+            );
+            if ticks == 0. {
+                segments.pop();
+            }
+            segments.push(segment);
+        }
+
+        let mut map = Self {
+            segments,
+            meters: vec![],
+        };
+        let (numerator, denominator) = Self::DEFAULT_TIME_SIGNATURE;
+        map.meters = vec![MeterSegment::new(0., 0., numerator, denominator)];
+        for (tick, numerator, denominator) in meter_changes {
+            let beat = map.tick_to_beat(tick);
+            let last = map.meters[map.meters.len() - 1];
+            // A change in the middle of a bar starts a new one.
+            let bars = ((beat - last.beat) / last.quarters_per_bar - 1e-6).ceil();
+            let meter = MeterSegment::new(beat, last.bar + bars.max(0.), numerator, denominator);
+            if beat <= last.beat {
+                map.meters.pop();
+            }
+            map.meters.push(meter);
+        }
+        map
+    }
+
+    pub fn tick_to_time(&self, tick: f64) -> Duration {
+        let Some(segment) = self.find(|s| s.tick <= tick) else {
+            return Duration::ZERO;
+        };
+        let seconds = segment.seconds + (tick - segment.tick) * segment.seconds_per_tick;
+        Duration::from_secs_f64(seconds.max(0.))
+    }
+
+    pub fn time_to_tick(&self, time: Duration) -> f64 {
+        let seconds = time.as_secs_f64();
+        let Some(segment) = self.find(|s| s.seconds <= seconds) else {
+            return 0.;
+        };
+        segment.tick + (seconds - segment.seconds) / segment.seconds_per_tick
+    }
+
+    pub fn tick_to_beat(&self, tick: f64) -> f64 {
+        let Some(segment) = self.find(|s| s.tick <= tick) else {
+            return 0.;
+        };
+        segment.beats + (tick - segment.tick) * segment.beats_per_tick
+    }
+
+    pub fn beat_to_tick(&self, beat: f64) -> f64 {
+        let Some(segment) = self.find(|s| s.beats <= beat) else {
+            return 0.;
+        };
+        segment.tick + (beat - segment.beats) / segment.beats_per_tick
+    }
+
+    pub fn time_to_beat(&self, time: Duration) -> f64 {
+        self.tick_to_beat(self.time_to_tick(time))
+    }
+
+    pub fn beat_to_time(&self, beat: f64) -> Duration {
+        self.tick_to_time(self.beat_to_tick(beat))
+    }
+
+    /// Bar and beat of the time signature at `beat` quarter notes from song start.
+    pub fn beat_to_bar_beat(&self, beat: f64) -> BarBeat {
+        let idx = self.meters.partition_point(|m| m.beat <= beat);
+        let Some(meter) = self.meters.get(idx.saturating_sub(1)) else {
+            return BarBeat { bar: 1, beat: 1. };
+        };
+        let quarters = (beat - meter.beat).max(0.);
+        let bars = (quarters / meter.quarters_per_bar).floor();
+        let in_bar = quarters - bars * meter.quarters_per_bar;
+        BarBeat {
+            bar: (meter.bar + bars) as u32 + 1,
+            beat: in_bar / meter.quarters_per_beat + 1.,
+        }
+    }
+
+    /// Quarter notes from song start to `position`.
+    pub fn bar_beat_to_beat(&self, position: BarBeat) -> f64 {
+        let bar = f64::from(position.bar.max(1) - 1);
+        let idx = self.meters.partition_point(|m| m.bar <= bar);
+        let Some(meter) = self.meters.get(idx.saturating_sub(1)) else {
+            return 0.;
+        };
+        let quarters = (bar - meter.bar) * meter.quarters_per_bar
+            + (position.beat - 1.).max(0.) * meter.quarters_per_beat;
+        meter.beat + quarters
+    }
+
+    pub fn time_to_bar_beat(&self, time: Duration) -> BarBeat {
+        self.beat_to_bar_beat(self.time_to_beat(time))
+    }
+
+    pub fn bar_beat_to_time(&self, position: BarBeat) -> Duration {
+        self.beat_to_time(self.bar_beat_to_beat(position))
+    }
+
+    /// Tempo in effect at `tick`.
+    pub fn bpm_at(&self, tick: f64) -> f64 {
+        self.find(|s| s.tick <= tick)
+            .map_or(Self::DEFAULT_BPM, |segment| segment.bpm)
+    }
+
+    /// Last segment matching `starts_before`, or the first one if none does.
+    fn find<P>(&self, starts_before: P) -> Option<&TempoSegment>
+    where
+        P: FnMut(&TempoSegment) -> bool,
+    {
+        let idx = self.segments.partition_point(starts_before);
+        self.segments.get(idx.saturating_sub(1))
+    }
+}
+
+impl MeterSegment {
+    fn new(beat: f64, bar: f64, numerator: u8, denominator: u16) -> Self {
+        let quarters_per_beat = 4. / f64::from(denominator.max(1));
+        Self {
+            beat,
+            bar,
+            quarters_per_bar: quarters_per_beat * f64::from(numerator.max(1)),
+            quarters_per_beat,
+        }
+    }
+}
+
+impl TempoSegment {
+    fn new(division: Division, tick: f64, seconds: f64, beats: f64, bpm: f64) -> Self {
+        let (seconds_per_tick, beats_per_tick) = match division {
+            Division::TicksPerQuarterNote(ticks) => {
+                let ticks = f64::from(ticks.max(1));
+                (60. / bpm / ticks, 1. / ticks)
+            }
+            Division::TimeCode {
+                frames_per_second,
+                ticks_per_frame,
+            } => {
+                let fps = match frames_per_second {
+                    TimeCodeType::FPS24 => 24.,
+                    TimeCodeType::FPS25 => 25.,
+                    TimeCodeType::DF30 | TimeCodeType::NDF30 => 30.,
+                };
+                let seconds_per_tick = 1. / fps / f64::from(ticks_per_frame.max(1));
+                (seconds_per_tick, seconds_per_tick * bpm / 60.)
+            }
+        };
+        Self {
+            tick,
+            seconds,
+            beats,
+            seconds_per_tick,
+            beats_per_tick,
+            bpm,
+        }
+    }
+}
+
+#[test]
+fn test_tempo_map_conversions() {
+    let mut midi_file = MidiFile {
+        header: midi_msg::Header {
+            division: Division::TicksPerQuarterNote(480),
+            ..Default::default()
+        },
+        tracks: vec![],
+    };
+    midi_file.add_track(midi_msg::Track::default());
+    // 120 BPM for 2 beats, then 60 BPM.
+    let set_tempo = |tempo| MidiMsg::Meta {
+        msg: Meta::SetTempo(tempo),
+    };
+    midi_file.extend_track(0, set_tempo(500_000), 0.);
+    midi_file.extend_track(0, set_tempo(1_000_000), 2.);
+
+    let map = TempoMap::new(&midi_file);
+    assert_eq!(map.tick_to_time(960.), Duration::from_secs(1));
+    assert_eq!(map.tick_to_time(1440.), Duration::from_secs(2));
+    assert_eq!(map.time_to_tick(Duration::from_secs(3)), 1920.);
+    assert_eq!(map.time_to_beat(Duration::from_millis(500)), 1.);
+    assert_eq!(map.beat_to_time(3.), Duration::from_secs(2));
+    assert_eq!(map.bpm_at(959.), 120.);
+    assert_eq!(map.bpm_at(960.), 60.);
+}
+
+#[test]
+fn test_tempo_map_bars() {
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(midi_msg::Track::default());
+    // 4/4 for 2 bars, then 6/8.
+    let time_signature = MidiMsg::Meta {
+        msg: Meta::TimeSignature(midi_msg::FileTimeSignature {
+            numerator: 6,
+            denominator: 8,
+            clocks_per_metronome_tick: 36,
+            thirty_second_notes_per_24_clocks: 8,
+        }),
+    };
+    midi_file.extend_track(0, time_signature, 8.);
+
+    let map = TempoMap::new(&midi_file);
+    let bar_beat = |bar, beat| BarBeat { bar, beat };
+    assert_eq!(map.beat_to_bar_beat(0.), bar_beat(1, 1.));
+    assert_eq!(map.beat_to_bar_beat(5.), bar_beat(2, 2.));
+    assert_eq!(map.beat_to_bar_beat(8.), bar_beat(3, 1.));
+    assert_eq!(map.beat_to_bar_beat(12.5), bar_beat(4, 4.));
+    assert_eq!(map.bar_beat_to_beat(bar_beat(4, 4.)), 12.5);
+    assert_eq!(
+        map.bar_beat_to_time(bar_beat(2, 1.)),
+        Duration::from_secs(2)
+    );
+}
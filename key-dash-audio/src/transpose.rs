@@ -0,0 +1,131 @@
+//! Transposition: Shifts note numbers of melodic channels before they reach the synthesizer.
+//!
+
+use super::sysex::SysEx;
+
+/// Rewrites note numbers of `[status, data1, data2]` messages by a number of semitones.
+///
+/// Notes remember the key they were started on, so changing the transposition while notes are
+/// held doesn't leave them hanging.
+#[derive(Debug, Clone)]
+pub(crate) struct Transposer {
+    semitones: i8,
+    /// Master coarse tuning set by the song, see [`SysEx::MasterCoarseTuning`]
+    master_semitones: i8,
+    /// Bit mask of channels that are left alone
+    drum_channels: u16,
+    /// Transposed key of every sounding note, by channel and original key.
+    sounding: [[Option<u8>; 128]; 16],
+}
+
+impl Transposer {
+    /// Channel 10, as defined by General MIDI.
+    const DEFAULT_DRUM_CHANNELS: u16 = 1 << 9;
+
+    pub const fn new() -> Self {
+        Self {
+            semitones: 0,
+            master_semitones: 0,
+            drum_channels: Self::DEFAULT_DRUM_CHANNELS,
+            sounding: [[None; 128]; 16],
+        }
+    }
+
+    /// Takes effect on the following note ons.
+    pub const fn set_semitones(&mut self, semitones: i8) {
+        self.semitones = semitones;
+    }
+
+    /// Forget every sounding note, as after a reset of the receiver.
+    pub fn reset(&mut self) {
+        self.sounding = [[None; 128]; 16];
+    }
+
+    const fn is_drum_channel(&self, channel: u8) -> bool {
+        self.drum_channels & 1 << (channel & 0x0f) != 0
+    }
+
+    /// Follow the drum channels and master tuning of the song.
+    pub fn apply_sysex(&mut self, sysex: SysEx) {
+        match sysex {
+            SysEx::Reset => {
+                self.reset();
+                self.master_semitones = 0;
+                self.drum_channels = Self::DEFAULT_DRUM_CHANNELS;
+            }
+            SysEx::MasterCoarseTuning(semitones) => self.master_semitones = semitones,
+            SysEx::DrumPart { channel, drums } => {
+                let bit = 1 << (channel & 0x0f);
+                if drums {
+                    self.drum_channels |= bit;
+                } else {
+                    self.drum_channels &= !bit;
+                }
+            }
+            SysEx::MasterVolume(_) | SysEx::MasterFineTuning(_) => (),
+        }
+    }
+
+    /// Transpose `message`, or `None` if the transposed note is out of range.
+    pub fn apply(&mut self, [status, data1, data2]: [u8; 3]) -> Option<[u8; 3]> {
+        if self.is_drum_channel(status) {
+            return Some([status, data1, data2]);
+        }
+        let channel = usize::from(status & 0x0f);
+        let sounding = &mut self.sounding[channel];
+        let key = usize::from(data1 & 0x7f);
+        let shifted = || {
+            let note =
+                i16::from(data1) + i16::from(self.semitones) + i16::from(self.master_semitones);
+            u8::try_from(note).ok().filter(|note| *note < 0x80)
+        };
+
+        let note = match status & 0xf0 {
+            0x90 if data2 > 0 => {
+                let note = shifted();
+                sounding[key] = note;
+                note?
+            }
+            0x80 | 0x90 => sounding[key].take().or_else(shifted)?,
+            0xa0 => sounding[key].or_else(shifted)?,
+            // All Sound Off, All Notes Off and the mode changes that imply it.
+            0xb0 if data1 == 0x78 || (0x7b..=0x7f).contains(&data1) => {
+                *sounding = [None; 128];
+                data1
+            }
+            _ => data1,
+        };
+        Some([status, note, data2])
+    }
+}
+
+#[test]
+fn test_transposer() {
+    let mut transposer = Transposer::new();
+    transposer.set_semitones(3);
+    assert_eq!(transposer.apply([0x90, 60, 100]), Some([0x90, 63, 100]));
+    // Drums are left alone.
+    assert_eq!(transposer.apply([0x99, 36, 100]), Some([0x99, 36, 100]));
+    // Notes are released where they were started.
+    transposer.set_semitones(-2);
+    assert_eq!(transposer.apply([0x80, 60, 0]), Some([0x80, 63, 0]));
+    assert_eq!(transposer.apply([0x90, 60, 0]), Some([0x90, 58, 0]));
+    assert_eq!(transposer.apply([0x90, 1, 100]), None);
+}
+
+#[test]
+fn test_transposer_sysex() {
+    let mut transposer = Transposer::new();
+    transposer.apply_sysex(SysEx::MasterCoarseTuning(-12));
+    transposer.apply_sysex(SysEx::DrumPart {
+        channel: 10,
+        drums: true,
+    });
+    assert_eq!(transposer.apply([0x90, 60, 100]), Some([0x90, 48, 100]));
+    assert_eq!(transposer.apply([0x9a, 36, 100]), Some([0x9a, 36, 100]));
+    // Only the transposition of the player is left after a reset.
+    transposer.apply_sysex(SysEx::Reset);
+    transposer.set_semitones(2);
+    assert_eq!(transposer.apply([0x9a, 36, 100]), Some([0x9a, 38, 100]));
+    assert_eq!(transposer.apply([0x90, 60, 100]), Some([0x90, 62, 100]));
+}
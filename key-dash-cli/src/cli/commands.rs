@@ -1,4 +1,12 @@
-use clap::Subcommand;
+use clap::{Subcommand, ValueEnum};
+use color_eyre::Result;
+use key_dash_audio::{
+    PlaylistEntry, loader, m3u,
+    render::{self, RenderOptions, SampleFormat},
+};
+use std::path::{Path, PathBuf};
+
+use crate::ui::format_duration;
 
 #[derive(Subcommand)]
 pub enum Commands {
@@ -8,4 +16,117 @@ pub enum Commands {
         #[arg(short, long)]
         list: bool,
     },
+    /// Renders a MIDI file into a WAV file without an audio device
+    Render {
+        /// MIDI file to render
+        midi: PathBuf,
+
+        /// SoundFont used for synthesis
+        #[arg(short, long, value_name = "FILE")]
+        soundfont: PathBuf,
+
+        /// Output WAV file, defaults to the MIDI file with a `.wav` extension
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Sample rate in Hz
+        #[arg(short = 'r', long, default_value_t = 44100)]
+        sample_rate: u32,
+
+        /// Sample format of the WAV file
+        #[arg(short, long, value_enum, default_value_t = BitDepth::Int16)]
+        bit_depth: BitDepth,
+    },
+    /// Writes MIDI files into an M3U or M3U8 playlist, or lists the songs of one
+    Playlist {
+        /// Playlist file
+        playlist: PathBuf,
+
+        /// MIDI files or playlists to write into the playlist, in order. Without any, the
+        /// playlist is listed.
+        midi: Vec<PathBuf>,
+
+        /// Add the files after the songs already in the playlist
+        #[arg(short, long)]
+        append: bool,
+
+        /// Write absolute paths instead of paths relative to the playlist
+        #[arg(long)]
+        absolute: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BitDepth {
+    /// 16-bit integer
+    #[value(name = "16")]
+    Int16,
+    /// 24-bit integer
+    #[value(name = "24")]
+    Int24,
+    /// 32-bit float
+    #[value(name = "float")]
+    Float32,
+}
+
+impl From<BitDepth> for SampleFormat {
+    fn from(value: BitDepth) -> Self {
+        match value {
+            BitDepth::Int16 => Self::Int16,
+            BitDepth::Int24 => Self::Int24,
+            BitDepth::Float32 => Self::Float32,
+        }
+    }
+}
+
+/// Handles [`Commands::Render`].
+pub fn render(
+    midi: &Path,
+    soundfont: &Path,
+    output: Option<&Path>,
+    options: RenderOptions,
+) -> Result<()> {
+    let song = loader::read_midi(midi)?;
+    let soundfont = loader::read_soundfont(soundfont)?;
+    let output = output.map_or_else(|| midi.with_extension("wav"), Path::to_path_buf);
+
+    let duration = render::render_to_file(&soundfont, song.midi_file, &output, options)?;
+    println!(
+        "Rendered {:.1}s of audio into {}",
+        duration.as_secs_f64(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Handles [`Commands::Playlist`].
+pub fn playlist(playlist: &Path, midi: &[PathBuf], append: bool, absolute: bool) -> Result<()> {
+    if midi.is_empty() {
+        for (idx, entry) in m3u::read_m3u(playlist)?.iter().enumerate() {
+            let duration = entry.duration.map_or_else(String::new, format_duration);
+            println!(
+                "{:>3}. {} {duration}  {}",
+                idx + 1,
+                entry.title,
+                entry.path.display()
+            );
+        }
+        return Ok(());
+    }
+
+    let mut entries = if append && playlist.exists() {
+        m3u::read_m3u(playlist)?
+    } else {
+        vec![]
+    };
+    for path in midi {
+        if m3u::is_playlist(path) {
+            entries.extend(m3u::read_m3u(path)?);
+        } else {
+            entries.push(PlaylistEntry::new(path.clone()));
+        }
+    }
+    m3u::write_m3u(playlist, &entries, absolute)?;
+    println!("Wrote {} songs into {}", entries.len(), playlist.display());
+    Ok(())
 }
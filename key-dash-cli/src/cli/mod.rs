@@ -1,5 +1,11 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use color_eyre::Result;
 use commands::Commands;
+use key_dash_audio::{
+    PlayerError, gm,
+    output::{FileBackend, NullBackend, OutputBackend, RodioBackend},
+    render::RenderOptions,
+};
 use std::path::PathBuf;
 mod commands;
 
@@ -18,31 +24,67 @@ pub struct Cli {
     #[arg(short, long)]
     pub debug: Option<bool>,
 
+    /// MIDI file to play, repeat to queue several
+    #[arg(short, long, value_name = "FILE")]
+    pub midi: Vec<PathBuf>,
+
+    /// SoundFont to play with
+    #[arg(short, long, value_name = "FILE")]
+    pub soundfont: Option<PathBuf>,
+
+    /// Play a MIDI channel (1-16) with another SoundFont, repeat for several channels
+    #[arg(long, value_name = "CHANNEL=FILE", value_parser = parse_channel_font)]
+    pub channel_font: Vec<(u8, PathBuf)>,
+
+    /// Play a MIDI channel (1-16) with a fixed preset instead of the song's program changes,
+    /// bank 128 holds the drum kits
+    #[arg(long, value_name = "CHANNEL=BANK:PROGRAM", value_parser = parse_channel_preset)]
+    pub channel_preset: Vec<(u8, (u16, u8))>,
+
+    /// Where to play the audio
+    #[arg(long, value_enum, default_value_t = Output::Device)]
+    pub output: Output,
+
+    /// WAV file to record into with `--output file`
+    #[arg(long, value_name = "FILE", required_if_eq("output", "file"))]
+    pub output_file: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
-impl Cli {
-    pub fn run() {
-        // Receive command line arguments
-        let cli = Cli::parse();
+/// Audio outputs of the TUI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    /// The default sound device
+    Device,
+    /// Nowhere, playback runs without a sound device
+    Null,
+    /// A WAV file, see `--output-file`
+    File,
+}
 
+impl Cli {
+    /// Handles command line arguments.
+    ///
+    /// Returns `true` if a subcommand was run, in which case the TUI should not be started.
+    pub fn run(&self) -> Result<bool> {
         // You can check the value provided by positional arguments, or option arguments
-        if let Some(name) = cli.name.as_deref() {
+        if let Some(name) = self.name.as_deref() {
             println!("Value for name: {name}");
         }
 
-        if let Some(config_path) = cli.config.as_deref() {
+        if let Some(config_path) = self.config.as_deref() {
             println!("Value for config: {}", config_path.display());
         }
 
-        if let Some(debug) = cli.debug {
+        if let Some(debug) = self.debug {
             println!("Debugging is: {}", debug);
         }
 
         // You can check for the existence of subcommands, and if found use their
         // matches just as you would the top level cmd
-        match &cli.command {
+        match &self.command {
             Some(Commands::Test { list }) => {
                 if *list {
                     println!("Printing testing lists...");
@@ -50,11 +92,79 @@ impl Cli {
                     println!("Not printing testing lists...");
                 }
             }
-            None => {}
+            Some(Commands::Render {
+                midi,
+                soundfont,
+                output,
+                sample_rate,
+                bit_depth,
+            }) => {
+                let options = RenderOptions {
+                    sample_rate: *sample_rate,
+                    sample_format: (*bit_depth).into(),
+                };
+                commands::render(midi, soundfont, output.as_deref(), options)?;
+            }
+            Some(Commands::Playlist {
+                playlist,
+                midi,
+                append,
+                absolute,
+            }) => commands::playlist(playlist, midi, *append, *absolute)?,
+            None => return Ok(false),
         }
+        Ok(true)
+    }
+
+    /// Opens the audio output selected by `--output`.
+    pub fn open_output(&self) -> Result<Box<dyn OutputBackend>, PlayerError> {
+        Ok(match (self.output, &self.output_file) {
+            (Output::Device, _) => Box::new(RodioBackend::open_default()?),
+            (Output::Null, _) | (Output::File, None) => Box::new(NullBackend::default()),
+            (Output::File, Some(path)) => {
+                Box::new(FileBackend::create(path, RenderOptions::default())?)
+            }
+        })
     }
 }
 
+/// `CHANNEL=FILE` with a channel of 1-16, returned as 0-15.
+fn parse_channel_font(value: &str) -> Result<(u8, PathBuf), String> {
+    let (channel, path) = value.split_once('=').ok_or("expected CHANNEL=FILE")?;
+    Ok((parse_channel(channel)?, PathBuf::from(path)))
+}
+
+/// `CHANNEL=BANK:PROGRAM` with a channel of 1-16, returned as 0-15.
+fn parse_channel_preset(value: &str) -> Result<(u8, (u16, u8)), String> {
+    let (channel, preset) = value
+        .split_once('=')
+        .ok_or("expected CHANNEL=BANK:PROGRAM")?;
+    let (bank, program) = preset
+        .split_once(':')
+        .ok_or("expected CHANNEL=BANK:PROGRAM")?;
+    let bank = bank
+        .parse()
+        .ok()
+        .filter(|bank| *bank <= gm::DRUM_BANK)
+        .ok_or("bank must be 0-128")?;
+    let program = program
+        .parse()
+        .ok()
+        .filter(|program| *program <= 127)
+        .ok_or("program must be 0-127")?;
+    Ok((parse_channel(channel)?, (bank, program)))
+}
+
+/// MIDI channel 1-16, as 0-15.
+fn parse_channel(channel: &str) -> Result<u8, String> {
+    channel
+        .parse::<u8>()
+        .ok()
+        .filter(|channel| (1..=16).contains(channel))
+        .map(|channel| channel - 1)
+        .ok_or_else(|| "channel must be 1-16".to_string())
+}
+
 #[test]
 fn test_cli() {
     let args = vec!["--name", "test_name", "--config", "test_config"];
@@ -62,3 +172,96 @@ fn test_cli() {
     assert_eq!(cli.name, Some("test_name".to_string()));
     assert_eq!(cli.config, Some(PathBuf::from("test_config")));
 }
+
+#[test]
+fn test_play_files() {
+    let args = vec![
+        "key-dash",
+        "-m",
+        "song.mid",
+        "--midi",
+        "next.mid",
+        "--soundfont",
+        "font.sf2",
+    ];
+    let cli = Cli::parse_from(args);
+    assert_eq!(
+        cli.midi,
+        [PathBuf::from("song.mid"), PathBuf::from("next.mid")]
+    );
+    assert_eq!(cli.soundfont, Some(PathBuf::from("font.sf2")));
+    assert!(cli.command.is_none());
+}
+
+#[test]
+fn test_render_command() {
+    let args = vec![
+        "key-dash", "render", "song.mid", "-s", "font.sf2", "-b", "24",
+    ];
+    let cli = Cli::parse_from(args);
+    let Some(Commands::Render {
+        midi,
+        soundfont,
+        output,
+        sample_rate,
+        bit_depth,
+    }) = cli.command
+    else {
+        panic!("Expected render command");
+    };
+    assert_eq!(midi, PathBuf::from("song.mid"));
+    assert_eq!(soundfont, PathBuf::from("font.sf2"));
+    assert_eq!(output, None);
+    assert_eq!(sample_rate, 44100);
+    assert_eq!(bit_depth, commands::BitDepth::Int24);
+}
+
+#[test]
+fn test_playlist_command() {
+    let args = vec!["key-dash", "playlist", "list.m3u8", "a.mid", "b.mid", "-a"];
+    let cli = Cli::parse_from(args);
+    let Some(Commands::Playlist {
+        playlist,
+        midi,
+        append,
+        absolute,
+    }) = cli.command
+    else {
+        panic!("Expected playlist command");
+    };
+    assert_eq!(playlist, PathBuf::from("list.m3u8"));
+    assert_eq!(midi, [PathBuf::from("a.mid"), PathBuf::from("b.mid")]);
+    assert!(append && !absolute);
+}
+
+#[test]
+fn test_output_file() {
+    let args = vec!["key-dash", "--output", "file", "--output-file", "out.wav"];
+    let cli = Cli::parse_from(args);
+    assert_eq!(cli.output, Output::File);
+    assert_eq!(cli.output_file, Some(PathBuf::from("out.wav")));
+
+    let args = vec!["key-dash", "--output", "file"];
+    assert!(Cli::try_parse_from(args).is_err());
+}
+
+#[test]
+fn test_channel_overrides() {
+    let args = vec![
+        "key-dash",
+        "--channel-font",
+        "5=strings.sf2",
+        "--channel-preset",
+        "10=128:25",
+        "--channel-preset",
+        "1=0:48",
+    ];
+    let cli = Cli::parse_from(args);
+    assert_eq!(cli.channel_font, [(4, PathBuf::from("strings.sf2"))]);
+    assert_eq!(cli.channel_preset, [(9, (128, 25)), (0, (0, 48))]);
+
+    for value in ["0=0:0", "17=0:0", "1=129:0", "1=0:128", "1=0"] {
+        let args = vec!["key-dash", "--channel-preset", value];
+        assert!(Cli::try_parse_from(args).is_err(), "{value}");
+    }
+}
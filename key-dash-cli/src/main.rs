@@ -1,3 +1,6 @@
+use clap::Parser;
+use key_dash_audio::output::NullBackend;
+
 mod cli;
 mod ui;
 
@@ -21,8 +24,11 @@ async fn main() -> color_eyre::Result<()> {
     let locale = sys_locale::get_locale().unwrap_or_else(|| "en".to_string());
     rust_i18n::set_locale(&locale);
 
-    // Run Cli
-    cli::Cli::run();
+    // Run Cli, subcommands exit without starting the TUI
+    let cli = cli::Cli::parse();
+    if cli.run()? {
+        return Ok(());
+    }
 
     // Initialize the terminal
     //
@@ -35,7 +41,33 @@ async fn main() -> color_eyre::Result<()> {
     // See: [`ratatui::init`]
     let terminal = ratatui::init();
 
-    let result = ui::App::default().run(terminal).await;
+    let mut app = ui::App::default();
+    if let Ok(dir) = std::env::current_dir() {
+        app.browse(&dir);
+    }
+    match cli.open_output() {
+        Ok(output) => app.set_output(output),
+        Err(err) => {
+            // Keep the player usable without a sound device.
+            app.show_error(&err);
+            app.set_output(Box::new(NullBackend::default()));
+        }
+    }
+    app.read_song_settings();
+    let default_soundfont = app.find_soundfonts();
+    if let Some(soundfont) = cli.soundfont.or(default_soundfont) {
+        app.load_soundfont(soundfont);
+    }
+    for (channel, path) in cli.channel_font {
+        app.load_channel_font(channel, path);
+    }
+    for (channel, preset) in cli.channel_preset {
+        app.set_channel_preset(channel, preset);
+    }
+    for midi in cli.midi {
+        app.load_midi(midi);
+    }
+    let result = app.run(terminal).await;
 
     restore();
     result
@@ -1,19 +1,37 @@
 mod tab;
 
-use std::time::Duration;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use color_eyre::{Result, eyre::Ok};
+use color_eyre::Result;
 use crossterm::event::{
     Event as CrosstermEvent, EventStream as CrosstermStream, KeyCode, KeyEvent, KeyEventKind,
 };
-use key_dash_audio::Player;
+use key_dash_audio::{
+    ChannelOverride, LoopPoint, PlaybackState, Player, PlayerError, PlayerEvent, Playlist,
+    PlaylistEntry, RepeatMode, Song,
+    loader::{self, MidiInfo},
+    m3u,
+    output::OutputBackend,
+};
 use ratatui::{
     DefaultTerminal,
     buffer::Buffer,
     layout::{Constraint, Flex, Layout, Rect},
-    text::Text,
     widgets::Widget,
 };
+use rustysynth::SoundFont;
+use tab::{
+    AboutTab, ChannelActivities, FileBrowser, FilesTab, MixerView, PianoRoll, PlayerTab,
+    PlaylistTab, Setting, SettingsTab, SoundFontLibrary, SoundFontTab, Tab, TabBar,
+};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc},
+    task::JoinSet,
+};
 use tokio_stream::StreamExt;
 
 /// The main application which holds the state and logic of the application.
@@ -21,30 +39,111 @@ use tokio_stream::StreamExt;
 pub struct App {
     should_quit: bool,
     player: Player,
+    /// Last error reported by [`Player`]
+    last_error: Option<String>,
+    /// Start of the A-B loop being marked
+    loop_start: Option<Duration>,
+    /// Files being loaded in the background
+    loads: JoinSet<Loaded>,
+    /// Infos of the MIDI files in the file browser, read one by one in the background. Dropped
+    /// when another directory is browsed, which stops the reading.
+    midi_infos: Option<mpsc::UnboundedReceiver<(PathBuf, MidiInfo)>>,
+    playlist: Playlist,
+    /// Entry of the playlist the Playlist tab keys apply to
+    playlist_cursor: usize,
+    /// M3U file the playlist was last opened from or saved to
+    playlist_file: Option<PathBuf>,
+    /// Outcome of the last playlist file operation
+    playlist_message: Option<String>,
+    /// Path being typed for a playlist file operation, takes all keys
+    prompt: Option<PathPrompt>,
+    browser: FileBrowser,
+    soundfonts: SoundFontLibrary,
+    tab: Tab,
+    settings: Settings,
+    /// Followed from [`PlayerEvent`]s for the channel mixer
+    channels: ChannelActivities,
+    /// Channel of the mixer the mute and solo keys apply to
+    selected_channel: u8,
+}
+
+/// A file that finished loading in the background.
+enum Loaded {
+    /// The file of a playlist entry
+    Song(PlaylistEntry, Result<Song, PlayerError>),
+    SoundFont(PathBuf, Result<Arc<SoundFont>, PlayerError>),
+    /// A SoundFont for one MIDI channel
+    ChannelFont(u8, Result<Arc<SoundFont>, PlayerError>),
+    /// An M3U playlist, replacing the current one or added to it
+    Playlist {
+        path: PathBuf,
+        replace: bool,
+        entries: Result<Vec<PlaylistEntry>, PlayerError>,
+    },
+    /// A SoundFont parsed to show its presets, without switching to it
+    SoundFontPreview(PathBuf, Result<Arc<SoundFont>, PlayerError>),
+    /// The playlist was written into an M3U file.
+    PlaylistSaved(PathBuf, Result<(), PlayerError>),
+}
+
+/// What to do with the path typed on the Playlist tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptAction {
+    Open,
+    Save,
+}
+
+/// A path being typed on the Playlist tab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PathPrompt {
+    action: PromptAction,
+    input: String,
 }
 
 impl App {
     // For controlling frame generate speed
     const FRAMES_PER_SECOND: f32 = 120.0;
+    const SPEED_STEP: f64 = 0.05;
+    /// Song settings are written once they haven't changed for this long.
+    const SAVE_DELAY: Duration = Duration::from_secs(1);
 
     /// Run the application's main loop.
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
         let period = Duration::from_secs_f32(1.0 / Self::FRAMES_PER_SECOND);
         let mut interval = tokio::time::interval(period);
-        let mut crosstermStream = CrosstermStream::new();
-        // let mut playerStream = self.player;
+        let mut crossterm_stream = CrosstermStream::new();
+        let mut player_events = self.player.subscribe();
 
         while !self.should_quit {
             tokio::select! {
                 _ = interval.tick() => {
+                  self.save_song_settings(Self::SAVE_DELAY);
                   terminal.draw(|frame| frame.render_widget(&self, frame.area()))?;
                 },
-                Some(event) = crosstermStream.next() => {
+                Some(event) = crossterm_stream.next() => {
                   self.handle_crossterm_events(event?)?;
                 },
+                event = player_events.recv() => match event {
+                  Ok(event) => self.handle_player_event(event),
+                  // Positions are sent often, missing some of them is fine.
+                  Err(RecvError::Lagged(_)) => {},
+                  Err(RecvError::Closed) => break,
+                },
+                info = next_midi_info(&mut self.midi_infos) => match info {
+                  Some((path, info)) => self.browser.set_info(path, info),
+                  None => self.midi_infos = None,
+                },
+                Some(loaded) = self.loads.join_next() => match loaded {
+                  Ok(loaded) => self.handle_loaded(loaded),
+                  // A loader that panicked only loses what it was loading.
+                  Err(err) => {
+                    self.last_error = Some(t!("app.error.task", error = err).into_owned());
+                  },
+                },
             }
         }
 
+        self.save_song_settings(Duration::ZERO);
         Ok(())
     }
 
@@ -61,21 +160,636 @@ impl App {
                 Ok(())
             }
         }?;
+        // Keys may have selected another SoundFont, or shown the tab.
+        if self.tab == Tab::SoundFont {
+            self.preview_soundfont();
+        }
         Ok(())
     }
 
+    /// Add the MIDI file at `path` to the playlist, or the songs of an M3U playlist, and play it
+    /// if nothing else plays.
+    pub fn load_midi(&mut self, path: PathBuf) {
+        if m3u::is_playlist(&path) {
+            self.open_playlist(path, false);
+            return;
+        }
+        self.playlist.push(PlaylistEntry::new(path));
+        self.play_if_idle();
+    }
+
+    /// Add the MIDI file at `path` to the playlist and play it right away.
+    fn play_file(&mut self, path: PathBuf) {
+        self.playlist.push(PlaylistEntry::new(path));
+        self.jump_to(self.playlist.entries().len() - 1);
+    }
+
+    /// Show `dir` in the file browser, reading the track counts and durations of its MIDI files
+    /// in the background.
+    pub fn browse(&mut self, dir: &Path) {
+        if let Err(source) = self.browser.open(dir) {
+            self.show_error(&PlayerError::Io {
+                path: dir.to_path_buf(),
+                source,
+            });
+            return;
+        }
+        // Replacing the receiver stops reading the previous directory.
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.midi_infos = Some(receiver);
+        let paths = self.browser.missing_infos();
+        tokio::task::spawn_blocking(move || {
+            for path in paths {
+                if sender.is_closed() {
+                    break;
+                }
+                if let Ok(info) = loader::read_info(&path) {
+                    let _ = sender.send((path, info));
+                }
+            }
+        });
+    }
+
+    /// Start the playlist if nothing plays yet.
+    fn play_if_idle(&mut self) {
+        if self.playlist.current().is_none() {
+            self.playlist.skip_forward();
+            self.play_current();
+        }
+    }
+
+    /// Read the M3U playlist at `path` without blocking the UI, then play its songs instead of
+    /// the current ones with `replace`, or after them.
+    fn open_playlist(&mut self, path: PathBuf, replace: bool) {
+        self.loads.spawn_blocking(move || {
+            let entries = m3u::read_m3u(&path);
+            Loaded::Playlist {
+                path,
+                replace,
+                entries,
+            }
+        });
+    }
+
+    /// Write the playlist into the M3U file at `path` without blocking the UI, computing the
+    /// durations of songs that weren't played yet.
+    fn save_playlist(&mut self, path: PathBuf) {
+        let entries = self.playlist.entries().to_vec();
+        self.loads.spawn_blocking(move || {
+            let result = m3u::write_m3u(&path, &entries, false);
+            Loaded::PlaylistSaved(path, result)
+        });
+    }
+
+    /// Load the current entry of the playlist without blocking the UI, then play it.
+    fn play_current(&mut self) {
+        let Some(entry) = self.playlist.current_entry().cloned() else {
+            return;
+        };
+        self.loads.spawn_blocking(move || {
+            let song = loader::read_midi(&entry.path);
+            Loaded::Song(entry, song)
+        });
+    }
+
+    /// Load the SoundFont at `path` without blocking the UI, then switch to it.
+    pub fn load_soundfont(&mut self, path: PathBuf) {
+        self.loads.spawn_blocking(move || {
+            let soundfont = loader::read_soundfont(&path);
+            Loaded::SoundFont(path, soundfont)
+        });
+    }
+
+    /// Load the SoundFont at `path` without blocking the UI, then play MIDI `channel` (0-15)
+    /// with it.
+    pub fn load_channel_font(&mut self, channel: u8, path: PathBuf) {
+        self.loads
+            .spawn_blocking(move || Loaded::ChannelFont(channel, loader::read_soundfont(path)));
+    }
+
+    /// Play MIDI `channel` (0-15) with `preset`, a bank and program, instead of the song's
+    /// program changes.
+    pub fn set_channel_preset(&mut self, channel: u8, preset: (u16, u8)) {
+        self.update_channel_override(channel, |channel_override| {
+            channel_override.preset = Some(preset);
+        });
+    }
+
+    fn update_channel_override<F>(&mut self, channel: u8, f: F)
+    where
+        F: FnOnce(&mut ChannelOverride),
+    {
+        let Some(channel_override) = self.player.channel_overrides().get(usize::from(channel))
+        else {
+            return;
+        };
+        let mut channel_override = channel_override.clone().unwrap_or_default();
+        f(&mut channel_override);
+        self.player
+            .set_channel_override(channel, Some(channel_override));
+    }
+
+    /// List the SoundFonts of the usual directories on the SoundFont tab, returns the default
+    /// one.
+    pub fn find_soundfonts(&mut self) -> Option<PathBuf> {
+        self.soundfonts.scan();
+        self.soundfonts.read_default()
+    }
+
+    /// Remember the settings of songs in the config directory, across runs.
+    pub fn read_song_settings(&mut self) {
+        if let Some(dir) = config_dir()
+            && let Err(err) = self.player.set_settings_file(dir.join("song-settings"))
+        {
+            self.show_error(&err);
+        }
+    }
+
+    /// Play through `output`.
+    pub fn set_output(&mut self, output: Box<dyn OutputBackend>) {
+        self.player.set_output(Some(output));
+    }
+
+    /// Show `err` until the next one.
+    pub fn show_error(&mut self, err: &PlayerError) {
+        self.last_error = Some(localize_error(err));
+    }
+
+    fn handle_loaded(&mut self, loaded: Loaded) {
+        match loaded {
+            Loaded::Song(entry, Ok(song)) => self.play_loaded(&entry, song),
+            Loaded::SoundFont(path, Ok(soundfont)) => {
+                self.soundfonts.set_loaded(&path, Arc::clone(&soundfont));
+                self.player.set_soundfont(soundfont);
+                if self.player.status().state == PlaybackState::Stopped {
+                    self.start_playback();
+                }
+            }
+            Loaded::SoundFontPreview(path, soundfont) => {
+                let soundfont = soundfont.map_err(|err| self.show_error(&err)).ok();
+                self.soundfonts.set_preview(path, soundfont);
+                // The selection may have moved on meanwhile.
+                self.preview_soundfont();
+            }
+            Loaded::ChannelFont(channel, Ok(soundfont)) => {
+                self.update_channel_override(channel, |channel_override| {
+                    channel_override.soundfont = Some(soundfont);
+                });
+            }
+            Loaded::Playlist {
+                path,
+                replace,
+                entries: Ok(entries),
+            } => {
+                self.playlist_message = Some(
+                    t!(
+                        "app.playlist.opened",
+                        count = entries.len(),
+                        path = path.display()
+                    )
+                    .into_owned(),
+                );
+                if replace {
+                    self.playlist.clear();
+                    self.playlist_cursor = 0;
+                    self.playlist_file = Some(path);
+                }
+                for entry in entries {
+                    self.playlist.push(entry);
+                }
+                self.play_if_idle();
+            }
+            Loaded::PlaylistSaved(path, Ok(())) => {
+                self.playlist_message = Some(
+                    t!(
+                        "app.playlist.saved",
+                        count = self.playlist.entries().len(),
+                        path = path.display()
+                    )
+                    .into_owned(),
+                );
+                self.playlist_file = Some(path);
+            }
+            Loaded::Playlist {
+                entries: Err(err), ..
+            }
+            | Loaded::PlaylistSaved(_, Err(err)) => {
+                self.playlist_message = Some(localize_error(&err));
+            }
+            Loaded::Song(_, Err(err))
+            | Loaded::SoundFont(_, Err(err))
+            | Loaded::ChannelFont(_, Err(err)) => {
+                self.show_error(&err);
+            }
+        }
+    }
+
+    /// Play `song`, loaded for `entry`, unless another entry became current meanwhile. The
+    /// first time a format 2 file is loaded, its entry is replaced by one per sequence.
+    fn play_loaded(&mut self, entry: &PlaylistEntry, song: Song) {
+        let Some(current) = self.playlist.current() else {
+            return;
+        };
+        let current_entry = &self.playlist.entries()[current];
+        if (&current_entry.path, current_entry.sequence) != (&entry.path, entry.sequence) {
+            return;
+        }
+        let mut sequences = song.into_sequences();
+        let sequence = entry.sequence.unwrap_or(0).min(sequences.len() - 1);
+        if entry.sequence.is_none() && sequences.len() > 1 {
+            let entries = sequences
+                .iter()
+                .enumerate()
+                .map(|(idx, song)| PlaylistEntry {
+                    sequence: Some(idx),
+                    title: song.name.clone(),
+                    duration: Some(song.length()),
+                    ..entry.clone()
+                })
+                .collect();
+            self.playlist.expand(current, entries);
+        }
+        let song = sequences.swap_remove(sequence);
+        if let Some(entry) = self.playlist.current_entry_mut() {
+            entry.duration = Some(song.length());
+        }
+        self.channels = ChannelActivities::default();
+        self.save_song_settings(Duration::ZERO);
+        self.player.set_song(song);
+        self.start_playback();
+    }
+
+    /// Play the following entry of the playlist, if any.
+    fn play_next(&mut self) {
+        if self.playlist.skip_forward().is_some() {
+            self.play_current();
+        }
+    }
+
+    /// Play what follows the song that ended, see [`Playlist::advance`].
+    fn advance(&mut self) {
+        if self.playlist.advance().is_some() {
+            self.play_current();
+        }
+    }
+
+    /// Play the preceding entry of the playlist, if any.
+    fn play_previous(&mut self) {
+        if self.playlist.skip_back().is_some() {
+            self.play_current();
+        }
+    }
+
+    /// Play entry `idx` of the playlist.
+    fn jump_to(&mut self, idx: usize) {
+        if self.playlist.jump(idx).is_some() {
+            self.play_current();
+        }
+    }
+
+    /// Remove entry `idx` from the playlist, playing the following one if `idx` was playing.
+    fn remove_entry(&mut self, idx: usize) {
+        let playing = self.playlist.current() == Some(idx);
+        self.playlist.remove(idx);
+        self.playlist_cursor = self
+            .playlist_cursor
+            .min(self.playlist.entries().len().saturating_sub(1));
+        if playing {
+            if self.playlist.current().is_some() {
+                self.play_current();
+            } else if let Err(err) = self.player.stop_playback() {
+                self.show_error(&err);
+            }
+        }
+    }
+
+    /// Set the repeat mode of the playlist, the player repeats a single song itself.
+    fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.playlist.set_repeat(repeat);
+        self.player.set_repeat_one(repeat == RepeatMode::One);
+    }
+
+    fn start_playback(&mut self) {
+        match self.player.start_playback() {
+            // The other file is still loading.
+            Ok(()) | Err(PlayerError::NoFont | PlayerError::NoMidi) => {}
+            Err(err) => self.show_error(&err),
+        }
+    }
+
+    /// Write the song settings once their last change is `delay` old, see
+    /// [`Player::save_song_settings`].
+    fn save_song_settings(&mut self, delay: Duration) {
+        if let Err(err) = self.player.save_song_settings(delay) {
+            self.show_error(&err);
+        }
+    }
+
+    /// Handles the events sent by [`Player`] during playback.
+    fn handle_player_event(&mut self, event: PlayerEvent) {
+        self.channels.handle_event(&event, Instant::now());
+        match event {
+            PlayerEvent::Error(message) => self.last_error = Some(message),
+            PlayerEvent::EndOfSong => self.advance(),
+            _ => {}
+        }
+    }
+
     /// Handles the key events and updates the state of [`App`].
+    ///
+    /// Keys of the current tab take precedence over the global ones.
     fn on_key_event(&mut self, key: KeyEvent) -> Result<()> {
+        if self.prompt.is_some() {
+            self.on_prompt_key_event(key);
+            return Ok(());
+        }
+        if self.on_tab_key_event(key) {
+            return Ok(());
+        }
         match (key.modifiers, key.code) {
-            // (_, KeyCode::Char(' ')) => self.player.toggle_play_pause(),
+            (_, KeyCode::Tab) => self.tab = self.tab.next(),
+            (_, KeyCode::BackTab) => self.tab = self.tab.previous(),
+            (_, KeyCode::Char(digit @ '1'..='9')) => {
+                if let Some(tab) = Tab::from_digit(digit) {
+                    self.tab = tab;
+                }
+            }
+            (_, KeyCode::Char(' ')) => self.toggle_play_pause(),
+            (_, KeyCode::Char('[')) => self.change_speed(-Self::SPEED_STEP),
+            (_, KeyCode::Char(']')) => self.change_speed(Self::SPEED_STEP),
+            (_, KeyCode::Char('-')) => self.change_transpose(-1),
+            (_, KeyCode::Char('+' | '=')) => self.change_transpose(1),
+            (_, KeyCode::Char('a')) => self.mark_loop_start(),
+            (_, KeyCode::Char('b')) => self.mark_loop_end(),
+            (_, KeyCode::Char('c')) => {
+                self.loop_start = None;
+                self.player.clear_loop();
+            }
+            (_, KeyCode::Char('z')) => self.change_roll_zoom(-1),
+            (_, KeyCode::Char('x')) => self.change_roll_zoom(1),
+            (_, KeyCode::Char('n')) => self.play_next(),
+            (_, KeyCode::Char('p')) => self.play_previous(),
+            (_, KeyCode::Char('r')) => self.set_repeat(self.playlist.repeat().cycle()),
+            (_, KeyCode::Char('S')) => self.playlist.set_shuffle(!self.playlist.shuffle()),
             (_, KeyCode::Char('q')) => self.should_quit = true,
             // For testing purposes, you can uncomment the following lines to trigger a panic or an error.
-            // (_, KeyCode::Char('p')) => panic!("User triggered panic"),
+            // (_, KeyCode::Char('P')) => panic!("User triggered panic"),
             // (_, KeyCode::Char('e')) => bail!("User triggered error"),
             _ => {}
         }
         Ok(())
     }
+
+    /// Handles the keys of the current tab, returns whether `key` was one of them.
+    fn on_tab_key_event(&mut self, key: KeyEvent) -> bool {
+        match (self.tab, key.code) {
+            (Tab::Files, code) => return self.on_files_key_event(code),
+            (Tab::Player, KeyCode::Up) => {
+                self.selected_channel = self.selected_channel.saturating_sub(1);
+            }
+            (Tab::Player, KeyCode::Down) => {
+                self.selected_channel = (self.selected_channel + 1).min(15);
+            }
+            (Tab::Player, KeyCode::Char('m')) => self.toggle_mute(),
+            (Tab::Player, KeyCode::Char('s')) => self.toggle_solo(),
+            (Tab::Playlist, KeyCode::Up) => {
+                self.playlist_cursor = self.playlist_cursor.saturating_sub(1);
+            }
+            (Tab::Playlist, KeyCode::Down) => {
+                self.playlist_cursor =
+                    (self.playlist_cursor + 1).min(self.playlist.entries().len().saturating_sub(1));
+            }
+            (Tab::Playlist, KeyCode::Enter) => self.jump_to(self.playlist_cursor),
+            (Tab::Playlist, KeyCode::Char('d') | KeyCode::Delete) => {
+                self.remove_entry(self.playlist_cursor);
+            }
+            (Tab::Playlist, KeyCode::Char('o')) => self.start_prompt(PromptAction::Open),
+            (Tab::Playlist, KeyCode::Char('w')) => self.start_prompt(PromptAction::Save),
+            (Tab::SoundFont, KeyCode::Up) => self.soundfonts.move_cursor(-1),
+            (Tab::SoundFont, KeyCode::Down) => self.soundfonts.move_cursor(1),
+            (Tab::SoundFont, KeyCode::PageUp) => self.soundfonts.scroll_presets(-10),
+            (Tab::SoundFont, KeyCode::PageDown) => self.soundfonts.scroll_presets(10),
+            (Tab::SoundFont, KeyCode::Enter) => self.switch_soundfont(),
+            (Tab::SoundFont, KeyCode::Char('d')) => self.make_default_soundfont(),
+            (Tab::Settings, KeyCode::Up) => {
+                self.settings.selected = self.settings.selected.previous()
+            }
+            (Tab::Settings, KeyCode::Down) => {
+                self.settings.selected = self.settings.selected.next()
+            }
+            (Tab::Settings, KeyCode::Left) => self.change_setting(-1),
+            (Tab::Settings, KeyCode::Right) => self.change_setting(1),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Handles the keys of the file browser, returns whether `code` was one of them. While
+    /// searching, typed characters go to the search.
+    fn on_files_key_event(&mut self, code: KeyCode) -> bool {
+        let searching = self.browser.searching();
+        match code {
+            KeyCode::Up => self.browser.move_cursor(-1),
+            KeyCode::Down => self.browser.move_cursor(1),
+            KeyCode::PageUp => self.browser.move_cursor(-10),
+            KeyCode::PageDown => self.browser.move_cursor(10),
+            KeyCode::Enter => {
+                self.browser.end_search();
+                self.open_selected_file(true);
+            }
+            KeyCode::Esc => self.browser.clear_search(),
+            KeyCode::Backspace if searching => self.browser.pop_query(),
+            KeyCode::Char(c) if searching => self.browser.push_query(c),
+            KeyCode::Char('/') => self.browser.start_search(),
+            KeyCode::Char('e') => self.open_selected_file(false),
+            KeyCode::Left | KeyCode::Backspace => {
+                if let Some(parent) = self.browser.dir().parent().map(Path::to_path_buf) {
+                    self.browse(&parent);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Open the selected directory of the file browser, or add the selected file to the
+    /// playlist, playing it right away with `play`.
+    fn open_selected_file(&mut self, play: bool) {
+        let Some(entry) = self.browser.selected().cloned() else {
+            return;
+        };
+        if entry.is_dir {
+            self.browse(&entry.path);
+        } else if play {
+            self.play_file(entry.path);
+        } else {
+            self.load_midi(entry.path);
+        }
+    }
+
+    /// Switch to the SoundFont selected on the SoundFont tab, loading it the first time.
+    fn switch_soundfont(&mut self) {
+        let Some(entry) = self.soundfonts.selected().cloned() else {
+            return;
+        };
+        match self.soundfonts.soundfont(&entry).cloned() {
+            Some(soundfont) => {
+                self.soundfonts
+                    .set_loaded(&entry.path, Arc::clone(&soundfont));
+                self.player.set_soundfont(soundfont);
+            }
+            None => self.load_soundfont(entry.path),
+        }
+    }
+
+    /// Parse the SoundFont selected on the SoundFont tab in the background to show its presets,
+    /// if it wasn't loaded. One at a time.
+    fn preview_soundfont(&mut self) {
+        if let Some(path) = self.soundfonts.preview_wanted() {
+            self.loads.spawn_blocking(move || {
+                let soundfont = loader::read_soundfont(&path);
+                Loaded::SoundFontPreview(path, soundfont)
+            });
+        }
+    }
+
+    /// Load the SoundFont selected on the SoundFont tab at startup from now on.
+    fn make_default_soundfont(&mut self) {
+        let Some(path) = self.soundfonts.selected().map(|entry| entry.path.clone()) else {
+            return;
+        };
+        if let Err(err) = self.soundfonts.set_default(path) {
+            self.show_error(&err);
+        }
+    }
+
+    /// Ask for the path of the playlist file to open or save, the last one by default.
+    fn start_prompt(&mut self, action: PromptAction) {
+        let input = self.playlist_file.as_ref().map_or_else(
+            || "playlist.m3u8".to_string(),
+            |path| path.display().to_string(),
+        );
+        self.prompt = Some(PathPrompt { action, input });
+    }
+
+    /// Edit the path being typed, Enter to confirm, Esc to cancel.
+    fn on_prompt_key_event(&mut self, key: KeyEvent) {
+        let Some(prompt) = &mut self.prompt else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Enter => {
+                let action = prompt.action;
+                let path = PathBuf::from(prompt.input.trim());
+                self.prompt = None;
+                if path.as_os_str().is_empty() {
+                    return;
+                }
+                match action {
+                    PromptAction::Open => self.open_playlist(path, true),
+                    PromptAction::Save => self.save_playlist(path),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Step the selected setting down (negative `step`) or up.
+    fn change_setting(&mut self, step: i8) {
+        match self.settings.selected {
+            Setting::MasterGain => {
+                let gain_db = self.player.mixer().master_gain_db() + f32::from(step);
+                self.player.set_master_gain_db(gain_db);
+            }
+            Setting::Speed => self.change_speed(f64::from(step) * Self::SPEED_STEP),
+            Setting::Transpose => self.change_transpose(step),
+            Setting::Repeat => {
+                let mut repeat = self.playlist.repeat().cycle();
+                if step < 0 {
+                    repeat = repeat.cycle();
+                }
+                self.set_repeat(repeat);
+            }
+            Setting::RollWindow => self.change_roll_zoom(i32::from(step)),
+        }
+    }
+
+    fn change_speed(&mut self, step: f64) {
+        let speed = self.player.song_settings().speed() + step;
+        // Stay on the grid of steps, also after clamping.
+        let speed = (speed / Self::SPEED_STEP).round() * Self::SPEED_STEP;
+        self.player.set_speed(speed);
+    }
+
+    fn change_transpose(&mut self, semitones: i8) {
+        let transpose = self.player.song_settings().transpose();
+        self.player
+            .set_transpose(transpose.saturating_add(semitones));
+    }
+
+    fn toggle_mute(&self) {
+        let channel = self.selected_channel;
+        let mute = self
+            .player
+            .mixer()
+            .channel(channel)
+            .is_some_and(|mix| mix.mute);
+        self.player.set_channel_mute(channel, !mute);
+    }
+
+    fn toggle_solo(&self) {
+        let channel = self.selected_channel;
+        let solo = self
+            .player
+            .mixer()
+            .channel(channel)
+            .is_some_and(|mix| mix.solo);
+        self.player.set_channel_solo(channel, !solo);
+    }
+
+    /// Show less (negative `step`) or more of the song ahead in the piano roll.
+    fn change_roll_zoom(&mut self, step: i32) {
+        let range = PianoRoll::ZOOM_RANGE;
+        self.settings.roll_zoom =
+            (self.settings.roll_zoom + step).clamp(*range.start(), *range.end());
+    }
+
+    /// Start marking a new A-B loop at the current position.
+    fn mark_loop_start(&mut self) {
+        self.loop_start = Some(self.player.status().position);
+        self.player.clear_loop();
+    }
+
+    /// Loop from the marked start to the current position.
+    fn mark_loop_end(&mut self) {
+        let Some(start) = self.loop_start else {
+            return;
+        };
+        let end = self.player.status().position;
+        match self
+            .player
+            .set_loop(LoopPoint::Time(start), LoopPoint::Time(end))
+        {
+            Ok(()) => self.loop_start = None,
+            Err(err) => self.show_error(&err),
+        }
+    }
+
+    fn toggle_play_pause(&mut self) {
+        let result = match self.player.status().state {
+            PlaybackState::Playing => self.player.pause(),
+            PlaybackState::Paused => self.player.play(),
+            PlaybackState::Stopped | PlaybackState::Finished => Ok(()),
+        };
+        if let Err(err) = result {
+            self.show_error(&err);
+        }
+    }
 }
 
 impl Widget for &App {
@@ -86,18 +800,149 @@ impl Widget for &App {
     /// - <https://docs.rs/ratatui/latest/ratatui/widgets/index.html>
     /// - <https://github.com/ratatui/ratatui/tree/main/ratatui-widgets/examples>
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let text = Text::raw("TODO");
+        let [tab_bar, content] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(area);
+        TabBar { selected: self.tab }.render(tab_bar, buf);
+
+        let status = self.player.status();
         let area = center(
-            area,
-            Constraint::Length(text.width() as u16),
-            Constraint::Length(1),
+            content,
+            Constraint::Percentage(80),
+            Constraint::Length(content.height.saturating_sub(2)),
         );
-        text.render(area, buf);
+        match self.tab {
+            Tab::Player => {
+                let mixer = self.player.mixer();
+                PlayerTab {
+                    status: &status,
+                    metadata: self.player.metadata(),
+                    last_error: self.last_error.as_deref(),
+                    loop_start: self.loop_start,
+                    notes: self.player.notes(),
+                    roll_window: PianoRoll::window_for(self.settings.roll_zoom),
+                    mixer: MixerView {
+                        activities: &self.channels,
+                        mixer: &mixer,
+                        soundfont: self.player.soundfont().map(Arc::as_ref),
+                        overrides: self.player.channel_overrides(),
+                        selected: self.selected_channel,
+                        now: Instant::now(),
+                    },
+                }
+                .render(area, buf);
+            }
+            Tab::Playlist => PlaylistTab {
+                playlist: &self.playlist,
+                cursor: self.playlist_cursor,
+                prompt: self.prompt.as_ref().map(|prompt| {
+                    let label = match prompt.action {
+                        PromptAction::Open => t!("app.playlist.open"),
+                        PromptAction::Save => t!("app.playlist.save"),
+                    };
+                    (label, prompt.input.as_str())
+                }),
+                message: self.playlist_message.as_deref(),
+            }
+            .render(area, buf),
+            Tab::Files => FilesTab {
+                browser: &self.browser,
+            }
+            .render(area, buf),
+            Tab::SoundFont => SoundFontTab {
+                library: &self.soundfonts,
+            }
+            .render(area, buf),
+            Tab::Settings => SettingsTab {
+                status: &status,
+                repeat: self.playlist.repeat(),
+                roll_zoom: self.settings.roll_zoom,
+                selected: self.settings.selected,
+            }
+            .render(area, buf),
+            Tab::About => AboutTab.render(area, buf),
+        }
     }
 }
 
+/// Settings of the UI itself, see [`Tab::Settings`].
 #[derive(Debug, Default)]
-struct Settings {}
+struct Settings {
+    /// Zoom level of the piano roll, see [`PianoRoll::window_for`]
+    roll_zoom: i32,
+    /// Setting the left and right keys change
+    selected: Setting,
+}
+
+/// The next info of `midi_infos`, `None` once all are read. Never ready without a receiver.
+async fn next_midi_info(
+    midi_infos: &mut Option<mpsc::UnboundedReceiver<(PathBuf, MidiInfo)>>,
+) -> Option<(PathBuf, MidiInfo)> {
+    match midi_infos {
+        Some(receiver) => receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// `m:ss` of `duration`.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Directory of the key-dash settings of the user, if there is a config directory.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("key-dash"))
+}
+
+/// User facing message of `err`, in the current locale.
+fn localize_error(err: &PlayerError) -> String {
+    match err {
+        PlayerError::NoSink => t!("app.error.no_sink").into_owned(),
+        PlayerError::NoFont => t!("app.error.no_font").into_owned(),
+        PlayerError::NoMidi => t!("app.error.no_midi").into_owned(),
+        PlayerError::NoDevice => t!("app.error.no_device").into_owned(),
+        PlayerError::EmptyLoop => t!("app.error.empty_loop").into_owned(),
+        PlayerError::Io { path, source } => {
+            t!("app.error.io", path = path.display(), error = source).into_owned()
+        }
+        PlayerError::Write { path, source } => {
+            t!("app.error.write", path = path.display(), error = source).into_owned()
+        }
+        PlayerError::SoundFont {
+            path: Some(path),
+            source,
+        } => t!("app.error.soundfont", path = path.display(), error = source).into_owned(),
+        PlayerError::SoundFont { path: None, source } => {
+            t!("app.error.soundfont_data", error = source).into_owned()
+        }
+        PlayerError::Midi {
+            path: Some(path),
+            offset,
+            source,
+        } => t!(
+            "app.error.midi",
+            path = path.display(),
+            offset = offset,
+            error = source
+        )
+        .into_owned(),
+        PlayerError::Midi {
+            path: None,
+            offset,
+            source,
+        } => t!("app.error.midi_data", offset = offset, error = source).into_owned(),
+        PlayerError::Synthesizer(source) => {
+            t!("app.error.synthesizer", error = source).into_owned()
+        }
+        PlayerError::AudioOutput(source) => {
+            t!("app.error.audio_output", error = source).into_owned()
+        }
+    }
+}
 
 fn center(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect {
     let [area] = Layout::horizontal([horizontal])
@@ -106,3 +951,57 @@ fn center(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect {
     let [area] = Layout::vertical([vertical]).flex(Flex::Center).areas(area);
     area
 }
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_millis(125_900)), "2:05");
+    assert_eq!(format_duration(Duration::from_secs(3725)), "62:05");
+}
+
+#[test]
+fn test_localize_error() {
+    rust_i18n::set_locale("en");
+    assert_eq!(
+        localize_error(&PlayerError::EmptyLoop),
+        "A loop must end after it starts"
+    );
+    let err = PlayerError::Midi {
+        path: Some("song.mid".into()),
+        offset: 14,
+        source: midi_msg::ParseError::UnexpectedEnd,
+    };
+    assert!(localize_error(&err).starts_with("Could not read MIDI file song.mid at byte 14: "));
+    // Data that didn't come from a file leaves out the path.
+    let err = PlayerError::Midi {
+        path: None,
+        offset: 14,
+        source: midi_msg::ParseError::UnexpectedEnd,
+    };
+    assert!(localize_error(&err).starts_with("Could not read MIDI file at byte 14: "));
+}
+
+#[test]
+fn test_tab_keys() {
+    use crossterm::event::KeyModifiers;
+
+    let mut app = App::default();
+    let mut press = |code| {
+        app.on_key_event(KeyEvent::new(code, KeyModifiers::NONE))
+            .unwrap()
+    };
+    press(KeyCode::Tab);
+    press(KeyCode::Char('5'));
+    press(KeyCode::Down);
+    press(KeyCode::Right);
+    assert_eq!(app.tab, Tab::Settings);
+    assert_eq!(app.settings.selected, Setting::Speed);
+    assert_eq!(app.player.song_settings().speed(), 1.05);
+    // Up and down select mixer channels on the Player tab instead.
+    app.on_key_event(KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT))
+        .unwrap();
+    app.on_key_event(KeyEvent::new(KeyCode::Char('1'), KeyModifiers::NONE))
+        .unwrap();
+    app.on_key_event(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE))
+        .unwrap();
+    assert_eq!((app.tab, app.selected_channel), (Tab::Player, 1));
+}
@@ -0,0 +1,51 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Stylize,
+    text::Line,
+    widgets::{Paragraph, Widget},
+};
+
+/// Content of [`super::Tab::About`]: Name, version and key bindings.
+pub struct AboutTab;
+
+impl AboutTab {
+    /// Keys and the i18n key of what they do.
+    const KEYS: [(&str, &str); 15] = [
+        ("Space", "app.about.keys.play_pause"),
+        ("[ ]", "app.about.keys.speed"),
+        ("- +", "app.about.keys.transpose"),
+        ("a b c", "app.about.keys.loop"),
+        ("n p", "app.about.keys.next_previous"),
+        ("r", "app.about.keys.repeat"),
+        ("S", "app.about.keys.shuffle"),
+        ("z x", "app.about.keys.zoom"),
+        ("↑ ↓ m s", "app.about.keys.mixer"),
+        ("↑↓ ⏎ d o w", "app.about.keys.playlist"),
+        ("⏎ e ← /", "app.about.keys.files"),
+        ("↑↓ ⏎ d ⇞⇟", "app.about.keys.soundfont"),
+        ("Tab ⇧Tab", "app.about.keys.tabs"),
+        ("1-6", "app.about.keys.tab_number"),
+        ("q", "app.about.keys.quit"),
+    ];
+}
+
+impl Widget for AboutTab {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines = vec![
+            Line::from(vec![
+                t!("app.name").into_owned().bold(),
+                format!(" {}", env!("CARGO_PKG_VERSION")).into(),
+            ]),
+            Line::from(t!("app.description").into_owned()),
+            Line::default(),
+            Line::from(t!("app.about.keys.title").into_owned().bold()),
+        ];
+        lines.extend(
+            Self::KEYS
+                .iter()
+                .map(|(key, action)| Line::from(format!("{key:>10}  {}", t!(*action)))),
+        );
+        Paragraph::new(lines).render(area, buf);
+    }
+}
@@ -0,0 +1,338 @@
+use key_dash_audio::loader::{self, MidiInfo};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{List, ListItem, ListState, StatefulWidget, Widget},
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::ui::format_duration;
+
+/// A directory or MIDI file listed by the [`FileBrowser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// Directory listing of [`super::Tab::Files`]: Subdirectories and MIDI files, narrowed down by a
+/// fuzzy search.
+#[derive(Debug, Default)]
+pub struct FileBrowser {
+    dir: PathBuf,
+    /// Parent directory, subdirectories, then MIDI files, by name
+    entries: Vec<BrowserEntry>,
+    /// Track counts and durations, read in the background
+    infos: HashMap<PathBuf, MidiInfo>,
+    query: String,
+    /// Typed characters go to `query`
+    searching: bool,
+    /// Indices of `entries` matching `query`, best first
+    visible: Vec<usize>,
+    /// Index into `visible`
+    cursor: usize,
+}
+
+impl FileBrowser {
+    /// Name the parent directory is listed as.
+    const PARENT: &str = "..";
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// List `dir`. Coming back up from a subdirectory selects it.
+    pub fn open(&mut self, dir: &Path) -> io::Result<()> {
+        let dir = std::path::absolute(dir)?;
+        let mut entries = vec![];
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            // Follows symbolic links.
+            let is_dir = path.is_dir();
+            if is_dir || loader::is_midi(&path) {
+                entries.push(BrowserEntry { name, path, is_dir });
+            }
+        }
+        entries.sort_by_cached_key(|entry| (!entry.is_dir, entry.name.to_lowercase()));
+        if let Some(parent) = dir.parent() {
+            entries.insert(
+                0,
+                BrowserEntry {
+                    name: Self::PARENT.to_string(),
+                    path: parent.to_path_buf(),
+                    is_dir: true,
+                },
+            );
+        }
+
+        let previous = std::mem::replace(&mut self.dir, dir);
+        self.entries = entries;
+        self.query.clear();
+        self.searching = false;
+        self.update_visible();
+        self.cursor = self
+            .visible
+            .iter()
+            .position(|idx| self.entries[*idx].path == previous)
+            .unwrap_or(0);
+        Ok(())
+    }
+
+    /// MIDI files of the directory without a [`MidiInfo`] yet.
+    pub fn missing_infos(&self) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.is_dir && !self.infos.contains_key(&entry.path))
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    pub fn set_info(&mut self, path: PathBuf, info: MidiInfo) {
+        self.infos.insert(path, info);
+    }
+
+    pub fn selected(&self) -> Option<&BrowserEntry> {
+        self.visible.get(self.cursor).map(|idx| &self.entries[*idx])
+    }
+
+    /// Move the selection up (negative `step`) or down.
+    pub fn move_cursor(&mut self, step: isize) {
+        let last = self.visible.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(step).min(last);
+    }
+
+    pub fn searching(&self) -> bool {
+        self.searching
+    }
+
+    /// Send typed characters to the search.
+    pub const fn start_search(&mut self) {
+        self.searching = true;
+    }
+
+    /// Stop typing, keeping the matches.
+    pub const fn end_search(&mut self) {
+        self.searching = false;
+    }
+
+    /// Stop searching, listing everything again.
+    pub fn clear_search(&mut self) {
+        self.searching = false;
+        self.query.clear();
+        self.update_visible();
+    }
+
+    pub fn push_query(&mut self, c: char) {
+        self.query.push(c);
+        self.update_visible();
+    }
+
+    pub fn pop_query(&mut self) {
+        self.query.pop();
+        self.update_visible();
+    }
+
+    fn update_visible(&mut self) {
+        if self.query.is_empty() {
+            self.visible = (0..self.entries.len()).collect();
+        } else {
+            let mut scored: Vec<_> = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.name != Self::PARENT)
+                .filter_map(|(idx, entry)| Some((idx, fuzzy_score(&self.query, &entry.name)?)))
+                .collect();
+            // Stable: Equal scores keep directories first and names in order.
+            scored.sort_by_key(|(_, score)| Reverse(*score));
+            self.visible = scored.into_iter().map(|(idx, _)| idx).collect();
+        }
+        self.cursor = 0;
+    }
+}
+
+/// Score of `text` for the fuzzy search `query`, higher is better: The characters of `query` must
+/// appear in `text` in order, ignoring case. Consecutive characters and characters starting a
+/// word score more.
+fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut start = 0;
+    let mut previous = None;
+    for c in query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+    {
+        let found = start + text[start..].iter().position(|t| *t == c)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 4;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 2;
+        }
+        previous = Some(found);
+        start = found + 1;
+    }
+    Some(score)
+}
+
+/// `name` cut to `width` columns with an ellipsis if it's wider, else padded to them.
+fn fit(name: &str, width: usize) -> String {
+    let columns = |text: &str| Span::raw(text).width();
+    let name_columns = columns(name);
+    if name_columns <= width {
+        return format!("{name}{}", " ".repeat(width - name_columns));
+    }
+    let mut fitted = String::new();
+    // Leaves a column for the ellipsis.
+    let mut used = 1;
+    for c in name.chars() {
+        used += columns(c.encode_utf8(&mut [0; 4]));
+        if used > width {
+            break;
+        }
+        fitted.push(c);
+    }
+    if width > 0 {
+        fitted.push('…');
+    }
+    format!(
+        "{fitted}{}",
+        " ".repeat(width.saturating_sub(columns(&fitted)))
+    )
+}
+
+/// Content of [`super::Tab::Files`]: The [`FileBrowser`] with the track count and duration of
+/// each MIDI file.
+pub struct FilesTab<'a> {
+    pub browser: &'a FileBrowser,
+}
+
+impl Widget for FilesTab<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [header, search, list, _, hint] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let browser = self.browser;
+
+        Line::from(browser.dir.display().to_string())
+            .bold()
+            .render(header, buf);
+        if browser.searching || !browser.query.is_empty() {
+            let mut spans = vec!["/".dark_gray(), browser.query.as_str().into()];
+            if browser.searching {
+                spans.push("▏".slow_blink());
+            }
+            Line::from(spans).render(search, buf);
+        }
+        let keys = if browser.searching {
+            t!("app.files.search_hint")
+        } else {
+            t!("app.files.hint")
+        };
+        keys.into_owned().dark_gray().render(hint, buf);
+
+        if browser.visible.is_empty() {
+            t!("app.files.empty")
+                .into_owned()
+                .dark_gray()
+                .render(list, buf);
+            return;
+        }
+        let name_width = usize::from(list.width.saturating_sub(18));
+        let items = browser.visible.iter().map(|idx| {
+            let entry = &browser.entries[*idx];
+            if entry.is_dir {
+                let name = fit(&format!("{}/", entry.name), list.width.into());
+                return ListItem::new(Line::from(name).blue().bold());
+            }
+            let (tracks, duration) =
+                browser
+                    .infos
+                    .get(&entry.path)
+                    .map_or((String::new(), String::new()), |info| {
+                        (
+                            t!("app.files.tracks", count = info.tracks).into_owned(),
+                            format_duration(info.duration),
+                        )
+                    });
+            ListItem::new(Line::from(format!(
+                "{} {tracks:>10} {duration:>6}",
+                fit(&entry.name, name_width)
+            )))
+        });
+        let mut state = ListState::default().with_selected(Some(browser.cursor));
+        StatefulWidget::render(
+            List::new(items).highlight_style(Style::new().reversed()),
+            list,
+            buf,
+            &mut state,
+        );
+    }
+}
+
+#[test]
+fn test_fuzzy_score() {
+    assert_eq!(fuzzy_score("xyz", "song.mid"), None);
+    assert_eq!(fuzzy_score("SNG", "song.mid"), Some(9));
+    // Consecutive characters at a word start beat scattered ones.
+    assert!(fuzzy_score("bach", "js bach.mid") > fuzzy_score("bach", "bright march.mid"));
+}
+
+#[test]
+fn test_fit() {
+    assert_eq!(fit("song.mid", 10), "song.mid  ");
+    assert_eq!(fit("a very long name.mid", 10), "a very lo…");
+    // Wide characters take two columns.
+    assert_eq!(fit("小夜曲第一号.mid", 8), "小夜曲… ");
+    assert_eq!(fit("song.mid", 0), "");
+}
+
+#[test]
+fn test_file_browser() {
+    let dir = std::env::temp_dir().join(format!("key-dash-browser-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("Bach")).unwrap();
+    for name in ["minuet.MID", "toccata.kar", "notes.txt", ".hidden.mid"] {
+        std::fs::write(dir.join(name), b"").unwrap();
+    }
+    let mut browser = FileBrowser::default();
+    browser.open(&dir.join("Bach")).unwrap();
+    browser.open(&dir).unwrap();
+    let names: Vec<_> = browser
+        .visible
+        .iter()
+        .map(|idx| browser.entries[*idx].name.as_str())
+        .collect();
+    assert_eq!(names, ["..", "Bach", "minuet.MID", "toccata.kar"]);
+    // Back up from Bach, which stays selected.
+    assert_eq!(browser.selected().unwrap().name, "Bach");
+    assert_eq!(browser.missing_infos().len(), 2);
+
+    browser.start_search();
+    for c in "tca".chars() {
+        browser.push_query(c);
+    }
+    assert_eq!(browser.selected().unwrap().name, "toccata.kar");
+    browser.clear_search();
+    assert_eq!(browser.visible.len(), 4);
+    std::fs::remove_dir_all(dir).unwrap();
+}
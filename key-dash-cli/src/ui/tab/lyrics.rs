@@ -0,0 +1,98 @@
+use key_dash_audio::lyrics::{LyricLine, LyricPosition, Lyrics};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span},
+    widgets::{Paragraph, Widget},
+};
+use std::time::Duration;
+
+/// Karaoke lyrics of the Player tab: The line being sung with the current syllable highlighted,
+/// between the previous line and the following ones.
+pub struct LyricsPanel<'a> {
+    pub lyrics: &'a Lyrics,
+    pub position: Duration,
+}
+
+impl Widget for LyricsPanel<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() || self.lyrics.is_empty() {
+            return;
+        }
+        let current = self.lyrics.position_at(self.position);
+        // Keep one line of context above the current one.
+        let first = current.map_or(0, |current| current.line.saturating_sub(1));
+        let lines: Vec<_> = self
+            .lyrics
+            .lines
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(usize::from(area.height))
+            .map(|(idx, line)| match current {
+                Some(current) if idx < current.line => line_of(line).dark_gray(),
+                Some(current) if idx == current.line => sung_line(line, current),
+                _ => line_of(line),
+            })
+            .collect();
+        Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .render(area, buf);
+    }
+}
+
+fn line_of(line: &LyricLine) -> Line<'_> {
+    Line::from(line.text())
+}
+
+/// `line` with the syllables up to `current` in color, the current one stands out.
+fn sung_line(line: &LyricLine, current: LyricPosition) -> Line<'_> {
+    let spans = line.syllables.iter().enumerate().map(|(idx, syllable)| {
+        let style = match idx.cmp(&current.syllable) {
+            std::cmp::Ordering::Less => Style::new().cyan(),
+            std::cmp::Ordering::Equal => Style::new().yellow().bold(),
+            std::cmp::Ordering::Greater => Style::new(),
+        };
+        Span::styled(syllable.text.as_str(), style)
+    });
+    Line::from_iter(spans)
+}
+
+#[test]
+fn test_lyrics_panel() {
+    use key_dash_audio::lyrics::Syllable;
+    use ratatui::style::Color;
+
+    let syllable = |millis, text: &str| Syllable {
+        time: Duration::from_millis(millis),
+        text: text.to_string(),
+    };
+    let lyrics = Lyrics {
+        headers: vec![],
+        lines: vec![
+            LyricLine {
+                paragraph: true,
+                syllables: vec![syllable(0, "Row "), syllable(500, "row")],
+            },
+            LyricLine {
+                paragraph: false,
+                syllables: vec![syllable(1000, "your "), syllable(1500, "boat")],
+            },
+        ],
+    };
+    let area = Rect::new(0, 0, 9, 2);
+    let mut buf = Buffer::empty(area);
+    LyricsPanel {
+        lyrics: &lyrics,
+        position: Duration::from_millis(1600),
+    }
+    .render(area, &mut buf);
+
+    assert_eq!(buf[(0, 0)].symbol(), " ");
+    assert_eq!(buf[(1, 0)].fg, Color::DarkGray);
+    // "your boat" fills the second row, "boat" is being sung.
+    assert_eq!(buf[(0, 1)].fg, Color::Cyan);
+    assert_eq!(buf[(5, 1)].symbol(), "b");
+    assert_eq!(buf[(5, 1)].fg, Color::Yellow);
+}
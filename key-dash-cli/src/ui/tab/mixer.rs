@@ -0,0 +1,253 @@
+use key_dash_audio::{ChannelOverride, Mixer, PlayerEvent, gm};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::Widget,
+};
+use rustysynth::SoundFont;
+use std::time::{Duration, Instant};
+
+use super::piano_roll::CHANNEL_COLORS;
+
+/// What the UI knows about a MIDI channel, followed from [`PlayerEvent`]s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ChannelActivity {
+    program: u8,
+    /// Bank Select MSB
+    bank: u8,
+    drums: bool,
+    /// Channel Volume controller
+    volume: u8,
+    /// Pan controller, 64 is center
+    pan: u8,
+    /// Notes held
+    held: u16,
+    /// Velocity of the last note on, 0 to 1
+    peak: f32,
+    peak_at: Option<Instant>,
+}
+
+impl ChannelActivity {
+    const fn new(channel: u8) -> Self {
+        Self {
+            program: 0,
+            bank: 0,
+            drums: channel == 9,
+            volume: 100,
+            pan: 64,
+            held: 0,
+            peak: 0.,
+            peak_at: None,
+        }
+    }
+}
+
+/// Programs, controllers and note activity of all 16 channels during playback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelActivities {
+    channels: [ChannelActivity; 16],
+}
+
+impl Default for ChannelActivities {
+    fn default() -> Self {
+        Self {
+            channels: std::array::from_fn(|channel| ChannelActivity::new(channel as u8)),
+        }
+    }
+}
+
+impl ChannelActivities {
+    /// Time for a meter to fall to half its peak.
+    const HALF_LIFE: Duration = Duration::from_millis(250);
+
+    /// Follow `event`, received at `now`.
+    pub fn handle_event(&mut self, event: &PlayerEvent, now: Instant) {
+        match *event {
+            PlayerEvent::NoteOn {
+                channel, velocity, ..
+            } => {
+                let activity = &mut self.channels[usize::from(channel & 0x0f)];
+                activity.held = activity.held.saturating_add(1);
+                activity.peak = f32::from(velocity) / 127.;
+                activity.peak_at = Some(now);
+            }
+            PlayerEvent::NoteOff { channel, .. } => {
+                let activity = &mut self.channels[usize::from(channel & 0x0f)];
+                activity.held = activity.held.saturating_sub(1);
+            }
+            PlayerEvent::ControlChange {
+                channel,
+                control,
+                value,
+            } => {
+                let activity = &mut self.channels[usize::from(channel & 0x0f)];
+                match control {
+                    0x00 => activity.bank = value,
+                    0x07 => activity.volume = value,
+                    0x0a => activity.pan = value,
+                    // All Sound Off, All Notes Off and the mode changes that imply it
+                    0x78 | 0x7b..=0x7f => activity.held = 0,
+                    _ => (),
+                }
+            }
+            PlayerEvent::ProgramChange { channel, program } => {
+                self.channels[usize::from(channel & 0x0f)].program = program;
+            }
+            PlayerEvent::DrumPart { channel, drums } => {
+                self.channels[usize::from(channel & 0x0f)].drums = drums;
+            }
+            PlayerEvent::Reset => *self = Self::default(),
+            _ => (),
+        }
+    }
+
+    /// Meter level of `channel` at `now`, 0 to 1: The last velocity, fading out faster once the
+    /// notes are released.
+    fn level(&self, channel: usize, now: Instant) -> f32 {
+        let activity = &self.channels[channel];
+        let Some(peak_at) = activity.peak_at else {
+            return 0.;
+        };
+        let elapsed = now.saturating_duration_since(peak_at);
+        let fading = activity.peak * 0.5f32.powf(elapsed.div_duration_f32(Self::HALF_LIFE));
+        if activity.held > 0 {
+            fading.max(activity.peak / 2.)
+        } else {
+            fading
+        }
+    }
+}
+
+/// Channel mixer of the Player tab: One row per MIDI channel with its instrument, activity meter,
+/// pan, volume and mute/solo state.
+pub struct MixerView<'a> {
+    pub activities: &'a ChannelActivities,
+    pub mixer: &'a Mixer,
+    /// Names the presets, General MIDI names are shown without one
+    pub soundfont: Option<&'a SoundFont>,
+    /// Channels played by another SoundFont or preset, named after those
+    pub overrides: &'a [Option<ChannelOverride>; 16],
+    /// Channel the mute and solo keys apply to
+    pub selected: u8,
+    pub now: Instant,
+}
+
+impl MixerView<'_> {
+    /// Columns needed for a row.
+    pub const WIDTH: u16 = 46;
+    const NAME_WIDTH: u16 = 20;
+    const METER_WIDTH: u16 = 10;
+}
+
+impl Widget for MixerView<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let rows = Layout::vertical([Constraint::Length(1); 16]).split(area);
+        for (channel, row) in rows.iter().enumerate() {
+            let activity = &self.activities.channels[channel];
+            let mix = self
+                .mixer
+                .channel(channel as u8)
+                .copied()
+                .unwrap_or_default();
+            let audible = self.mixer.is_audible(channel as u8);
+
+            let channel_override = self.overrides[channel].as_ref();
+            let soundfont = channel_override
+                .and_then(|channel_override| channel_override.soundfont.as_deref())
+                .or(self.soundfont);
+            let (bank, program, drums) =
+                match channel_override.and_then(|channel_override| channel_override.preset) {
+                    Some((bank, program)) => (
+                        u8::try_from(bank).unwrap_or(0),
+                        program,
+                        bank >= gm::DRUM_BANK,
+                    ),
+                    None => (activity.bank, activity.program, activity.drums),
+                };
+            let name = gm::instrument_name(soundfont, bank, program, drums);
+            let name: String = name.chars().take(usize::from(Self::NAME_WIDTH)).collect();
+            let level = self.activities.level(channel, self.now);
+            let filled = (level * f32::from(Self::METER_WIDTH)).round() as usize;
+            let meter = format!(
+                "{}{}",
+                "█".repeat(filled),
+                "·".repeat(usize::from(Self::METER_WIDTH) - filled)
+            );
+            let pan = match i16::from(activity.pan) - 64 {
+                0 => "C".to_string(),
+                pan if pan < 0 => format!("L{}", -pan),
+                pan => format!("R{pan}"),
+            };
+
+            let flag = |on: bool, text: &'static str, color: Color| {
+                if on {
+                    Span::styled(text, Style::new().fg(color).bold())
+                } else {
+                    Span::raw("·")
+                }
+            };
+            let line = Line::from(vec![
+                format!("{:>2} ", channel + 1).into(),
+                flag(mix.mute, "M", Color::Red),
+                flag(mix.solo, "S", Color::Yellow),
+                format!(" {name:<width$} ", width = usize::from(Self::NAME_WIDTH)).into(),
+                Span::styled(meter, Style::new().fg(CHANNEL_COLORS[channel])),
+                format!(" {pan:>3} {:>3}", activity.volume).into(),
+            ]);
+            let line = if audible { line } else { line.dark_gray() };
+            let line = if channel == usize::from(self.selected) {
+                line.reversed()
+            } else {
+                line
+            };
+            line.render(*row, buf);
+        }
+    }
+}
+
+#[test]
+fn test_channel_activities() {
+    let now = Instant::now();
+    let mut activities = ChannelActivities::default();
+    let events = [
+        PlayerEvent::ProgramChange {
+            channel: 1,
+            program: 40,
+        },
+        PlayerEvent::ControlChange {
+            channel: 1,
+            control: 0x0a,
+            value: 32,
+        },
+        PlayerEvent::NoteOn {
+            channel: 1,
+            note: 60,
+            velocity: 127,
+        },
+    ];
+    for event in &events {
+        activities.handle_event(event, now);
+    }
+    let activity = activities.channels[1];
+    assert_eq!((activity.program, activity.pan, activity.held), (40, 32, 1));
+    assert!(activities.channels[9].drums);
+
+    // Held notes keep half the peak, released ones fade out.
+    assert_eq!(
+        activities.level(1, now + ChannelActivities::HALF_LIFE * 4),
+        0.5
+    );
+    activities.handle_event(
+        &PlayerEvent::NoteOff {
+            channel: 1,
+            note: 60,
+        },
+        now,
+    );
+    assert_eq!(activities.level(1, now + ChannelActivities::HALF_LIFE), 0.5);
+
+    activities.handle_event(&PlayerEvent::Reset, now);
+    assert_eq!(activities, ChannelActivities::default());
+}
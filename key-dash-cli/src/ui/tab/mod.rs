@@ -1,9 +1,30 @@
+mod about;
+mod files;
+mod lyrics;
+mod mixer;
+mod piano_roll;
+mod player;
+mod playlist;
+mod settings;
+mod soundfont;
+
+pub use about::AboutTab;
+pub use files::{FileBrowser, FilesTab};
+pub use lyrics::LyricsPanel;
+pub use mixer::{ChannelActivities, MixerView};
+pub use piano_roll::PianoRoll;
+pub use player::PlayerTab;
+pub use playlist::PlaylistTab;
+pub use settings::{Setting, SettingsTab};
+pub use soundfont::{SoundFontLibrary, SoundFontTab};
+
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
-    widgets::{Block, BorderType, Paragraph, Widget},
+    style::{Style, Stylize},
+    widgets::{Tabs, Widget},
 };
-use strum::{Display, EnumIter, FromRepr};
+use strum::{Display, EnumIter, FromRepr, IntoEnumIterator};
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumIter, FromRepr)]
 pub enum Tab {
@@ -12,6 +33,8 @@ pub enum Tab {
     Player,
     #[strum(to_string = "app.tab.playlist")]
     Playlist,
+    #[strum(to_string = "app.tab.files")]
+    Files,
     #[strum(to_string = "app.tab.soundfont")]
     SoundFont,
     #[strum(to_string = "app.tab.settings")]
@@ -34,17 +57,42 @@ impl Tab {
         let next_index = current_index.saturating_add(1);
         Self::from_repr(next_index).unwrap_or(self)
     }
+
+    /// Tab selected by number key `digit`, counting from 1 as shown in the [`TabBar`].
+    pub fn from_digit(digit: char) -> Option<Self> {
+        let index = digit.to_digit(10)?.checked_sub(1)?;
+        Self::from_repr(index as usize)
+    }
+
+    /// Title in the current locale.
+    pub fn title(self) -> String {
+        t!(self.to_string()).into_owned()
+    }
+}
+
+/// Titles of all tabs, numbered for the number keys, with `selected` highlighted.
+pub struct TabBar {
+    pub selected: Tab,
 }
 
-impl Widget for Tab {
+impl Widget for TabBar {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        // in a real app these might be separate widgets
-        match self {
-            Self::Player => Paragraph::new("Player").render(area, buf),
-            Self::Playlist => Paragraph::new("Playlist").render(area, buf),
-            Self::SoundFont => Paragraph::new("SoundFont").render(area, buf),
-            Self::Settings => Paragraph::new("Settings").render(area, buf),
-            Self::About => Paragraph::new("About").render(area, buf),
-        }
+        let titles = Tab::iter()
+            .enumerate()
+            .map(|(idx, tab)| format!("{} {}", idx + 1, tab.title()));
+        Tabs::new(titles)
+            .select(self.selected as usize)
+            .highlight_style(Style::new().reversed().bold())
+            .render(area, buf);
     }
 }
+
+#[test]
+fn test_tab_navigation() {
+    assert_eq!(Tab::Player.previous(), Tab::Player);
+    assert_eq!(Tab::Player.next(), Tab::Playlist);
+    assert_eq!(Tab::About.next(), Tab::About);
+    assert_eq!(Tab::from_digit('5'), Some(Tab::Settings));
+    assert_eq!(Tab::from_digit('0'), None);
+    assert_eq!(Tab::from_digit('7'), None);
+}
@@ -0,0 +1,193 @@
+use key_dash_audio::{NoteList, notes::Note};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+use std::time::Duration;
+
+/// Colour of the notes of each MIDI channel.
+pub const CHANNEL_COLORS: [Color; 16] = [
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::Gray,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+    Color::Indexed(208),
+    Color::Indexed(141),
+    Color::Indexed(37),
+];
+
+/// Scrolling piano roll: Upcoming notes fall towards a keyboard strip, where sounding keys light
+/// up in the colour of their channel.
+pub struct PianoRoll<'a> {
+    pub notes: &'a NoteList,
+    pub position: Duration,
+    /// Song time shown above the keyboard
+    pub window: Duration,
+    /// Semitones non-drum notes are transposed by
+    pub transpose: i8,
+}
+
+impl PianoRoll<'_> {
+    /// Window of zoom level 0.
+    pub const DEFAULT_WINDOW: Duration = Duration::from_secs(4);
+    /// Zoom levels, each halves or doubles the window.
+    pub const ZOOM_RANGE: std::ops::RangeInclusive<i32> = -3..=3;
+
+    /// Time shown above the keyboard at `zoom`, smaller is closer.
+    pub fn window_for(zoom: i32) -> Duration {
+        let zoom = zoom.clamp(*Self::ZOOM_RANGE.start(), *Self::ZOOM_RANGE.end());
+        Self::DEFAULT_WINDOW.mul_f64(2f64.powi(zoom))
+    }
+
+    /// Key `note` sounds with.
+    fn key(&self, note: &Note) -> u8 {
+        if note.channel == 9 {
+            return note.key;
+        }
+        (i16::from(note.key) + i16::from(self.transpose)).clamp(0, 127) as u8
+    }
+
+    /// Keys shown: Whole octaves around the played ones.
+    fn key_range(&self) -> (u8, u8) {
+        let Some((low, high)) = self.notes.key_range() else {
+            return (48, 83);
+        };
+        let transpose = |key: u8| (i16::from(key) + i16::from(self.transpose)).clamp(0, 127);
+        let low = transpose(low).min(i16::from(low));
+        let high = transpose(high).max(i16::from(high));
+        (
+            (low - low % 12) as u8,
+            (high - high % 12 + 11).min(127) as u8,
+        )
+    }
+}
+
+impl Widget for PianoRoll<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height < 2 || area.width == 0 {
+            return;
+        }
+        let (low, high) = self.key_range();
+        let keys = u32::from(high - low) + 1;
+        let width = u32::from(area.width);
+        // One column per key if they fit, else keys share columns.
+        let (columns, left) = if keys <= width {
+            (keys, area.x + ((width - keys) / 2) as u16)
+        } else {
+            (width, area.x)
+        };
+        let column = |key: u8| -> Option<u16> {
+            let offset = u32::from(key.checked_sub(low)?);
+            (key <= high).then(|| left + (offset * columns / keys) as u16)
+        };
+
+        let rows = u32::from(area.height - 1);
+        let keyboard = area.bottom() - 1;
+        let row_length = self.window / rows;
+        if !row_length.is_zero() {
+            let row_of = |time: Duration| {
+                let offset = time.saturating_sub(self.position);
+                (offset.as_nanos() / row_length.as_nanos()) as u32
+            };
+            let end = self.position + self.window;
+            for note in self.notes.between(self.position, end) {
+                let Some(x) = column(self.key(note)) else {
+                    continue;
+                };
+                let first = row_of(note.start);
+                let last =
+                    row_of(note.end.saturating_sub(Duration::from_nanos(1))).clamp(first, rows - 1);
+                let style = Style::new().fg(CHANNEL_COLORS[usize::from(note.channel & 0x0f)]);
+                for row in first..=last {
+                    buf[(x, keyboard - 1 - row as u16)]
+                        .set_symbol("█")
+                        .set_style(style);
+                }
+            }
+        }
+
+        for key in low..=high {
+            let Some(x) = column(key) else {
+                continue;
+            };
+            let cell = &mut buf[(x, keyboard)];
+            // Shared columns show a white key if they have one.
+            if is_white_key(key) || cell.symbol() != "█" {
+                let color = if is_white_key(key) {
+                    Color::White
+                } else {
+                    Color::DarkGray
+                };
+                cell.set_symbol("█").set_fg(color);
+            }
+        }
+        for note in self.notes.sounding_at(self.position) {
+            if let Some(x) = column(self.key(note)) {
+                buf[(x, keyboard)].set_fg(CHANNEL_COLORS[usize::from(note.channel & 0x0f)]);
+            }
+        }
+    }
+}
+
+const fn is_white_key(key: u8) -> bool {
+    matches!(key % 12, 0 | 2 | 4 | 5 | 7 | 9 | 11)
+}
+
+#[test]
+fn test_piano_roll() {
+    use key_dash_audio::tempo_map::TempoMap;
+    use midi_msg::{Channel, ChannelVoiceMsg, MidiFile, MidiMsg, Track};
+
+    let voice = |msg| MidiMsg::ChannelVoice {
+        channel: Channel::Ch2,
+        msg,
+    };
+    let mut midi_file = MidiFile::default();
+    midi_file.add_track(Track::default());
+    midi_file.extend_track(
+        0,
+        voice(ChannelVoiceMsg::NoteOn {
+            note: 60,
+            velocity: 100,
+        }),
+        0.,
+    );
+    midi_file.extend_track(
+        0,
+        voice(ChannelVoiceMsg::NoteOff {
+            note: 60,
+            velocity: 0,
+        }),
+        4.,
+    );
+    let notes = NoteList::new(&midi_file, &TempoMap::new(&midi_file));
+
+    // One octave from C4, one row per second, the note ends at 2s.
+    let area = Rect::new(0, 0, 12, 5);
+    let mut buf = Buffer::empty(area);
+    PianoRoll {
+        notes: &notes,
+        position: Duration::from_millis(500),
+        window: Duration::from_secs(4),
+        transpose: 2,
+    }
+    .render(area, &mut buf);
+
+    let column: Vec<_> = (0..5).map(|y| buf[(2, y)].fg).collect();
+    let green = CHANNEL_COLORS[1];
+    assert_eq!(column, [Color::Reset, Color::Reset, green, green, green]);
+    assert_eq!(buf[(0, 4)].fg, Color::White);
+    assert_eq!(buf[(1, 4)].fg, Color::DarkGray);
+    assert_eq!(PianoRoll::window_for(-1), Duration::from_secs(2));
+}
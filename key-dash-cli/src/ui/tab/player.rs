@@ -0,0 +1,214 @@
+use key_dash_audio::{NoteList, PlaybackState, PlayerStatus, SongMetadata, metadata::MetaKind};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{LineGauge, Widget},
+};
+use std::time::Duration;
+
+use super::{LyricsPanel, MixerView, PianoRoll};
+use crate::ui::format_duration;
+
+/// Content of [`super::Tab::Player`]: Song, SoundFont, playback state, progress, lyrics, the
+/// channel mixer and the piano roll.
+pub struct PlayerTab<'a> {
+    pub status: &'a PlayerStatus,
+    /// Metadata of the current song
+    pub metadata: Option<&'a SongMetadata>,
+    /// Last error reported during playback
+    pub last_error: Option<&'a str>,
+    /// Start of the A-B loop being marked
+    pub loop_start: Option<Duration>,
+    /// Notes of the current song
+    pub notes: Option<&'a NoteList>,
+    /// Song time the piano roll shows ahead
+    pub roll_window: Duration,
+    pub mixer: MixerView<'a>,
+}
+
+impl PlayerTab<'_> {
+    /// Rows of the status lines above the lyrics.
+    const STATUS_HEIGHT: u16 = 13;
+    const LYRICS_HEIGHT: u16 = 4;
+}
+
+impl Widget for PlayerTab<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let has_lyrics = self
+            .metadata
+            .is_some_and(|metadata| !metadata.lyrics().is_empty());
+        let lyrics_height = if has_lyrics { Self::LYRICS_HEIGHT } else { 0 };
+        let [status_area, lyrics, channels] = Layout::vertical([
+            Constraint::Length(Self::STATUS_HEIGHT),
+            Constraint::Length(lyrics_height),
+            Constraint::Fill(1),
+        ])
+        .spacing(1)
+        .areas(area);
+
+        let [file, title, soundfont, state, position, progress, error] =
+            Layout::vertical([Constraint::Length(1); 7])
+                .spacing(1)
+                .areas(status_area);
+
+        let status = self.status;
+        let none = t!("app.player.none");
+        Line::from(vec![
+            t!("app.player.file").bold(),
+            status.midi_name.as_deref().unwrap_or(&none).into(),
+        ])
+        .render(file, buf);
+        let song_title = self.metadata.and_then(SongMetadata::title);
+        let composer = self.metadata.and_then(SongMetadata::composer);
+        let mut spans = vec![
+            t!("app.player.title").into_owned().bold(),
+            song_title.unwrap_or(&none).to_string().into(),
+        ];
+        if let Some(composer) = composer {
+            spans.push(format!("  {composer}").italic());
+        }
+        Line::from(spans).render(title, buf);
+        Line::from(vec![
+            t!("app.player.soundfont").bold(),
+            status.soundfont_name.as_deref().unwrap_or(&none).into(),
+        ])
+        .render(soundfont, buf);
+
+        let state_text = match status.state {
+            PlaybackState::Stopped => t!("app.player.state.stopped"),
+            PlaybackState::Playing => t!("app.player.state.playing"),
+            PlaybackState::Paused => t!("app.player.state.paused"),
+            PlaybackState::Finished => t!("app.player.state.finished"),
+        };
+        let mut spans = vec![
+            state_text.bold(),
+            format!(
+                "  {:+.1} dB  {:.2}x  {:+}",
+                status.master_gain_db, status.speed, status.transpose
+            )
+            .into(),
+        ];
+        match (status.loop_region, self.loop_start) {
+            (_, Some(start)) => spans.push(format!("  A-B {} -", format_duration(start)).yellow()),
+            (Some(region), None) => spans.push(
+                format!(
+                    "  A-B {} - {}",
+                    format_duration(region.start),
+                    format_duration(region.end)
+                )
+                .yellow(),
+            ),
+            (None, None) => (),
+        }
+        if status.repeat_one {
+            spans.push(format!("  {}", t!("app.player.repeat_one")).yellow());
+        }
+        Line::from(spans).render(state, buf);
+
+        if let (Some(metadata), Some(bar_beat)) = (self.metadata, status.bar_beat) {
+            let mut spans = vec![
+                t!("app.player.bar").into_owned().bold(),
+                format!("{}.{}", bar_beat.bar, bar_beat.beat.floor()).into(),
+                format!("  {}", metadata.time_signature_at(status.position)).into(),
+            ];
+            if let Some(key) = metadata.key_signature_at(status.position) {
+                spans.push(format!("  {key}").into());
+            }
+            if let Some(marker) = metadata.marker_at(status.position)
+                && let MetaKind::Marker(text) | MetaKind::CuePoint(text) = &marker.kind
+            {
+                spans.push(format!("  {}", text.trim()).cyan());
+            }
+            Line::from(spans).render(position, buf);
+        }
+
+        let label = format!(
+            "{} / {}",
+            format_duration(status.position),
+            format_duration(status.duration.unwrap_or_default())
+        );
+        LineGauge::default()
+            .filled_style(Style::default().fg(Color::Cyan))
+            .label(label)
+            .ratio(status.progress())
+            .render(progress, buf);
+
+        if let Some(message) = self.last_error {
+            Line::from(message).red().render(error, buf);
+        }
+
+        if let Some(metadata) = self.metadata {
+            LyricsPanel {
+                lyrics: metadata.lyrics(),
+                position: status.position,
+            }
+            .render(lyrics, buf);
+        }
+
+        let [mixer, roll] =
+            Layout::horizontal([Constraint::Length(MixerView::WIDTH), Constraint::Fill(1)])
+                .spacing(2)
+                .areas(channels);
+        self.mixer.render(mixer, buf);
+        if let Some(notes) = self.notes {
+            PianoRoll {
+                notes,
+                position: status.position,
+                window: self.roll_window,
+                transpose: status.transpose,
+            }
+            .render(roll, buf);
+        }
+    }
+}
+
+#[test]
+fn test_player_tab() {
+    use super::ChannelActivities;
+    use key_dash_audio::Mixer;
+    use std::time::Instant;
+
+    rust_i18n::set_locale("en");
+    let status = PlayerStatus {
+        state: PlaybackState::Playing,
+        position: Duration::from_secs(61),
+        duration: Some(Duration::from_secs(122)),
+        midi_name: Some("song.mid".into()),
+        speed: 1.,
+        ..PlayerStatus::default()
+    };
+    let activities = ChannelActivities::default();
+    let mixer = Mixer::default();
+    let overrides = Default::default();
+    let area = Rect::new(0, 0, 80, 40);
+    let mut buf = Buffer::empty(area);
+    PlayerTab {
+        status: &status,
+        metadata: None,
+        last_error: Some("Broken"),
+        loop_start: None,
+        notes: None,
+        roll_window: Duration::from_secs(4),
+        mixer: MixerView {
+            activities: &activities,
+            mixer: &mixer,
+            soundfont: None,
+            overrides: &overrides,
+            selected: 0,
+            now: Instant::now(),
+        },
+    }
+    .render(area, &mut buf);
+
+    let row = |y| {
+        (0..area.width)
+            .map(|x| buf[(x, y)].symbol())
+            .collect::<String>()
+    };
+    assert!(row(0).starts_with("File: song.mid"));
+    assert!(row(6).starts_with("Playing  +0.0 dB  1.00x  +0"));
+    assert!(row(10).contains("1:01 / 2:02"));
+    assert!(row(12).starts_with("Broken"));
+}
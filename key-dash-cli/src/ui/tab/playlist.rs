@@ -0,0 +1,152 @@
+use key_dash_audio::{Playlist, RepeatMode};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{List, ListItem, ListState, StatefulWidget, Widget},
+};
+use std::borrow::Cow;
+
+use crate::ui::format_duration;
+
+/// Content of [`super::Tab::Playlist`]: The queued songs, the current one highlighted, with the
+/// shuffle and repeat modes.
+pub struct PlaylistTab<'a> {
+    pub playlist: &'a Playlist,
+    /// Entry the jump and remove keys apply to
+    pub cursor: usize,
+    /// Label and input of the path being typed
+    pub prompt: Option<(Cow<'static, str>, &'a str)>,
+    /// Outcome of the last playlist file operation
+    pub message: Option<&'a str>,
+}
+
+impl Widget for PlaylistTab<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [header, _, list, _, status, hint] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+
+        let shuffle = if self.playlist.shuffle() {
+            t!("app.settings.on")
+        } else {
+            t!("app.settings.off")
+        };
+        Line::from(vec![
+            t!("app.playlist.repeat").into_owned().bold(),
+            repeat_label(self.playlist.repeat()).into_owned().into(),
+            "  ".into(),
+            t!("app.playlist.shuffle").into_owned().bold(),
+            shuffle.into_owned().into(),
+        ])
+        .render(header, buf);
+        if let Some((label, input)) = self.prompt {
+            Line::from(vec![
+                label.into_owned().bold(),
+                input.into(),
+                "▏".slow_blink(),
+            ])
+            .render(status, buf);
+            t!("app.playlist.prompt_hint")
+                .into_owned()
+                .dark_gray()
+                .render(hint, buf);
+        } else {
+            if let Some(message) = self.message {
+                Line::from(message).render(status, buf);
+            }
+            t!("app.playlist.hint")
+                .into_owned()
+                .dark_gray()
+                .render(hint, buf);
+        }
+
+        if self.playlist.is_empty() {
+            t!("app.playlist.empty")
+                .into_owned()
+                .dark_gray()
+                .render(list, buf);
+            return;
+        }
+        let current = self.playlist.current();
+        let items = self
+            .playlist
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                let marker = if Some(idx) == current { "▶" } else { " " };
+                let duration = entry.duration.map_or_else(String::new, format_duration);
+                let line = Line::from(format!(
+                    "{marker}{:>4}. {:<width$} {duration:>6}",
+                    idx + 1,
+                    entry.title,
+                    width = usize::from(list.width.saturating_sub(14)),
+                ));
+                if Some(idx) == current {
+                    ListItem::new(line.yellow().bold())
+                } else {
+                    ListItem::new(line)
+                }
+            });
+        let mut state = ListState::default().with_selected(Some(self.cursor));
+        StatefulWidget::render(
+            List::new(items).highlight_style(Style::new().reversed()),
+            list,
+            buf,
+            &mut state,
+        );
+    }
+}
+
+/// Name of `repeat` in the current locale.
+pub fn repeat_label(repeat: RepeatMode) -> Cow<'static, str> {
+    match repeat {
+        RepeatMode::Off => t!("app.settings.off"),
+        RepeatMode::One => t!("app.playlist.repeat_one"),
+        RepeatMode::All => t!("app.playlist.repeat_all"),
+    }
+}
+
+#[test]
+fn test_playlist_tab() {
+    use key_dash_audio::PlaylistEntry;
+
+    rust_i18n::set_locale("en");
+    let mut playlist = Playlist::default();
+    for name in ["a.mid", "b.mid"] {
+        playlist.push(PlaylistEntry::new(name.into()));
+    }
+    playlist.jump(1).unwrap();
+    let area = Rect::new(0, 0, 30, 7);
+    let mut buf = Buffer::empty(area);
+    PlaylistTab {
+        playlist: &playlist,
+        cursor: 0,
+        prompt: Some(("Save as: ".into(), "list.m3u8")),
+        message: None,
+    }
+    .render(area, &mut buf);
+
+    let row = |y| {
+        (0..area.width)
+            .map(|x| buf[(x, y)].symbol())
+            .collect::<String>()
+    };
+    assert!(row(0).starts_with("Repeat: Off  Shuffle: Off"));
+    assert!(row(2).starts_with("    1. a.mid"));
+    assert!(
+        buf[(0, 2)]
+            .modifier
+            .contains(ratatui::style::Modifier::REVERSED)
+    );
+    assert!(row(3).starts_with("▶   2. b.mid"));
+    assert!(row(5).starts_with("Save as: list.m3u8▏"));
+}
@@ -0,0 +1,89 @@
+use key_dash_audio::{PlayerStatus, RepeatMode};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Stylize,
+    text::Line,
+    widgets::{Paragraph, Widget},
+};
+use strum::{Display, EnumIter, FromRepr, IntoEnumIterator};
+
+use super::{PianoRoll, playlist::repeat_label};
+
+/// A setting of [`super::Tab::Settings`], changed with the left and right keys.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Display, EnumIter, FromRepr)]
+pub enum Setting {
+    #[default]
+    #[strum(to_string = "app.settings.master_gain")]
+    MasterGain,
+    #[strum(to_string = "app.settings.speed")]
+    Speed,
+    #[strum(to_string = "app.settings.transpose")]
+    Transpose,
+    #[strum(to_string = "app.settings.repeat")]
+    Repeat,
+    #[strum(to_string = "app.settings.roll_window")]
+    RollWindow,
+}
+
+impl Setting {
+    /// The setting above, or `self` at the top.
+    pub fn previous(self) -> Self {
+        Self::from_repr((self as usize).saturating_sub(1)).unwrap_or(self)
+    }
+
+    /// The setting below, or `self` at the bottom.
+    pub fn next(self) -> Self {
+        Self::from_repr(self as usize + 1).unwrap_or(self)
+    }
+}
+
+/// Content of [`super::Tab::Settings`]: Playback and display settings with their values.
+pub struct SettingsTab<'a> {
+    pub status: &'a PlayerStatus,
+    /// Repeat mode of the playlist
+    pub repeat: RepeatMode,
+    /// Zoom level of the piano roll
+    pub roll_zoom: i32,
+    pub selected: Setting,
+}
+
+impl SettingsTab<'_> {
+    fn value(&self, setting: Setting) -> String {
+        let status = self.status;
+        match setting {
+            Setting::MasterGain => format!("{:+.1} dB", status.master_gain_db),
+            Setting::Speed => format!("{:.2}x", status.speed),
+            Setting::Transpose => format!("{:+}", status.transpose),
+            Setting::Repeat => repeat_label(self.repeat).into_owned(),
+            Setting::RollWindow => {
+                format!(
+                    "{:.1}s",
+                    PianoRoll::window_for(self.roll_zoom).as_secs_f64()
+                )
+            }
+        }
+    }
+}
+
+impl Widget for SettingsTab<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut lines: Vec<_> = Setting::iter()
+            .map(|setting| {
+                let line = Line::from(format!(
+                    "{:<24}{}",
+                    t!(setting.to_string()),
+                    self.value(setting)
+                ));
+                if setting == self.selected {
+                    line.reversed()
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.push(Line::default());
+        lines.push(t!("app.settings.hint").into_owned().dark_gray().into());
+        Paragraph::new(lines).render(area, buf);
+    }
+}
@@ -0,0 +1,404 @@
+use key_dash_audio::PlayerError;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{List, ListItem, ListState, Paragraph, StatefulWidget, Widget},
+};
+use rustysynth::SoundFont;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A SoundFont file of the [`SoundFontLibrary`].
+#[derive(Clone)]
+pub struct SoundFontEntry {
+    pub path: PathBuf,
+    /// File size in bytes, if it could be read
+    pub size: Option<u64>,
+    /// Parsed once it was loaded
+    pub soundfont: Option<Arc<SoundFont>>,
+}
+
+/// SoundFonts of [`super::Tab::SoundFont`]: The ones found in the usual directories and the ones
+/// loaded, with the one playing and the default one.
+#[derive(Default)]
+pub struct SoundFontLibrary {
+    entries: Vec<SoundFontEntry>,
+    /// Index of the entry playing
+    active: Option<usize>,
+    /// Loaded at startup without `--soundfont`
+    default: Option<PathBuf>,
+    /// Entry the keys apply to
+    cursor: usize,
+    /// First preset shown
+    preset_offset: usize,
+    /// Parsed for the presets of a selected entry that wasn't loaded, `None` if it couldn't be.
+    /// Only the last one is kept.
+    preview: Option<(PathBuf, Option<Arc<SoundFont>>)>,
+    /// Being parsed for `preview`
+    previewing: Option<PathBuf>,
+}
+
+impl SoundFontLibrary {
+    /// Directories searched for SoundFonts besides the current one, the Linux distribution
+    /// defaults.
+    const SYSTEM_DIRS: [&str; 3] = [
+        "/usr/share/soundfonts",
+        "/usr/share/sounds/sf2",
+        "/usr/local/share/soundfonts",
+    ];
+
+    pub fn selected(&self) -> Option<&SoundFontEntry> {
+        self.entries.get(self.cursor)
+    }
+
+    /// Move the selection up (negative `step`) or down.
+    pub fn move_cursor(&mut self, step: isize) {
+        let last = self.entries.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(step).min(last);
+        self.preset_offset = 0;
+    }
+
+    /// Scroll the preset list of the selected SoundFont up (negative `step`) or down.
+    pub fn scroll_presets(&mut self, step: isize) {
+        let presets = self
+            .selected()
+            .and_then(|entry| self.soundfont(entry))
+            .map_or(0, |soundfont| soundfont.get_presets().len());
+        self.preset_offset = self
+            .preset_offset
+            .saturating_add_signed(step)
+            .min(presets.saturating_sub(1));
+    }
+
+    /// The parsed SoundFont of `entry`, loaded or previewed.
+    pub fn soundfont<'a>(&'a self, entry: &'a SoundFontEntry) -> Option<&'a Arc<SoundFont>> {
+        entry.soundfont.as_ref().or_else(|| match &self.preview {
+            Some((path, soundfont)) if *path == entry.path => soundfont.as_ref(),
+            _ => None,
+        })
+    }
+
+    /// The selected SoundFont if it should be parsed for a preview, unless another one is being
+    /// parsed. It counts as being parsed from now on.
+    pub fn preview_wanted(&mut self) -> Option<PathBuf> {
+        if self.previewing.is_some() {
+            return None;
+        }
+        let entry = self.selected()?;
+        let previewed = self
+            .preview
+            .as_ref()
+            .is_some_and(|(path, _)| *path == entry.path);
+        if entry.soundfont.is_some() || previewed {
+            return None;
+        }
+        let path = entry.path.clone();
+        self.previewing = Some(path.clone());
+        Some(path)
+    }
+
+    /// Could the SoundFont at `path` not be parsed for a preview?
+    fn preview_failed(&self, path: &Path) -> bool {
+        matches!(&self.preview, Some((previewed, None)) if previewed == path)
+    }
+
+    /// The SoundFont at `path` was parsed for a preview, `None` if it couldn't be.
+    pub fn set_preview(&mut self, path: PathBuf, soundfont: Option<Arc<SoundFont>>) {
+        self.previewing = None;
+        self.preview = Some((path, soundfont));
+    }
+
+    /// Add the SoundFont at `path` unless it's known already, returns its index.
+    pub fn add(&mut self, path: &Path) -> usize {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        if let Some(idx) = self.entries.iter().position(|entry| entry.path == path) {
+            return idx;
+        }
+        let size = std::fs::metadata(&path).ok().map(|metadata| metadata.len());
+        self.entries.push(SoundFontEntry {
+            path,
+            size,
+            soundfont: None,
+        });
+        self.entries.len() - 1
+    }
+
+    /// Add the SoundFonts of the current directory, the data directory of the user and the
+    /// system directories.
+    pub fn scan(&mut self) {
+        let mut dirs: Vec<PathBuf> = Self::SYSTEM_DIRS.iter().map(PathBuf::from).collect();
+        if let Some(data) = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        {
+            dirs.insert(0, data.join("soundfonts"));
+        }
+        if let Ok(current) = std::env::current_dir() {
+            dirs.insert(0, current);
+        }
+        for dir in dirs {
+            let Ok(files) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            let mut paths: Vec<_> = files
+                .flatten()
+                .map(|file| file.path())
+                .filter(|path| is_soundfont(path))
+                .collect();
+            paths.sort();
+            for path in paths {
+                self.add(&path);
+            }
+        }
+    }
+
+    /// The SoundFont at `path` was loaded and plays now.
+    pub fn set_loaded(&mut self, path: &Path, soundfont: Arc<SoundFont>) {
+        let idx = self.add(path);
+        self.entries[idx].soundfont = Some(soundfont);
+        self.active = Some(idx);
+    }
+
+    /// Make `path` the default SoundFont, remembered in the config directory if there is one.
+    /// The path must be valid UTF-8 to be remembered.
+    pub fn set_default(&mut self, path: PathBuf) -> Result<(), PlayerError> {
+        if let Some(file) = default_file() {
+            let write = || {
+                let text = path.to_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "path is not valid UTF-8")
+                })?;
+                if let Some(dir) = file.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&file, text)
+            };
+            write().map_err(|source| PlayerError::Write {
+                path: file.clone(),
+                source,
+            })?;
+        }
+        self.default = Some(path);
+        Ok(())
+    }
+
+    /// Read the default SoundFont from the config directory, and add it.
+    pub fn read_default(&mut self) -> Option<PathBuf> {
+        let text = std::fs::read_to_string(default_file()?).ok()?;
+        let path = PathBuf::from(text.trim());
+        if path.as_os_str().is_empty() {
+            return None;
+        }
+        let idx = self.add(&path);
+        let path = self.entries[idx].path.clone();
+        self.default = Some(path.clone());
+        Some(path)
+    }
+}
+
+/// Is `path` a SoundFont 2 file, judging by its extension?
+fn is_soundfont(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("sf2"))
+}
+
+/// File the path of the default SoundFont is kept in.
+fn default_file() -> Option<PathBuf> {
+    Some(crate::ui::config_dir()?.join("default-soundfont"))
+}
+
+/// `12.3 MB` of `bytes`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Content of [`super::Tab::SoundFont`]: The known SoundFonts, and the information and presets of
+/// the selected one.
+pub struct SoundFontTab<'a> {
+    pub library: &'a SoundFontLibrary,
+}
+
+impl SoundFontTab<'_> {
+    fn render_list(&self, area: Rect, buf: &mut Buffer) {
+        let library = self.library;
+        if library.entries.is_empty() {
+            Paragraph::new(t!("app.soundfont.none_found").into_owned().dark_gray())
+                .render(area, buf);
+            return;
+        }
+        let items = library.entries.iter().enumerate().map(|(idx, entry)| {
+            let active = if library.active == Some(idx) {
+                "▶"
+            } else {
+                " "
+            };
+            let default = if library.default.as_deref() == Some(entry.path.as_path()) {
+                "★"
+            } else {
+                " "
+            };
+            let name = entry
+                .path
+                .file_name()
+                .unwrap_or(entry.path.as_os_str())
+                .to_string_lossy();
+            let line = Line::from(format!("{active}{default} {name}"));
+            if library.active == Some(idx) {
+                ListItem::new(line.yellow().bold())
+            } else {
+                ListItem::new(line)
+            }
+        });
+        let mut state = ListState::default().with_selected(Some(library.cursor));
+        StatefulWidget::render(
+            List::new(items).highlight_style(Style::new().reversed()),
+            area,
+            buf,
+            &mut state,
+        );
+    }
+
+    fn render_details(&self, entry: &SoundFontEntry, area: Rect, buf: &mut Buffer) {
+        let field = |label: &str, value: String| {
+            Line::from(vec![t!(label).into_owned().bold(), value.into()])
+        };
+        let mut lines = vec![
+            field("app.soundfont.file", entry.path.display().to_string()),
+            field(
+                "app.soundfont.size",
+                entry.size.map_or_else(String::new, format_size),
+            ),
+        ];
+        let Some(soundfont) = self.library.soundfont(entry) else {
+            lines.push(Line::default());
+            let message = if self.library.preview_failed(&entry.path) {
+                t!("app.soundfont.unreadable")
+            } else {
+                t!("app.soundfont.not_loaded")
+            };
+            lines.push(message.into_owned().dark_gray().into());
+            Paragraph::new(lines).render(area, buf);
+            return;
+        };
+        let info = soundfont.get_info();
+        let version = info.get_version();
+        lines.extend([
+            field(
+                "app.soundfont.name",
+                info.get_bank_name().trim().to_string(),
+            ),
+            field("app.soundfont.author", info.get_author().trim().to_string()),
+            field(
+                "app.soundfont.copyright",
+                info.get_copyright().trim().to_string(),
+            ),
+            field(
+                "app.soundfont.version",
+                format!("{}.{}", version.get_major(), version.get_minor()),
+            ),
+            field(
+                "app.soundfont.samples",
+                soundfont.get_sample_headers().len().to_string(),
+            ),
+            field(
+                "app.soundfont.comments",
+                info.get_comments()
+                    .lines()
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .to_string(),
+            ),
+            field(
+                "app.soundfont.presets",
+                soundfont.get_presets().len().to_string(),
+            ),
+            Line::default(),
+        ]);
+        let mut presets: Vec<_> = soundfont
+            .get_presets()
+            .iter()
+            .map(|preset| {
+                (
+                    preset.get_bank_number(),
+                    preset.get_patch_number(),
+                    preset.get_name().trim(),
+                )
+            })
+            .collect();
+        presets.sort_unstable();
+        let rows = usize::from(area.height).saturating_sub(lines.len());
+        lines.extend(
+            presets
+                .iter()
+                .skip(self.library.preset_offset)
+                .take(rows)
+                .map(|(bank, program, name)| Line::from(format!("{bank:>3}:{program:<3} {name}"))),
+        );
+        Paragraph::new(lines).render(area, buf);
+    }
+}
+
+impl Widget for SoundFontTab<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [main, _, hint] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let [list, details] = Layout::horizontal([Constraint::Percentage(35), Constraint::Fill(1)])
+            .spacing(2)
+            .areas(main);
+        self.render_list(list, buf);
+        if let Some(entry) = self.library.selected() {
+            self.render_details(entry, details, buf);
+        }
+        t!("app.soundfont.hint")
+            .into_owned()
+            .dark_gray()
+            .render(hint, buf);
+    }
+}
+
+#[test]
+fn test_soundfont_library() {
+    let mut library = SoundFontLibrary::default();
+    let a = library.add(Path::new("a.sf2"));
+    let b = library.add(Path::new("b.sf2"));
+    assert_eq!((a, b), (0, 1));
+    // Known paths aren't added twice, also relative ones.
+    let cwd = std::env::current_dir().unwrap();
+    assert_eq!(library.add(&cwd.join("b.sf2")), 1);
+    library.move_cursor(5);
+    assert_eq!(library.selected().unwrap().path, cwd.join("b.sf2"));
+    assert!(is_soundfont(Path::new("font.SF2")) && !is_soundfont(Path::new("font.sf3")));
+    assert_eq!(format_size(1536 * 1024), "1.5 MB");
+    assert_eq!(format_size(12), "12 B");
+
+    // The selected SoundFont is parsed for a preview once, one at a time.
+    let a_path = library.entries[a].path.clone();
+    assert_eq!(library.preview_wanted(), Some(cwd.join("b.sf2")));
+    library.move_cursor(-1);
+    assert_eq!(library.preview_wanted(), None);
+    library.set_preview(cwd.join("b.sf2"), None);
+    assert_eq!(library.preview_wanted(), Some(a_path.clone()));
+    library.set_preview(a_path, None);
+    assert_eq!(library.preview_wanted(), None);
+    assert!(library.soundfont(&library.entries[a]).is_none());
+}